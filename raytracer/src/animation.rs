@@ -0,0 +1,285 @@
+use crate::camera;
+use crate::hittable;
+use crate::image;
+use crate::material;
+use crate::utility;
+use crate::vector;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use vector::Vec3 as Point3;
+
+// Camera settings at a point in time, in seconds.
+#[derive(Clone, Copy)]
+pub struct Keyframe {
+    pub time: f64,
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub v_up: vector::Vec3,
+    pub vfov: f64,
+    pub focus_dist: f64,
+}
+
+impl Keyframe {
+    // Captures the current settings of cam at the given time.
+    pub fn from_camera(time: f64, cam: &camera::Camera) -> Self {
+        Keyframe {
+            time,
+            look_from: cam.look_from,
+            look_at: cam.look_at,
+            v_up: cam.v_up,
+            vfov: cam.vfov,
+            focus_dist: cam.focus_dist,
+        }
+    }
+
+    pub fn apply(&self, cam: &mut camera::Camera) {
+        cam.look_from = self.look_from;
+        cam.look_at = self.look_at;
+        cam.v_up = self.v_up;
+        cam.vfov = self.vfov;
+        cam.focus_dist = self.focus_dist;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    // passes through every keyframe with a continuous tangent
+    CatmullRom,
+}
+
+impl Interpolation {
+    pub fn from_name(name: &str) -> Option<Interpolation> {
+        match name {
+            "linear" => Some(Interpolation::Linear),
+            "catmull-rom" => Some(Interpolation::CatmullRom),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CameraAnimation {
+    keyframes: Vec<Keyframe>,
+    pub interpolation: Interpolation,
+    pub frame_rate: f64,
+    // fraction of each frame interval the shutter is open; 0.5 is a
+    // 180 degree shutter and 0 disables motion blur
+    pub shutter: f64,
+}
+
+// The camera's movement over the time the shutter is open. Every camera
+// sample is taken at its own uniformly random time in that interval.
+#[derive(Clone)]
+pub struct Shutter {
+    animation: CameraAnimation,
+    open: f64,
+    close: f64,
+}
+
+impl Shutter {
+    // The shutter opens at time open and closes at time close, in seconds.
+    pub fn new(animation: CameraAnimation, open: f64, close: f64) -> Self {
+        Shutter {
+            animation,
+            open,
+            close,
+        }
+    }
+
+    // The camera placement at a random time while the shutter is open.
+    pub fn sample(&self) -> Keyframe {
+        return self
+            .animation
+            .evaluate(utility::random_double(self.open, self.close));
+    }
+}
+
+impl CameraAnimation {
+    pub fn new(frame_rate: f64, interpolation: Interpolation) -> Self {
+        CameraAnimation {
+            keyframes: Vec::new(),
+            interpolation,
+            frame_rate,
+            shutter: 0.5,
+        }
+    }
+
+    pub fn add_keyframe(&mut self, keyframe: Keyframe) {
+        let index = self
+            .keyframes
+            .partition_point(|other| other.time <= keyframe.time);
+        self.keyframes.insert(index, keyframe);
+    }
+
+    pub fn duration(&self) -> f64 {
+        return self.keyframes.last().map_or(0.0, |key| key.time);
+    }
+
+    pub fn frame_count(&self) -> i32 {
+        if self.keyframes.is_empty() {
+            return 0;
+        }
+        return (self.duration() * self.frame_rate).floor() as i32 + 1;
+    }
+
+    pub fn evaluate(&self, time: f64) -> Keyframe {
+        let keys = &self.keyframes;
+        assert!(!keys.is_empty(), "camera animation has no keyframes");
+
+        let last = keys.len() - 1;
+        if time <= keys[0].time || last == 0 {
+            return Keyframe { time, ..keys[0] };
+        }
+        if time >= keys[last].time {
+            return Keyframe { time, ..keys[last] };
+        }
+
+        let i = keys.partition_point(|key| key.time <= time) - 1;
+        let (k1, k2) = (&keys[i], &keys[i + 1]);
+        let s = (time - k1.time) / (k2.time - k1.time);
+
+        match self.interpolation {
+            Interpolation::Linear => {
+                let lerp = |a: Point3, b: Point3| a * (1.0 - s) + b * s;
+                let lerpf = |a: f64, b: f64| a * (1.0 - s) + b * s;
+                return Keyframe {
+                    time,
+                    look_from: lerp(k1.look_from, k2.look_from),
+                    look_at: lerp(k1.look_at, k2.look_at),
+                    v_up: lerp(k1.v_up, k2.v_up),
+                    vfov: lerpf(k1.vfov, k2.vfov),
+                    focus_dist: lerpf(k1.focus_dist, k2.focus_dist),
+                };
+            }
+            Interpolation::CatmullRom => {
+                // end keyframes are repeated to provide the outer control points
+                let k0 = &keys[i.saturating_sub(1)];
+                let k3 = &keys[(i + 2).min(last)];
+                let spline = |p0: Point3, p1: Point3, p2: Point3, p3: Point3| {
+                    (p1 * 2.0
+                        + (p2 - p0) * s
+                        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * (s * s)
+                        + (-p0 + p1 * 3.0 - p2 * 3.0 + p3) * (s * s * s))
+                        * 0.5
+                };
+                let splinef = |p0: f64, p1: f64, p2: f64, p3: f64| {
+                    let v = spline(
+                        Point3::new(p0, 0.0, 0.0),
+                        Point3::new(p1, 0.0, 0.0),
+                        Point3::new(p2, 0.0, 0.0),
+                        Point3::new(p3, 0.0, 0.0),
+                    );
+                    v.x()
+                };
+                return Keyframe {
+                    time,
+                    look_from: spline(k0.look_from, k1.look_from, k2.look_from, k3.look_from),
+                    look_at: spline(k0.look_at, k1.look_at, k2.look_at, k3.look_at),
+                    v_up: spline(k0.v_up, k1.v_up, k2.v_up, k3.v_up),
+                    vfov: splinef(k0.vfov, k1.vfov, k2.vfov, k3.vfov),
+                    focus_dist: splinef(k0.focus_dist, k1.focus_dist, k2.focus_dist, k3.focus_dist),
+                };
+            }
+        }
+    }
+
+    // Renders one frame. With motion blur every sample sees the camera at
+    // its own time while the shutter is open.
+    pub fn render_frame(
+        &self,
+        frame: i32,
+        cam: &mut camera::Camera,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+    ) -> image::Image {
+        let frame_time = frame as f64 / self.frame_rate;
+        let open_time = self.shutter / self.frame_rate;
+
+        self.evaluate(frame_time).apply(cam);
+        cam.motion = (open_time > 0.0)
+            .then(|| Shutter::new(self.clone(), frame_time, frame_time + open_time));
+        let image = cam.render_image(world, materials);
+        cam.motion = None;
+        return image;
+    }
+
+    // Renders every frame into output_dir as frame_0001.png, frame_0002.png, ...
+    pub fn render_sequence<P: AsRef<Path>>(
+        &self,
+        cam: &mut camera::Camera,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+        output_dir: P,
+    ) -> io::Result<()> {
+        let output_dir = output_dir.as_ref();
+        fs::create_dir_all(output_dir)?;
+
+        let frame_count = self.frame_count();
+        for frame in 0..frame_count {
+            cam.progress
+                .note(&format!("Frame {} of {}", frame + 1, frame_count));
+            let image = self.render_frame(frame, cam, world, materials);
+            image.save(
+                output_dir.join(format!("frame_{:04}.png", frame + 1)),
+                &cam.display,
+            )?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Keyframes one second apart moving look_from along x through 0, 1, 3
+    // and 6 while the field of view widens.
+    fn animation(interpolation: Interpolation) -> CameraAnimation {
+        let mut cam = camera::Camera::new();
+        let mut animation = CameraAnimation::new(4.0, interpolation);
+        for (time, x) in [(1.0, 1.0), (0.0, 0.0), (3.0, 6.0), (2.0, 3.0)] {
+            cam.look_from = Point3::new(x, 0.0, 0.0);
+            cam.vfov = 20.0 + 10.0 * time;
+            animation.add_keyframe(Keyframe::from_camera(time, &cam));
+        }
+        return animation;
+    }
+
+    #[test]
+    fn interpolation_passes_through_the_keyframes() {
+        for interpolation in [Interpolation::Linear, Interpolation::CatmullRom] {
+            let animation = animation(interpolation);
+            assert!(animation.duration() == 3.0 && animation.frame_count() == 13);
+            for (time, x) in [(-1.0, 0.0), (0.0, 0.0), (1.0, 1.0), (2.0, 3.0), (4.0, 6.0)] {
+                let key = animation.evaluate(time);
+                assert!(key.time == time);
+                assert!((key.look_from.x() - x).abs() < 1e-12);
+            }
+        }
+
+        let key = animation(Interpolation::Linear).evaluate(1.25);
+        assert!((key.look_from.x() - 1.5).abs() < 1e-12);
+        assert!((key.vfov - 32.5).abs() < 1e-12);
+        // the spline follows the quadratic x = t (t + 1) / 2 of the middle
+        // keyframes
+        let key = animation(Interpolation::CatmullRom).evaluate(1.5);
+        assert!((key.look_from.x() - 1.875).abs() < 1e-12);
+    }
+
+    #[test]
+    fn shutter_samples_while_open() {
+        let shutter = Shutter::new(animation(Interpolation::Linear), 1.0, 1.5);
+        let mut halves = [0; 2];
+        for _ in 0..1000 {
+            let key = shutter.sample();
+            assert!((1.0..1.5).contains(&key.time));
+            assert!((key.look_from.x() - (1.0 + 2.0 * (key.time - 1.0))).abs() < 1e-12);
+            halves[(key.time >= 1.25) as usize] += 1;
+        }
+        assert!(halves[0] > 400 && halves[1] > 400);
+    }
+}
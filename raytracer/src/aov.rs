@@ -0,0 +1,119 @@
+use crate::image;
+use crate::material;
+use crate::vector;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use vector::Vec3 as Color;
+use vector::Vec3 as Point3;
+
+// Auxiliary values recorded where a camera ray first hits the scene. A ray
+// that escapes keeps the zero defaults apart from its background albedo.
+#[derive(Clone, Copy, Default)]
+pub struct AovSample {
+    pub hit: bool,
+    pub normal: vector::Vec3,
+    pub albedo: Color,
+    pub position: Point3,
+    // distance along the viewing axis
+    pub depth: f64,
+    pub material_id: material::MaterialId,
+    pub object_id: usize,
+}
+
+// Sums the samples of one pixel. Continuous values are averaged over every
+// sample, ids are taken from the first sample that hit something.
+#[derive(Clone, Copy, Default)]
+pub struct AovPixel {
+    count: usize,
+    normal: vector::Vec3,
+    albedo: Color,
+    position: Point3,
+    depth: f64,
+    ids: Option<(material::MaterialId, usize)>,
+}
+
+impl AovPixel {
+    pub fn add(&mut self, sample: &AovSample) {
+        self.count += 1;
+        self.normal = self.normal + sample.normal;
+        self.albedo = self.albedo + sample.albedo;
+        self.position = self.position + sample.position;
+        self.depth += sample.depth;
+        if sample.hit && self.ids.is_none() {
+            self.ids = Some((sample.material_id, sample.object_id));
+        }
+    }
+}
+
+// One image per auxiliary output. Ids are stored as id + 1 in every channel
+// so that 0 marks the background.
+pub struct AovBuffers {
+    pub normal: image::Image,
+    pub albedo: image::Image,
+    pub depth: image::Image,
+    pub position: image::Image,
+    pub material_id: image::Image,
+    pub object_id: image::Image,
+}
+
+impl AovBuffers {
+    pub fn new(width: usize, height: usize) -> Self {
+        AovBuffers {
+            normal: image::Image::new(width, height),
+            albedo: image::Image::new(width, height),
+            depth: image::Image::new(width, height),
+            position: image::Image::new(width, height),
+            material_id: image::Image::new(width, height),
+            object_id: image::Image::new(width, height),
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, pixel: &AovPixel) {
+        if pixel.count == 0 {
+            return;
+        }
+        let scale = 1.0 / pixel.count as f64;
+        let normal = pixel.normal * scale;
+        let normal = if normal.length_squared() > 0.0 {
+            normal.unit_vector()
+        } else {
+            normal
+        };
+        let depth = pixel.depth * scale;
+        self.normal.set(x, y, normal);
+        self.albedo.set(x, y, pixel.albedo * scale);
+        self.depth.set(x, y, Color::new(depth, depth, depth));
+        self.position.set(x, y, pixel.position * scale);
+
+        if let Some((material_id, object_id)) = pixel.ids {
+            let m = (material_id.index() + 1) as f64;
+            let o = (object_id + 1) as f64;
+            self.material_id.set(x, y, Color::new(m, m, m));
+            self.object_id.set(x, y, Color::new(o, o, o));
+        }
+    }
+
+    pub fn layers(&self) -> [(&'static str, &image::Image); 6] {
+        return [
+            ("normal", &self.normal),
+            ("albedo", &self.albedo),
+            ("depth", &self.depth),
+            ("position", &self.position),
+            ("material_id", &self.material_id),
+            ("object_id", &self.object_id),
+        ];
+    }
+
+    // Writes each buffer to output_dir as <name>.pfm.
+    pub fn save<P: AsRef<Path>>(&self, output_dir: P) -> io::Result<()> {
+        let output_dir = output_dir.as_ref();
+        fs::create_dir_all(output_dir)?;
+        for (name, layer) in self.layers() {
+            layer.write_pfm(output_dir.join(format!("{}.pfm", name)))?;
+        }
+        return Ok(());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::image;
+use crate::utility;
+use crate::vector;
+
+use std::io;
+use std::path::Path;
+
+// The shape of the thin-lens aperture. Samples lie in the unit square
+// [-1, 1]^2 and are scaled by the defocus disk.
+pub enum Aperture {
+    Disk,
+    // regular polygon inscribed in the unit circle; rotation is in degrees
+    Polygon { blades: u32, rotation: f64 },
+    Mask(ApertureMask),
+}
+
+impl Aperture {
+    pub fn sample(&self) -> vector::Vec3 {
+        match self {
+            Aperture::Disk => vector::Vec3::random_in_unit_disk(),
+            Aperture::Polygon { blades, rotation } => Self::sample_polygon(*blades, *rotation),
+            Aperture::Mask(mask) => mask.sample(),
+        }
+    }
+
+    // Picks one of the equal-area triangles fanning out from the centre and
+    // samples it uniformly.
+    fn sample_polygon(blades: u32, rotation: f64) -> vector::Vec3 {
+        let blades = blades.max(3);
+        let step = 2.0 * std::f64::consts::PI / blades as f64;
+        let k = (utility::random_double(0.0, blades as f64) as u32).min(blades - 1);
+        let angle0 = utility::degrees_to_radians(rotation) + k as f64 * step;
+        let angle1 = angle0 + step;
+
+        let mut a = utility::random_double(0.0, 1.0);
+        let mut b = utility::random_double(0.0, 1.0);
+        if a + b > 1.0 {
+            a = 1.0 - a;
+            b = 1.0 - b;
+        }
+        return vector::Vec3::new(
+            a * angle0.cos() + b * angle1.cos(),
+            a * angle0.sin() + b * angle1.sin(),
+            0.0,
+        );
+    }
+}
+
+// A grayscale transmission mask stretched over [-1, 1]^2, with white fully
+// open. Samples are drawn in proportion to transmission via a pixel CDF.
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    cdf: Vec<f64>,
+}
+
+impl ApertureMask {
+    pub fn new(mask: &image::Image) -> io::Result<Self> {
+        let mut cdf = Vec::with_capacity(mask.width * mask.height);
+        let mut total = 0.0;
+        for y in 0..mask.height {
+            for x in 0..mask.width {
+                let p = mask.get(x, y);
+                total += ((p.x() + p.y() + p.z()) / 3.0).max(0.0);
+                cdf.push(total);
+            }
+        }
+        if total <= 0.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "aperture mask is completely closed",
+            ));
+        }
+        for c in cdf.iter_mut() {
+            *c /= total;
+        }
+        Ok(ApertureMask {
+            width: mask.width,
+            height: mask.height,
+            cdf,
+        })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        return Self::new(&image::Image::read_pnm(path)?);
+    }
+
+    pub fn sample(&self) -> vector::Vec3 {
+        let u = utility::random_double(0.0, 1.0);
+        let index = self
+            .cdf
+            .partition_point(|c| *c <= u)
+            .min(self.cdf.len() - 1);
+        let x = (index % self.width) as f64 + utility::random_double(0.0, 1.0);
+        let y = (index / self.width) as f64 + utility::random_double(0.0, 1.0);
+        // image rows run downwards, the aperture's v axis upwards
+        return vector::Vec3::new(
+            2.0 * x / self.width as f64 - 1.0,
+            1.0 - 2.0 * y / self.height as f64,
+            0.0,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polygon_samples_stay_inside_the_blades() {
+        // a square rotated by 45 degrees has its corners on the axes, so it
+        // is the set |x| + |y| <= 1
+        let aperture = Aperture::Polygon {
+            blades: 4,
+            rotation: 0.0,
+        };
+        let mut quadrants = [0; 4];
+        for _ in 0..10000 {
+            let p = aperture.sample();
+            assert!(p.x().abs() + p.y().abs() <= 1.0 + 1e-12);
+            quadrants[(p.x() > 0.0) as usize * 2 + (p.y() > 0.0) as usize] += 1;
+        }
+        assert!(quadrants.iter().all(|&count| count > 2200));
+
+        // rotated by 45 degrees it is the square |x|, |y| <= sqrt(1/2)
+        let aperture = Aperture::Polygon {
+            blades: 4,
+            rotation: 45.0,
+        };
+        for _ in 0..10000 {
+            let p = aperture.sample();
+            assert!(p.x().abs().max(p.y().abs()) <= 0.5f64.sqrt() + 1e-12);
+        }
+    }
+
+    #[test]
+    fn mask_samples_follow_the_transmission() {
+        // a 2x2 mask with the top left closed, the top right half open and
+        // the bottom row fully open
+        let mut image = image::Image::new(2, 2);
+        image.set(1, 0, vector::Vec3::new(0.5, 0.5, 0.5));
+        image.set(0, 1, vector::Vec3::new(1.0, 1.0, 1.0));
+        image.set(1, 1, vector::Vec3::new(1.0, 1.0, 1.0));
+        let mask = ApertureMask::new(&image).unwrap();
+
+        let mut counts = [0; 4];
+        for _ in 0..25000 {
+            let p = mask.sample();
+            assert!(p.x().abs() <= 1.0 && p.y().abs() <= 1.0);
+            counts[(p.y() < 0.0) as usize * 2 + (p.x() > 0.0) as usize] += 1;
+        }
+        assert!(counts[0] == 0);
+        // expected 5000, 10000 and 10000
+        assert!((4500..5500).contains(&counts[1]));
+        assert!((9300..10700).contains(&counts[2]));
+        assert!((9300..10700).contains(&counts[3]));
+
+        let closed = ApertureMask::new(&image::Image::new(2, 2));
+        assert!(closed.err().unwrap().kind() == io::ErrorKind::InvalidData);
+    }
+}
@@ -1,176 +1,1279 @@
+use crate::animation;
+use crate::aov;
+use crate::aperture;
+use crate::checkpoint;
 use crate::color;
+use crate::debug_view;
+use crate::film;
 use crate::hittable;
+use crate::image;
 use crate::interval;
+use crate::lens;
 use crate::material;
+use crate::path_debug;
+use crate::physical_camera;
+use crate::progress;
+use crate::progressive;
 use crate::ray;
+use crate::spectrum;
+use crate::stats;
 use crate::utility;
 use crate::vector;
 
-use core::f64::INFINITY;
-use core::f64::NEG_INFINITY;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
 
 use vector::Vec3 as Point3;
 use vector::Vec3 as Color;
 
-pub struct Camera {
-    image_height: i32,
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FisheyeMapping {
+    // image radius proportional to the angle from the optical axis
+    Equidistant,
+    // image radius proportional to sin(angle / 2), preserving solid angle
+    Equisolid,
+}
+
+#[derive(Clone, Copy)]
+pub enum Projection {
+    // pinhole or thin-lens perspective using vfov
+    Perspective,
+    // parallel rays along the view direction; view_width is in world units
+    Orthographic { view_width: f64 },
+    // circular fisheye whose image circle spans the shorter image side and
+    // covers fov degrees; pixels outside the circle stay black
+    Fisheye { mapping: FisheyeMapping, fov: f64 },
+    // full 360x180 degree latitude/longitude panorama, best with a 2:1 aspect
+    Equirectangular,
+}
+
+impl Projection {
+    // A projection by name. size is the view width of an orthographic
+    // projection and the field of view of a fisheye in degrees.
+    pub fn from_name(name: &str, size: Option<f64>) -> Option<Projection> {
+        let projection = match name {
+            "perspective" => Projection::Perspective,
+            "orthographic" => Projection::Orthographic {
+                view_width: size.unwrap_or(4.0),
+            },
+            "fisheye" => Projection::Fisheye {
+                mapping: FisheyeMapping::Equidistant,
+                fov: size.unwrap_or(180.0),
+            },
+            "fisheye-equisolid" => Projection::Fisheye {
+                mapping: FisheyeMapping::Equisolid,
+                fov: size.unwrap_or(180.0),
+            },
+            "equirectangular" => Projection::Equirectangular,
+            _ => return None,
+        };
+        return Some(projection);
+    }
+}
+
+// Where the camera is and how the image maps onto the scene, derived from
+// the placement settings.
+#[derive(Clone, Copy, Default)]
+struct View {
     center: Point3,
     pixel00_loc: Point3,
     pixel_delta_u: vector::Vec3,
     pixel_delta_v: vector::Vec3,
-    pub look_from: Point3,
-    pub look_at: Point3,
-    v_up: vector::Vec3,
     u: vector::Vec3,
     v: vector::Vec3,
     w: vector::Vec3,
-    pub defocus_angle: f64,
-    pub focus_dist: f64,
+    focus_dist: f64,
+    // defocus_angle, or the one the exposure's f-stop gives
+    defocus_angle: f64,
     defocus_disk_u: vector::Vec3,
     defocus_disk_v: vector::Vec3,
 }
 
+pub struct Camera {
+    pub aspect_ratio: f64,
+    pub image_width: i32,
+    pub vfov: f64,
+    pub samples_per_pixel: i32,
+    // index of the first sample render takes in each pixel; a render split
+    // into parts gives every part its own so seeded samples do not repeat
+    pub sample_offset: i32,
+    pub max_depth: i32,
+    pub projection: Projection,
+    image_height: i32,
+    view: View,
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub v_up: vector::Vec3,
+    pub defocus_angle: f64,
+    pub focus_dist: f64,
+    pub aperture: aperture::Aperture,
+    // when set, the camera moves while the shutter is open and every sample
+    // sees it at its own time
+    pub motion: Option<animation::Shutter>,
+    // trace sampled wavelengths instead of RGB so dielectrics can disperse
+    pub spectral: bool,
+    // sideways ray origin offset for omni-directional stereo panoramas,
+    // negative for the left eye; only used by the equirectangular projection
+    pub ods_eye_offset: f64,
+    // when set, rays are traced from the film through this lens system and
+    // the projection, vfov and defocus settings are ignored
+    pub lens: Option<lens::LensSystem>,
+    // photographic exposure; its f-stop replaces defocus_angle when set
+    pub exposure: Option<physical_camera::Exposure>,
+    // sensor height in mm, relating vfov to a focal length for the f-stop
+    pub sensor_height: f64,
+    // cos^4 natural vignetting of the thin lens
+    pub vignetting: bool,
+    pub distortion: physical_camera::LensDistortion,
+    // lateral chromatic aberration: red is magnified by 1 + ca / 2 and blue
+    // by 1 - ca / 2 relative to green
+    pub chromatic_aberration: f64,
+    // exposure, tone mapping and quantization used when writing the image
+    pub display: color::DisplayTransform,
+    // reconstruction filter the samples are splatted with
+    pub filter: film::Filter,
+    // renders a diagnostic view of the scene instead of its shading
+    pub debug_view: Option<debug_view::DebugView>,
+    // how render reports its progress on stderr
+    pub progress: progress::Style,
+}
+
 impl Camera {
-    pub const ASPECT_RATIO: f64 = 16.0 / 9.0;
-    pub const IMAGE_WIDTH: i32 = 1200;
-    pub const VFOV: i32 = 20;
-    pub const SAMPLES_PER_PIXEL: i32 = 200;
-    pub const MAX_DEPTH: i32 = 50;
     pub fn new() -> Camera {
         Camera {
+            aspect_ratio: 16.0 / 9.0,
+            image_width: 1200,
+            vfov: 20.0,
+            samples_per_pixel: 200,
+            sample_offset: 0,
+            max_depth: 50,
+            projection: Projection::Perspective,
             image_height: 0,
-            center: Point3::new(0.0, 0.0, 0.0),
-            pixel00_loc: Point3::new(0.0, 0.0, 0.0),
-            pixel_delta_u: vector::Vec3::new(0.0, 0.0, 0.0),
-            pixel_delta_v: vector::Vec3::new(0.0, 0.0, 0.0),
+            view: View::default(),
             look_from: vector::Vec3::new(0.0, 0.0, -1.0),
             look_at: vector::Vec3::new(0.0, 0.0, 0.0),
             v_up: vector::Vec3::new(0.0, 1.0, 0.0),
-            u: vector::Vec3::new(0.0, 0.0, 0.0),
-            v: vector::Vec3::new(0.0, 0.0, 0.0),
-            w: vector::Vec3::new(0.0, 0.0, 0.0),
             defocus_angle: 0.0,
             focus_dist: 10.0,
-            defocus_disk_u: vector::Vec3::new(0.0, 0.0, 0.0),
-            defocus_disk_v: vector::Vec3::new(0.0, 0.0, 0.0),
+            aperture: aperture::Aperture::Disk,
+            motion: None,
+            spectral: false,
+            ods_eye_offset: 0.0,
+            lens: None,
+            exposure: None,
+            sensor_height: 24.0,
+            vignetting: false,
+            distortion: physical_camera::LensDistortion::default(),
+            chromatic_aberration: 0.0,
+            display: color::DisplayTransform::new(),
+            filter: film::Filter::Box { radius: 0.5 },
+            debug_view: None,
+            progress: progress::Style::new(),
         }
     }
 
-    pub fn render(&mut self, world: &mut dyn hittable::Hittable) {
+    pub fn render_image(
+        &mut self,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+    ) -> image::Image {
+        return self.render_pass(world, materials, false).0;
+    }
+
+    // Renders the image along with the auxiliary buffers recorded at the
+    // first hit of every camera ray.
+    pub fn render_image_with_aovs(
+        &mut self,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+    ) -> (image::Image, aov::AovBuffers) {
+        let (image, aovs) = self.render_pass(world, materials, true);
+        return (image, aovs.expect("the pass records the requested buffers"));
+    }
+
+    // Renders the image, and the auxiliary buffers when with_aovs is set.
+    fn render_pass(
+        &mut self,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+        with_aovs: bool,
+    ) -> (image::Image, Option<aov::AovBuffers>) {
         self.initialize();
+        let (width, height) = (self.image_width as usize, self.image_height as usize);
+        let mut film = film::Film::new(width, height, self.filter);
+        let mut aovs = with_aovs.then(|| aov::AovBuffers::new(width, height));
+        self.render_rows(
+            world,
+            materials,
+            &mut film,
+            0,
+            aovs.as_mut(),
+            &mut |_, _| true,
+        );
+        return (self.develop(&film), aovs);
+    }
 
-        // render
-        println!("P3\n{} {}\n255\n", Self::IMAGE_WIDTH, self.image_height);
+    // Renders like render_image, continuing from checkpoint and its film when
+    // they come from an earlier run. The checkpoint is saved to path every
+    // interval, when Ctrl-C is caught and once the image is done, so a
+    // finished render can later be resumed with more samples per pixel.
+    // Returns None when the render was interrupted.
+    pub fn render_checkpointed(
+        &mut self,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+        checkpoint: &mut checkpoint::Checkpoint,
+        film: Option<film::Film>,
+        path: &Path,
+        interval: Duration,
+    ) -> io::Result<Option<image::Image>> {
+        self.initialize();
+        let (width, height) = (self.image_width as usize, self.image_height as usize);
+        let mut film = film.unwrap_or_else(|| film::Film::new(width, height, self.filter));
+        if film.width != width || film.height != height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "checkpoint is {}x{} but the camera renders {}x{}",
+                    film.width, film.height, width, height
+                ),
+            ));
+        }
 
-        for s in 0..self.image_height {
-            eprintln!("\rScanlines remaining: {} ", self.image_height - s);
-            for t in 0..Self::IMAGE_WIDTH {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-                for _sample in 0..Self::SAMPLES_PER_PIXEL {
-                    let r = self.get_ray(t, s);
-                    pixel_color = pixel_color + Self::ray_color(r, Self::MAX_DEPTH, world);
+        let (samples_per_pixel, sample_offset) = (self.samples_per_pixel, self.sample_offset);
+        loop {
+            if checkpoint.pass_samples == 0 {
+                let remaining = samples_per_pixel - checkpoint.samples;
+                if remaining <= 0 {
+                    break;
                 }
-                color::write_color(pixel_color, Self::SAMPLES_PER_PIXEL);
+                checkpoint.pass_samples = remaining;
+                checkpoint.next_row = 0;
             }
+
+            self.samples_per_pixel = checkpoint.pass_samples;
+            self.sample_offset = checkpoint.samples;
+            let mut last_save = Instant::now();
+            let mut saved = Ok(());
+            let first_row = checkpoint.next_row;
+            let finished = self.render_rows(
+                world,
+                materials,
+                &mut film,
+                first_row,
+                None,
+                &mut |film, row| {
+                    checkpoint.next_row = row;
+                    let stop = checkpoint::interrupted();
+                    if stop || last_save.elapsed() >= interval {
+                        saved = checkpoint.save(path, film);
+                        last_save = Instant::now();
+                    }
+                    return !stop && saved.is_ok();
+                },
+            );
+            self.samples_per_pixel = samples_per_pixel;
+            self.sample_offset = sample_offset;
+            saved?;
+            if !finished {
+                return Ok(None);
+            }
+
+            checkpoint.samples += checkpoint.pass_samples;
+            checkpoint.pass_samples = 0;
+            checkpoint.next_row = 0;
         }
 
-        eprintln!("\rDone.       \n");
+        checkpoint.save(path, &film)?;
+        return Ok(Some(self.develop(&film)));
     }
 
-    fn initialize(&mut self) {
-        let calc_image_height = Self::IMAGE_WIDTH as f64 / Self::ASPECT_RATIO;
-        self.image_height = if calc_image_height < 1.0 {
+    // Renders the whole image in passes until budget runs out, ignoring
+    // samples_per_pixel. Each pass about doubles the samples per pixel, the
+    // last one shrunk to what fits in the time left, and after_pass gets the
+    // image and its state once a pass is done. The samples alternate between
+    // two half images whose difference gives the noise estimate. Ctrl-C, when
+    // caught, and the end of the time budget also cut a pass short; the rows
+    // it reached keep their extra samples, which the film weighs correctly.
+    // The first pass is never cut short, so every pixel has samples.
+    pub fn render_progressive(
+        &mut self,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+        budget: &progressive::Budget,
+        after_pass: &mut dyn FnMut(&image::Image, &progressive::Pass) -> io::Result<()>,
+    ) -> io::Result<(image::Image, progressive::Stop)> {
+        self.initialize();
+        let (width, height) = (self.image_width as usize, self.image_height as usize);
+        let mut halves = [
+            film::Film::new(width, height, self.filter),
+            film::Film::new(width, height, self.filter),
+        ];
+        let (samples_per_pixel, sample_offset, style) =
+            (self.samples_per_pixel, self.sample_offset, self.progress);
+
+        let start = Instant::now();
+        let deadline = budget.time.map(|time| start + time);
+        let mut samples = 0;
+        let mut half_samples = [0, 0];
+        let mut seconds_per_sample = 0.0;
+        let mut index = 0;
+        let stop = loop {
+            if budget.samples.is_some_and(|limit| samples >= limit) {
+                break progressive::Stop::SampleLimit;
+            }
+            let first_pass = samples == 0;
+            if !first_pass && checkpoint::interrupted() {
+                break progressive::Stop::Interrupted;
+            }
+            // an odd count only comes from the sample limit, in the last pass
+            let mut count = samples.max(2);
+            if let Some(limit) = budget.samples {
+                count = count.min(limit - samples);
+            }
+            if let (Some(deadline), false) = (deadline, first_pass) {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let fits = (remaining.as_secs_f64() / seconds_per_sample).min(count as f64);
+                count = count.min(fits as i32 / 2 * 2);
+                if count == 0 {
+                    break progressive::Stop::TimeBudget;
+                }
+            }
+
+            let pass_start = Instant::now();
+            // the passes are reported instead of the rows
+            self.progress.verbosity = progress::Verbosity::Quiet;
+            let mut offset = sample_offset + samples;
+            let mut finished = true;
+            let counts = [(count + 1) / 2, count / 2];
+            for (film, half) in halves.iter_mut().zip(counts) {
+                self.samples_per_pixel = half;
+                self.sample_offset = offset;
+                finished = self.render_rows(world, materials, film, 0, None, &mut |_, _| {
+                    return first_pass
+                        || !checkpoint::interrupted()
+                            && deadline.is_none_or(|deadline| Instant::now() < deadline);
+                });
+                offset += half;
+                if !finished {
+                    break;
+                }
+            }
+            self.samples_per_pixel = samples_per_pixel;
+            self.sample_offset = sample_offset;
+            self.progress = style;
+            if !finished {
+                if checkpoint::interrupted() {
+                    break progressive::Stop::Interrupted;
+                }
+                break progressive::Stop::TimeBudget;
+            }
+            seconds_per_sample = pass_start.elapsed().as_secs_f64() / count as f64;
+            samples += count;
+            half_samples = [half_samples[0] + counts[0], half_samples[1] + counts[1]];
+            index += 1;
+
+            let noise = progressive::estimate_noise(
+                (&self.develop(&halves[0]), half_samples[0]),
+                (&self.develop(&halves[1]), half_samples[1]),
+                &self.display,
+            );
+            let pass = progressive::Pass {
+                index,
+                samples,
+                elapsed: start.elapsed(),
+                noise,
+            };
+            pass.report(style);
+            after_pass(&self.develop_halves(&halves), &pass)?;
+            // the noise is unknown while a half is still empty
+            if budget
+                .noise
+                .zip(noise)
+                .is_some_and(|(target, noise)| noise <= target)
+            {
+                break progressive::Stop::NoiseTarget;
+            }
+        };
+
+        return Ok((self.develop_halves(&halves), stop));
+    }
+
+    fn develop_halves(&self, halves: &[film::Film; 2]) -> image::Image {
+        let mut film = halves[0].clone();
+        film.merge(&halves[1]);
+        return self.develop(&film);
+    }
+
+    // Adds samples sample_offset.. of every pixel in the rows from first_row
+    // on to film. after_row gets the film and the next row once a row is
+    // done and stops the render by returning false. Returns whether every
+    // row was rendered.
+    fn render_rows(
+        &self,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+        film: &mut film::Film,
+        first_row: i32,
+        mut aovs: Option<&mut aov::AovBuffers>,
+        after_row: &mut dyn FnMut(&film::Film, i32) -> bool,
+    ) -> bool {
+        let samples_per_row = self.image_width as u64 * self.samples_per_pixel.max(0) as u64;
+        let rows = (self.image_height - first_row).max(0) as u64;
+        let progress = progress::Progress::new(self.progress, samples_per_row * rows);
+
+        for s in first_row..self.image_height {
+            for t in 0..self.image_width {
+                let mut aov_pixel = aov::AovPixel::default();
+                for sample in 0..self.samples_per_pixel {
+                    let (film_x, film_y) = Self::film_point(t, s, self.sample_offset + sample);
+                    let Some((r, weight)) = self.get_ray(film_x, film_y) else {
+                        // points outside the projection are black
+                        film.add_sample(film_x, film_y, Color::new(0.0, 0.0, 0.0));
+                        continue;
+                    };
+                    stats::count(stats::Counter::CameraRay);
+                    let mut aov_sample = aov::AovSample::default();
+                    let record = aovs.as_ref().map(|_| &mut aov_sample);
+                    let sample_color = if let Some(view) = self.debug_view {
+                        self.debug_color(view, r, world, materials)
+                    } else if self.spectral {
+                        self.spectral_color(r, world, materials, record)
+                    } else {
+                        Self::ray_color(r, self.max_depth, world, materials, record, None)
+                    };
+                    film.add_sample(film_x, film_y, weight * sample_color);
+
+                    if aovs.is_some() {
+                        if aov_sample.hit {
+                            aov_sample.depth = vector::dot(
+                                &(aov_sample.position - self.view.center),
+                                &-self.view.w,
+                            );
+                        }
+                        aov_pixel.add(&aov_sample);
+                    }
+                }
+                if let Some(aovs) = aovs.as_mut() {
+                    aovs.set(t as usize, s as usize, &aov_pixel);
+                }
+            }
+            progress.advance(samples_per_row);
+            if !after_row(film, s + 1) {
+                progress.finish();
+                return false;
+            }
+        }
+        progress.finish();
+        return true;
+    }
+
+    // Turns the accumulated samples into the output image.
+    fn develop(&self, film: &film::Film) -> image::Image {
+        if let Some(view) = self.debug_view {
+            return view.finish(film.to_image(1.0));
+        }
+        let exposure_scale = self.exposure.map_or(1.0, |exposure| exposure.scale());
+        return film.to_image(exposure_scale);
+    }
+
+    // Starts sample number sample of pixel (x, y) and returns the continuous
+    // image position it goes through.
+    fn film_point(x: i32, y: i32, sample: i32) -> (f64, f64) {
+        utility::seed_sample(x, y, sample);
+        let px = utility::random_double(0.0, 1.0);
+        let py = utility::random_double(0.0, 1.0);
+        return (x as f64 + px, y as f64 + py);
+    }
+
+    // Traces the given samples of pixel (x, y) as render would and logs every
+    // vertex of their paths. With a seed set the paths are exactly the ones
+    // of the seeded render. The paths are the RGB ones, spectral cameras
+    // ignored, so callers reject spectral renders. Returns None for pixels
+    // outside the image.
+    pub fn trace_pixel(
+        &mut self,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+        x: i32,
+        y: i32,
+        samples: std::ops::Range<i32>,
+    ) -> Option<Vec<path_debug::PathLog>> {
+        self.initialize();
+        if x < 0 || y < 0 || x >= self.image_width || y >= self.image_height {
+            return None;
+        }
+
+        let mut paths = Vec::new();
+        for sample in samples {
+            let film = Self::film_point(x, y, sample);
+            let mut path = path_debug::PathLog::new(sample, film);
+            if let Some((r, weight)) = self.get_ray(film.0, film.1) {
+                path.origin = r.origin();
+                path.direction = r.direction();
+                path.weight = weight;
+                let color =
+                    Self::ray_color(r, self.max_depth, world, materials, None, Some(&mut path));
+                path.radiance = weight * color;
+            }
+            paths.push(path);
+        }
+        return Some(paths);
+    }
+
+    // Width and height of the rendered image in pixels.
+    pub fn image_size(&self) -> (usize, usize) {
+        let calc_image_height = self.image_width as f64 / self.aspect_ratio;
+        let image_height = if calc_image_height < 1.0 {
             1
         } else {
-            calc_image_height as i32
+            calc_image_height as usize
         };
-        self.center = self.look_from;
+        return (self.image_width.max(0) as usize, image_height);
+    }
 
-        let theta = utility::degrees_to_radians(Self::VFOV as f64);
-        let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h * self.focus_dist;
-        let viewport_width =
-            viewport_height * (Self::IMAGE_WIDTH as f64 / self.image_height as f64);
+    fn initialize(&mut self) {
+        self.image_height = self.image_size().1 as i32;
+        self.view = self.view_at(&animation::Keyframe::from_camera(0.0, self));
 
-        self.w = (self.look_from - self.look_at).unit_vector();
-        self.u = vector::cross(&self.v_up, &self.w).unit_vector();
-        self.v = vector::cross(&self.w, &self.u);
+        // a moving lens camera stays focused where the shutter opens
+        if let Some(lens) = &mut self.lens {
+            if !lens.focus(self.focus_dist) {
+                eprintln!("Could not focus lens at {}", self.focus_dist);
+            }
+        }
+    }
 
-        let viewport_u = self.u * viewport_width;
-        let viewport_v = -self.v * viewport_height;
+    // The view of the camera placed as key says.
+    fn view_at(&self, key: &animation::Keyframe) -> View {
+        let image_aspect = self.image_width as f64 / self.image_height as f64;
+        let (viewport_width, viewport_height, viewport_dist) = match self.projection {
+            Projection::Orthographic { view_width } => (view_width, view_width / image_aspect, 0.0),
+            _ => {
+                let theta = utility::degrees_to_radians(key.vfov);
+                let h = (theta / 2.0).tan();
+                let viewport_height = 2.0 * h * key.focus_dist;
+                (
+                    viewport_height * image_aspect,
+                    viewport_height,
+                    key.focus_dist,
+                )
+            }
+        };
+
+        let center = key.look_from;
+        let w = (key.look_from - key.look_at).unit_vector();
+        let u = vector::cross(&key.v_up, &w).unit_vector();
+        let v = vector::cross(&w, &u);
 
-        self.pixel_delta_u = viewport_u / Self::IMAGE_WIDTH as f64;
-        self.pixel_delta_v = viewport_v / self.image_height as f64;
+        let viewport_u = u * viewport_width;
+        let viewport_v = -v * viewport_height;
+
+        let pixel_delta_u = viewport_u / self.image_width as f64;
+        let pixel_delta_v = viewport_v / self.image_height as f64;
 
         let viewport_upper_left =
-            self.center - (self.w * self.focus_dist) - viewport_u / 2.0 - viewport_v / 2.0;
-        self.pixel00_loc = viewport_upper_left + (self.pixel_delta_u + self.pixel_delta_v) * 0.5f64;
+            center - (w * viewport_dist) - viewport_u / 2.0 - viewport_v / 2.0;
+        let pixel00_loc = viewport_upper_left + (pixel_delta_u + pixel_delta_v) * 0.5f64;
+        let defocus_angle = match self.exposure {
+            Some(exposure) => {
+                let focal_length = self.sensor_height * lens::LensSystem::SCENE_UNITS_PER_MM
+                    / (2.0 * (utility::degrees_to_radians(key.vfov) / 2.0).tan());
+                exposure.defocus_angle(focal_length, key.focus_dist)
+            }
+            None => self.defocus_angle,
+        };
         let defocus_radius =
-            self.focus_dist * utility::degrees_to_radians(self.defocus_angle / 2.0).tan();
-        self.defocus_disk_u = self.u * defocus_radius;
-        self.defocus_disk_v = self.v * defocus_radius;
+            key.focus_dist * utility::degrees_to_radians(defocus_angle / 2.0).tan();
+
+        return View {
+            center,
+            pixel00_loc,
+            pixel_delta_u,
+            pixel_delta_v,
+            u,
+            v,
+            w,
+            focus_dist: key.focus_dist,
+            defocus_angle,
+            defocus_disk_u: u * defocus_radius,
+            defocus_disk_v: v * defocus_radius,
+        };
     }
 
-    fn ray_color(r: ray::Ray, depth: i32, world: &mut dyn hittable::Hittable) -> Color {
+    // aov, when given, receives what the ray hits first and path every
+    // vertex of the path.
+    fn ray_color(
+        r: ray::Ray,
+        depth: i32,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+        aov: Option<&mut aov::AovSample>,
+        mut path: Option<&mut path_debug::PathLog>,
+    ) -> Color {
         let mut hit_record = hittable::HitRecord::new(
             vector::Vec3 { e: [0.0; 3] },
             vector::Vec3 { e: [0.0; 3] },
-            material::Material::Lambertian(material::Lambertian::new(Color::new(0.0, 0.0, 0.0))),
+            material::MaterialId::default(),
             0.0,
             true,
         );
 
         if depth <= 0 {
+            if let Some(path) = path {
+                path.termination = path_debug::Termination::MaxDepth;
+            }
             return Color::new(0.0, 0.0, 0.0);
         }
 
         if world.hit(
             &r,
-            interval::Interval::new(0.001, INFINITY),
+            interval::Interval::new(0.001, f64::INFINITY),
             &mut hit_record,
         ) {
+            if let Some(aov) = aov {
+                Self::record_aov(aov, &hit_record, materials);
+            }
             let mut scattered = ray::Ray::new(
                 vector::Vec3::new(0.0, 0.0, 0.0),
                 vector::Vec3::new(0.0, 0.0, 0.0),
             );
             let mut attenuation = Color::new(0.0, 0.0, 0.0);
 
-            if hit_record
-                .mat
-                .scatter(&r, &hit_record, &mut attenuation, &mut scattered)
-            {
-                return attenuation * Self::ray_color(scattered, depth - 1, world);
+            let mat = materials.get(hit_record.mat);
+            let event = mat.scatter_event(&r, &hit_record, &mut attenuation, &mut scattered);
+            if let Some(path) = path.as_deref_mut() {
+                path.add_vertex(&hit_record, mat, event, attenuation, scattered.direction());
+            }
+            if event != material::ScatterEvent::Absorbed {
+                if depth > 1 {
+                    stats::count(stats::Counter::BounceRay);
+                }
+                return attenuation
+                    * Self::ray_color(scattered, depth - 1, world, materials, None, path);
+            }
+            if let Some(path) = path {
+                path.termination = path_debug::Termination::Absorbed;
             }
             return Color::new(0.0, 0.0, 0.0);
         }
 
         let unit_direction = r.direction().unit_vector();
         let a = 0.5 * (unit_direction.y() + 1.0);
-        return Color { e: [1.0; 3] } * (1.0 - a) + Color { e: [0.5, 0.7, 1.0] } * a;
+        let sky = Color { e: [1.0; 3] } * (1.0 - a) + Color { e: [0.5, 0.7, 1.0] } * a;
+        if let Some(aov) = aov {
+            aov.albedo = sky;
+        }
+        if let Some(path) = path {
+            path.termination = path_debug::Termination::Miss;
+            path.environment = sky;
+        }
+        return sky;
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> ray::Ray {
-        let pixel_center = self.pixel00_loc + (self.pixel_delta_u * i) + (self.pixel_delta_v * j);
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+    // Color of a camera ray in a debug view. Scalar views return their raw
+    // value, which DebugView::finish scales over the whole image.
+    fn debug_color(
+        &self,
+        view: debug_view::DebugView,
+        r: ray::Ray,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+    ) -> Color {
+        let tests_before = stats::counters().intersection_tests;
+        let mut rec = hittable::HitRecord::default();
+        let mut hit = world.hit(&r, interval::Interval::new(0.001, f64::INFINITY), &mut rec);
 
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center
-        } else {
-            self.defocus_disk_sample()
+        let view = match view {
+            debug_view::DebugView::Surface(view) => {
+                if !hit {
+                    return Color::new(0.0, 0.0, 0.0);
+                }
+                return view.color(&rec);
+            }
+            debug_view::DebugView::Scalar(view) => view,
         };
-        let ray_direction = pixel_sample - ray_origin;
+        let value = match view {
+            debug_view::ScalarView::Depth => {
+                if !hit {
+                    return Color::new(0.0, 0.0, 0.0);
+                }
+                1.0 / vector::dot(&(rec.p - self.view.center), &-self.view.w).max(1e-6)
+            }
+            debug_view::ScalarView::Bounces | debug_view::ScalarView::IntersectionTests => {
+                // follow the path the way ray_color does, without shading it
+                let mut bounces = 0;
+                let mut r = r;
+                while hit {
+                    let mut attenuation = Color::new(0.0, 0.0, 0.0);
+                    let mut scattered = r;
+                    if !materials
+                        .get(rec.mat)
+                        .scatter(&r, &rec, &mut attenuation, &mut scattered)
+                    {
+                        break;
+                    }
+                    bounces += 1;
+                    if bounces >= self.max_depth {
+                        // ray_color does not trace the last scattered ray
+                        break;
+                    }
+                    stats::count(stats::Counter::BounceRay);
+                    r = scattered;
+                    hit = world.hit(&r, interval::Interval::new(0.001, f64::INFINITY), &mut rec);
+                }
+                if view == debug_view::ScalarView::Bounces {
+                    bounces as f64
+                } else {
+                    (stats::counters().intersection_tests - tests_before) as f64
+                }
+            }
+        };
+        return Color::new(value, value, value);
+    }
 
-        return ray::Ray::new(ray_origin, ray_direction);
+    fn record_aov(
+        aov: &mut aov::AovSample,
+        rec: &hittable::HitRecord,
+        materials: &material::MaterialList,
+    ) {
+        aov.hit = true;
+        aov.normal = rec.normal;
+        aov.albedo = materials.get(rec.mat).albedo();
+        aov.position = rec.p;
+        aov.material_id = rec.mat;
+        aov.object_id = rec.object_id;
     }
 
-    fn pixel_sample_square(&self) -> vector::Vec3 {
-        let px = -0.5 + utility::random_double(0.0, 1.0);
-        let py = -0.5 + utility::random_double(0.0, 1.0);
-        return (self.pixel_delta_u * px) + (self.pixel_delta_v * py);
+    // Traces one path with hero wavelength sampling and returns its RGB estimate.
+    fn spectral_color(
+        &self,
+        r: ray::Ray,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+        aov: Option<&mut aov::AovSample>,
+    ) -> Color {
+        let mut wavelengths =
+            spectrum::SampledWavelengths::sample_uniform(utility::random_double(0.0, 1.0));
+        let r = ray::Ray::with_wavelength(r.origin(), r.direction(), wavelengths.hero());
+        let radiance =
+            Self::spectral_ray_color(r, self.max_depth, world, materials, &mut wavelengths, aov);
+        return wavelengths.estimate_rgb(&radiance);
     }
 
-    fn defocus_disk_sample(&self) -> vector::Vec3 {
-        let p = vector::Vec3::random_in_unit_disk();
-        return self.center + (self.defocus_disk_u * p[0]) + (self.defocus_disk_v * p[1]);
+    fn spectral_ray_color(
+        r: ray::Ray,
+        depth: i32,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+        wavelengths: &mut spectrum::SampledWavelengths,
+        aov: Option<&mut aov::AovSample>,
+    ) -> spectrum::SampledSpectrum {
+        let mut hit_record = hittable::HitRecord::default();
+
+        if depth <= 0 {
+            return spectrum::SampledSpectrum::new(0.0);
+        }
+
+        if world.hit(
+            &r,
+            interval::Interval::new(0.001, f64::INFINITY),
+            &mut hit_record,
+        ) {
+            if let Some(aov) = aov {
+                Self::record_aov(aov, &hit_record, materials);
+            }
+            let mat = materials.get(hit_record.mat);
+            let mut scattered = ray::Ray::new(
+                vector::Vec3::new(0.0, 0.0, 0.0),
+                vector::Vec3::new(0.0, 0.0, 0.0),
+            );
+            let mut attenuation = Color::new(0.0, 0.0, 0.0);
+
+            if mat.scatter(&r, &hit_record, &mut attenuation, &mut scattered) {
+                if mat.is_dispersive() {
+                    wavelengths.terminate_secondary();
+                }
+                if depth > 1 {
+                    stats::count(stats::Counter::BounceRay);
+                }
+                let attenuation = spectrum::SampledSpectrum::from_rgb(attenuation, wavelengths);
+                return attenuation
+                    * Self::spectral_ray_color(
+                        scattered,
+                        depth - 1,
+                        world,
+                        materials,
+                        wavelengths,
+                        None,
+                    );
+            }
+            return spectrum::SampledSpectrum::new(0.0);
+        }
+
+        let unit_direction = r.direction().unit_vector();
+        let a = 0.5 * (unit_direction.y() + 1.0);
+        let sky = Color { e: [1.0; 3] } * (1.0 - a) + Color { e: [0.5, 0.7, 1.0] } * a;
+        if let Some(aov) = aov {
+            aov.albedo = sky;
+        }
+        return spectrum::SampledSpectrum::from_rgb(sky, wavelengths);
+    }
+
+    // Returns the ray through continuous image position (x, y) with the color
+    // weight of the sample, or None for image points the projection does not
+    // cover. Pixel (i, j) covers [i, i + 1) x [j, j + 1).
+    fn get_ray(&self, x: f64, y: f64) -> Option<(ray::Ray, Color)> {
+        let view = &match &self.motion {
+            Some(shutter) => self.view_at(&shutter.sample()),
+            None => self.view,
+        };
+        let pixel_sample =
+            view.pixel00_loc + (view.pixel_delta_u * (x - 0.5)) + (view.pixel_delta_v * (y - 0.5));
+
+        if let Some(lens) = &self.lens {
+            let r = self.get_lens_ray(view, lens, x, y)?;
+            return Some((r, Color::new(1.0, 1.0, 1.0)));
+        }
+
+        match self.projection {
+            Projection::Perspective => {
+                let (pixel_sample, weight) = self.apply_lens_effects(view, pixel_sample);
+                let ray_origin = if view.defocus_angle <= 0.0 {
+                    view.center
+                } else {
+                    self.defocus_disk_sample(view)
+                };
+                let ray_direction = pixel_sample - ray_origin;
+
+                return Some((ray::Ray::new(ray_origin, ray_direction), weight));
+            }
+            Projection::Orthographic { .. } => {
+                let r = ray::Ray::new(pixel_sample, -view.w);
+                return Some((r, Color::new(1.0, 1.0, 1.0)));
+            }
+            Projection::Fisheye { mapping, fov } => {
+                let radius = self.image_width.min(self.image_height) as f64 / 2.0;
+                let x = (x - self.image_width as f64 / 2.0) / radius;
+                let y = (y - self.image_height as f64 / 2.0) / radius;
+                let r = (x * x + y * y).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+
+                let half_fov = utility::degrees_to_radians(fov / 2.0);
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * half_fov,
+                    FisheyeMapping::Equisolid => 2.0 * (r * (half_fov / 2.0).sin()).asin(),
+                };
+                let phi = y.atan2(x);
+                let ray_direction = view.u * (theta.sin() * phi.cos())
+                    - view.v * (theta.sin() * phi.sin())
+                    - view.w * theta.cos();
+
+                let r = ray::Ray::new(view.center, ray_direction);
+                return Some((r, Color::new(1.0, 1.0, 1.0)));
+            }
+            Projection::Equirectangular => {
+                let x = x / self.image_width as f64;
+                let y = y / self.image_height as f64;
+                let longitude = (x - 0.5) * 2.0 * std::f64::consts::PI;
+                let latitude = (0.5 - y) * std::f64::consts::PI;
+                let ray_direction = view.u * (latitude.cos() * longitude.sin())
+                    + view.v * latitude.sin()
+                    - view.w * (latitude.cos() * longitude.cos());
+                // eyes sit on a circle, offset perpendicular to the viewing azimuth
+                let eye_tangent = view.u * longitude.cos() + view.w * longitude.sin();
+                let ray_origin = view.center + eye_tangent * self.ods_eye_offset;
+
+                let r = ray::Ray::new(ray_origin, ray_direction);
+                return Some((r, Color::new(1.0, 1.0, 1.0)));
+            }
+        }
+    }
+
+    // Applies chromatic aberration, distortion and vignetting to a point on
+    // the focus plane, returning the point to aim at and the sample weight.
+    fn apply_lens_effects(&self, view: &View, pixel_sample: Point3) -> (Point3, Color) {
+        let mut weight = Color::new(1.0, 1.0, 1.0);
+        let mut target = pixel_sample;
+
+        if self.chromatic_aberration != 0.0 || !self.distortion.is_identity() {
+            // normalized image plane coordinates at unit distance
+            let focus_center = view.center - view.w * view.focus_dist;
+            let offset = (pixel_sample - focus_center) / view.focus_dist;
+            let mut x = vector::dot(&offset, &view.u);
+            let mut y = vector::dot(&offset, &view.v);
+
+            if self.chromatic_aberration != 0.0 {
+                // trace one channel per sample, weighted so the average is unbiased
+                let channel = (utility::random_double(0.0, 3.0) as usize).min(2);
+                let magnification = 1.0 + self.chromatic_aberration * (1.0 - channel as f64) / 2.0;
+                x /= magnification;
+                y /= magnification;
+                weight = Color::new(0.0, 0.0, 0.0);
+                weight.e[channel] = 3.0;
+            }
+
+            (x, y) = self.distortion.undistort(x, y);
+            target = focus_center + (view.u * x + view.v * y) * view.focus_dist;
+        }
+
+        if self.vignetting {
+            let cos_theta = vector::dot(&(target - view.center).unit_vector(), &-view.w);
+            weight = weight * cos_theta.powi(4);
+        }
+
+        return (target, weight);
+    }
+
+    // Traces a ray from the film position of image point (x, y) through a
+    // random point on the rear lens element.
+    fn get_lens_ray(
+        &self,
+        view: &View,
+        lens: &lens::LensSystem,
+        x: f64,
+        y: f64,
+    ) -> Option<ray::Ray> {
+        let film = lens.film_point(
+            x / self.image_width as f64,
+            y / self.image_height as f64,
+            self.image_width as f64 / self.image_height as f64,
+        );
+        let disk = vector::Vec3::random_in_unit_disk() * lens.rear_aperture_radius();
+        let rear = Point3::new(disk.x(), disk.y(), lens.rear_z());
+        let (o, d) = lens.trace_from_film(film, rear - film)?;
+
+        // lens space looks down -z, which is -w in the camera frame
+        let ray_origin = view.center + view.u * o.x() + view.v * o.y() + view.w * o.z();
+        let ray_direction = view.u * d.x() + view.v * d.y() + view.w * d.z();
+        return Some(ray::Ray::new(ray_origin, ray_direction));
+    }
+
+    fn defocus_disk_sample(&self, view: &View) -> vector::Vec3 {
+        let p = self.aperture.sample();
+        return view.center + (view.defocus_disk_u * p[0]) + (view.defocus_disk_v * p[1]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::hittable_list;
+
+    // A 200x100 camera at the origin looking down -z.
+    fn camera(projection: Projection) -> Camera {
+        let mut cam = Camera::new();
+        cam.image_width = 200;
+        cam.aspect_ratio = 2.0;
+        cam.vfov = 90.0;
+        cam.look_from = Point3::new(0.0, 0.0, 0.0);
+        cam.look_at = Point3::new(0.0, 0.0, -1.0);
+        cam.projection = projection;
+        cam.initialize();
+        return cam;
+    }
+
+    fn assert_ray(cam: &Camera, film: (f64, f64), origin: Point3, direction: vector::Vec3) {
+        let (r, _) = cam.get_ray(film.0, film.1).expect("film point is covered");
+        assert!(
+            (r.origin() - origin).length() < 1e-9,
+            "origin at {:?}: ({}, {}, {})",
+            film,
+            r.origin().x(),
+            r.origin().y(),
+            r.origin().z()
+        );
+        let d = r.direction().unit_vector();
+        assert!(
+            (d - direction.unit_vector()).length() < 1e-9,
+            "direction at {:?}: ({}, {}, {})",
+            film,
+            d.x(),
+            d.y(),
+            d.z()
+        );
+    }
+
+    #[test]
+    fn perspective_maps_the_frame_to_the_field_of_view() {
+        let cam = camera(Projection::Perspective);
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        assert_ray(
+            &cam,
+            (100.0, 50.0),
+            origin,
+            vector::Vec3::new(0.0, 0.0, -1.0),
+        );
+        // the top edge lies 45 degrees up, and the frame is twice as wide
+        assert_ray(
+            &cam,
+            (100.0, 0.0),
+            origin,
+            vector::Vec3::new(0.0, 1.0, -1.0),
+        );
+        assert_ray(
+            &cam,
+            (150.0, 50.0),
+            origin,
+            vector::Vec3::new(1.0, 0.0, -1.0),
+        );
+    }
+
+    #[test]
+    fn orthographic_shifts_parallel_rays() {
+        let cam = camera(Projection::from_name("orthographic", Some(4.0)).unwrap());
+        let forward = vector::Vec3::new(0.0, 0.0, -1.0);
+        assert_ray(&cam, (100.0, 50.0), Point3::new(0.0, 0.0, 0.0), forward);
+        assert_ray(&cam, (150.0, 50.0), Point3::new(1.0, 0.0, 0.0), forward);
+        assert_ray(&cam, (200.0, 0.0), Point3::new(2.0, 1.0, 0.0), forward);
+    }
+
+    #[test]
+    fn fisheyes_map_the_image_radius_to_the_angle() {
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        let cam = camera(Projection::from_name("fisheye", None).unwrap());
+        // the image circle has a radius of 50 pixels and covers 180 degrees
+        assert_ray(
+            &cam,
+            (150.0, 50.0),
+            origin,
+            vector::Vec3::new(1.0, 0.0, 0.0),
+        );
+        assert_ray(
+            &cam,
+            (100.0, 25.0),
+            origin,
+            vector::Vec3::new(0.0, 1.0, -1.0),
+        );
+        assert!(cam.get_ray(160.0, 50.0).is_none());
+
+        let cam = camera(Projection::from_name("fisheye-equisolid", None).unwrap());
+        let theta = 2.0 * (0.5 * std::f64::consts::FRAC_PI_4.sin()).asin();
+        let expected = vector::Vec3::new(theta.sin(), 0.0, -theta.cos());
+        assert_ray(&cam, (125.0, 50.0), origin, expected);
+        assert_ray(
+            &cam,
+            (150.0, 50.0),
+            origin,
+            vector::Vec3::new(1.0, 0.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn equirectangular_maps_longitude_and_latitude() {
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        let cam = camera(Projection::from_name("equirectangular", None).unwrap());
+        assert_ray(
+            &cam,
+            (100.0, 50.0),
+            origin,
+            vector::Vec3::new(0.0, 0.0, -1.0),
+        );
+        assert_ray(
+            &cam,
+            (150.0, 50.0),
+            origin,
+            vector::Vec3::new(1.0, 0.0, 0.0),
+        );
+        assert_ray(&cam, (0.0, 50.0), origin, vector::Vec3::new(0.0, 0.0, 1.0));
+        assert_ray(
+            &cam,
+            (100.0, 25.0),
+            origin,
+            vector::Vec3::new(0.0, 1.0, -1.0),
+        );
+    }
+
+    #[test]
+    fn exposure_sets_the_defocus_without_changing_defocus_angle() {
+        let mut cam = camera(Projection::Perspective);
+        cam.defocus_angle = 3.0;
+        cam.focus_dist = 2.0;
+        cam.initialize();
+        assert!(cam.view.defocus_angle == 3.0);
+
+        // sensor_height 24 mm and vfov 90 give a 12 mm lens
+        let exposure = physical_camera::Exposure::new(100.0, 0.01, 4.0);
+        cam.exposure = Some(exposure);
+        for _ in 0..2 {
+            cam.initialize();
+            assert!(cam.defocus_angle == 3.0);
+            let expected = exposure.defocus_angle(0.012, 2.0);
+            assert!((cam.view.defocus_angle - expected).abs() < 1e-12);
+            let radius = 2.0 * utility::degrees_to_radians(expected / 2.0).tan();
+            assert!((cam.view.defocus_disk_u.length() - radius).abs() < 1e-12);
+        }
+
+        cam.exposure = None;
+        cam.initialize();
+        assert!(cam.view.defocus_angle == 3.0);
+    }
+
+    #[test]
+    fn moving_cameras_sample_the_open_shutter() {
+        // look_from moves from x = 0 to x = 1 while the shutter is open
+        let mut animation = animation::CameraAnimation::new(1.0, animation::Interpolation::Linear);
+        let mut cam = camera(Projection::Perspective);
+        animation.add_keyframe(animation::Keyframe::from_camera(0.0, &cam));
+        cam.look_from = Point3::new(1.0, 0.0, 0.0);
+        cam.look_at = Point3::new(1.0, 0.0, -1.0);
+        animation.add_keyframe(animation::Keyframe::from_camera(1.0, &cam));
+        cam.motion = Some(animation::Shutter::new(animation, 0.0, 1.0));
+        cam.initialize();
+
+        let mut halves = [0; 2];
+        for _ in 0..1000 {
+            let (r, _) = cam.get_ray(100.0, 50.0).unwrap();
+            let x = r.origin().x();
+            assert!((0.0..=1.0).contains(&x) && r.origin().y() == 0.0);
+            // the view moves with the camera
+            let d = r.direction().unit_vector();
+            assert!((d - vector::Vec3::new(0.0, 0.0, -1.0)).length() < 1e-9);
+            halves[(x >= 0.5) as usize] += 1;
+        }
+        assert!(halves[0] > 400 && halves[1] > 400);
+    }
+
+    // A grey unit sphere 2 in front of a quiet 20x10 camera, with 1 sample
+    // per pixel.
+    fn sphere_scene() -> (Camera, hittable_list::HittableList, material::MaterialList) {
+        let mut materials = material::MaterialList::new();
+        let grey = materials.add(material::Material::Lambertian(material::Lambertian::new(
+            Color::new(0.5, 0.5, 0.5),
+        )));
+        let world = hittable_list::HittableList::new(vec![Box::new(hittable::Sphere::new(
+            Point3::new(0.0, 0.0, -2.0),
+            1.0,
+            grey,
+        ))]);
+
+        let mut cam = camera(Projection::Perspective);
+        cam.image_width = 20;
+        cam.samples_per_pixel = 1;
+        cam.progress.verbosity = progress::Verbosity::Quiet;
+        return (cam, world, materials);
+    }
+
+    #[test]
+    fn aovs_match_the_image() {
+        let (mut cam, mut world, materials) = sphere_scene();
+        let (image, aovs) = cam.render_image_with_aovs(&mut world, &materials);
+        assert!(image.width == 20 && image.height == 10);
+        for (name, layer) in aovs.layers() {
+            assert!(layer.width == 20 && layer.height == 10, "{}", name);
+        }
+        // the sphere's front is 1 in front of the camera and curves away to
+        // 1.045 at the far corner of the pixel next to its centre; the sky
+        // has no depth
+        assert!((1.0..1.05).contains(&aovs.depth.get(10, 5).x()));
+        assert!(aovs.depth.get(0, 0).x() == 0.0);
+    }
+
+    #[test]
+    fn traced_samples_match_the_seeded_render() {
+        let (mut cam, mut world, materials) = sphere_scene();
+        utility::seed(11);
+        let image = cam.render_image(&mut world, &materials);
+        utility::seed(11);
+        for (x, y) in [(10, 5), (0, 0)] {
+            let paths = cam
+                .trace_pixel(&mut world, &materials, x, y, 0..1)
+                .expect("pixel is inside the image");
+            // one sample and a box filter leave the sample as the pixel
+            let difference = (paths[0].radiance - image.get(x as usize, y as usize)).length();
+            assert!(
+                difference < 1e-12,
+                "pixel {},{} is off by {}",
+                x,
+                y,
+                difference
+            );
+        }
+        // the centre pixel sees the sphere, the corner only the sky
+        let centre = cam
+            .trace_pixel(&mut world, &materials, 10, 5, 0..1)
+            .unwrap();
+        assert!(centre[0].vertices[0].material_type == "lambertian");
+        let corner = cam.trace_pixel(&mut world, &materials, 0, 0, 0..1).unwrap();
+        assert!(corner[0].vertices.is_empty());
+        assert!(corner[0].termination == path_debug::Termination::Miss);
+        assert!(cam
+            .trace_pixel(&mut world, &materials, 20, 5, 0..1)
+            .is_none());
+    }
+
+    #[test]
+    fn progressive_passes_double_up_to_the_limit() {
+        let (mut cam, mut world, materials) = sphere_scene();
+        let budget = progressive::Budget {
+            samples: Some(11),
+            ..Default::default()
+        };
+        let mut passes = Vec::new();
+        let (image, stop) = cam
+            .render_progressive(&mut world, &materials, &budget, &mut |image, pass| {
+                assert!(image.width == 20 && image.height == 10);
+                passes.push((pass.index, pass.samples, pass.noise));
+                return Ok(());
+            })
+            .unwrap();
+        assert!(stop == progressive::Stop::SampleLimit);
+        let samples: Vec<i32> = passes.iter().map(|pass| pass.1).collect();
+        assert!(samples == [2, 4, 8, 11], "{:?}", samples);
+        assert!(passes
+            .iter()
+            .all(|pass| pass.2.is_some_and(|noise| noise.is_finite() && noise > 0.0)));
+        assert!(image.get(0, 0).x() > 0.0);
+    }
+
+    #[test]
+    fn a_single_sample_does_not_meet_the_noise_target() {
+        // the first pass leaves the second half empty, which must not read
+        // as a noiseless image
+        let (mut cam, mut world, materials) = sphere_scene();
+        let budget = progressive::Budget {
+            noise: Some(1.0),
+            samples: Some(1),
+            ..Default::default()
+        };
+        let mut passes = Vec::new();
+        let (_, stop) = cam
+            .render_progressive(&mut world, &materials, &budget, &mut |_, pass| {
+                passes.push((pass.samples, pass.noise));
+                return Ok(());
+            })
+            .unwrap();
+        assert!(passes == [(1, None)]);
+        assert!(stop == progressive::Stop::SampleLimit);
+    }
+
+    #[test]
+    fn progressive_renders_finish_the_first_pass() {
+        // the time budget is spent before the first pass starts
+        let (mut cam, mut world, materials) = sphere_scene();
+        let budget = progressive::Budget {
+            time: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let mut passes = Vec::new();
+        let (image, stop) = cam
+            .render_progressive(&mut world, &materials, &budget, &mut |_, pass| {
+                passes.push(pass.samples);
+                return Ok(());
+            })
+            .unwrap();
+        assert!(stop == progressive::Stop::TimeBudget);
+        assert!(passes == [2]);
+        // every pixel sees the sky or the lit sphere
+        for y in 0..10 {
+            for x in 0..20 {
+                assert!(image.get(x, y).length() > 0.0, "pixel {},{} is black", x, y);
+            }
+        }
     }
 }
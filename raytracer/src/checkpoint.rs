@@ -0,0 +1,247 @@
+use crate::film;
+
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+// Where a render stands, saved next to the film so it can be continued after
+// the process stops. A render runs in passes over the whole image; a pass
+// takes the samples after the ones of every earlier pass, row by row. Rows
+// before next_row of the current pass hold samples + pass_samples samples
+// per pixel, the others samples.
+pub struct Checkpoint {
+    // preset the render belongs to, checked when resuming
+    pub scene: String,
+    // samples per pixel of the finished passes
+    pub samples: i32,
+    // samples per pixel of the pass in progress, 0 between passes
+    pub pass_samples: i32,
+    pub next_row: i32,
+}
+
+const MAGIC: &str = "RTCHECKPOINT 1";
+
+impl Checkpoint {
+    pub fn new(scene: &str) -> Self {
+        Checkpoint {
+            scene: scene.to_string(),
+            samples: 0,
+            pass_samples: 0,
+            next_row: 0,
+        }
+    }
+
+    // Writes a text header followed by the film's state. The file is
+    // replaced at once, so an interruption never leaves half a checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P, film: &film::Film) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+
+        let mut out = BufWriter::new(fs::File::create(&temporary)?);
+        writeln!(out, "{}", MAGIC)?;
+        writeln!(out, "scene {}", self.scene)?;
+        writeln!(out, "width {}", film.width)?;
+        writeln!(out, "height {}", film.height)?;
+        writeln!(out, "filter {:?}", film.filter)?;
+        writeln!(out, "samples {}", self.samples)?;
+        writeln!(out, "pass_samples {}", self.pass_samples)?;
+        writeln!(out, "next_row {}", self.next_row)?;
+        writeln!(out, "data")?;
+        film.write_state(&mut out)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        return fs::rename(&temporary, path);
+    }
+
+    // Reads a checkpoint and its film, which must have been rendered with
+    // filter at size (width, height).
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        filter: film::Filter,
+        size: (usize, usize),
+    ) -> io::Result<(Checkpoint, film::Film)> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut input = io::BufReader::new(fs::File::open(path)?);
+
+        let mut line = String::new();
+        input.read_line(&mut line)?;
+        if line.trim_end() != MAGIC {
+            return Err(invalid("not a render checkpoint".to_string()));
+        }
+
+        let mut checkpoint = Checkpoint::new("");
+        let (mut width, mut height) = (0, 0);
+        loop {
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                return Err(invalid("checkpoint ends before its data".to_string()));
+            }
+            let line = line.trim_end();
+            if line == "data" {
+                break;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let number = || {
+                value
+                    .parse::<i64>()
+                    .map_err(|_| invalid(format!("invalid {} in checkpoint: {}", key, value)))
+            };
+            match key {
+                "scene" => checkpoint.scene = value.to_string(),
+                "width" => width = number()?,
+                "height" => height = number()?,
+                "filter" => {
+                    if value != format!("{:?}", filter) {
+                        return Err(invalid(format!(
+                            "checkpoint was rendered with the filter {}",
+                            value
+                        )));
+                    }
+                }
+                "samples" => checkpoint.samples = number()? as i32,
+                "pass_samples" => checkpoint.pass_samples = number()? as i32,
+                "next_row" => checkpoint.next_row = number()? as i32,
+                _ => return Err(invalid(format!("unknown checkpoint field {}", key))),
+            }
+        }
+
+        // checked before the film is allocated, so a damaged header cannot
+        // ask for an arbitrary amount of memory
+        if width <= 0 || height <= 0 {
+            return Err(invalid(format!(
+                "invalid checkpoint size {}x{}",
+                width, height
+            )));
+        }
+        if (width as u64, height as u64) != (size.0 as u64, size.1 as u64) {
+            return Err(invalid(format!(
+                "checkpoint is {}x{} but the camera renders {}x{}",
+                width, height, size.0, size.1
+            )));
+        }
+
+        let mut film = film::Film::new(size.0, size.1, filter);
+        film.read_state(&mut input)?;
+        if input.read(&mut [0u8])? != 0 {
+            return Err(invalid("checkpoint has data after its film".to_string()));
+        }
+        return Ok((checkpoint, film));
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Makes Ctrl-C ask the render to save a checkpoint and stop instead of
+// killing the process. A second Ctrl-C exits at once.
+pub fn catch_interrupts() -> io::Result<()> {
+    return ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            process::exit(130);
+        }
+    })
+    .map_err(io::Error::other);
+}
+
+pub fn interrupted() -> bool {
+    return INTERRUPTED.load(Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::vector;
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let filter = film::Filter::Gaussian {
+            radius: 1.5,
+            alpha: 2.0,
+        };
+        let mut film = film::Film::new(5, 3, filter);
+        film.add_sample(1.3, 2.7, vector::Vec3::new(0.25, 1.5, 3.0));
+        film.add_sample(4.9, 0.1, vector::Vec3::new(7.0, 0.0, 0.125));
+        let checkpoint = Checkpoint {
+            scene: "glass".to_string(),
+            samples: 16,
+            pass_samples: 8,
+            next_row: 2,
+        };
+
+        let path = std::env::temp_dir().join(format!("checkpoint-test-{}", process::id()));
+        checkpoint.save(&path, &film).unwrap();
+        let (loaded, loaded_film) = Checkpoint::load(&path, filter, (5, 3)).unwrap();
+        let mismatched = Checkpoint::load(&path, film::Filter::Box { radius: 0.5 }, (5, 3));
+        fs::remove_file(&path).unwrap();
+
+        assert!(loaded.scene == "glass" && loaded.samples == 16);
+        assert!(loaded.pass_samples == 8 && loaded.next_row == 2);
+        let (image, loaded_image) = (film.to_image(1.0), loaded_film.to_image(1.0));
+        for y in 0..3 {
+            for x in 0..5 {
+                let (a, b) = (image.get(x, y), loaded_image.get(x, y));
+                assert!(a.x() == b.x() && a.y() == b.y() && a.z() == b.z());
+            }
+        }
+        assert!(mismatched.is_err());
+    }
+
+    #[test]
+    fn load_rejects_damaged_checkpoints() {
+        let filter = film::Filter::Box { radius: 0.5 };
+        let mut film_state = Vec::new();
+        film::Film::new(2, 1, filter)
+            .write_state(&mut film_state)
+            .unwrap();
+        let header = |width: &str, height: &str| {
+            let mut data = format!(
+                "{}\nscene glass\nwidth {}\nheight {}\nfilter {:?}\nsamples 4\n\
+                 pass_samples 0\nnext_row 0\ndata\n",
+                MAGIC, width, height, filter
+            )
+            .into_bytes();
+            data.extend(&film_state);
+            return data;
+        };
+
+        let path = std::env::temp_dir().join(format!("checkpoint-damaged-{}", process::id()));
+        let load = |data: &[u8]| {
+            fs::write(&path, data).unwrap();
+            return Checkpoint::load(&path, filter, (2, 1)).map(|(checkpoint, _)| checkpoint);
+        };
+        assert!(load(&header("2", "1")).unwrap().samples == 4);
+
+        let mut trailing = header("2", "1");
+        trailing.push(0);
+        let truncated = header("2", "1");
+        let truncated = &truncated[..truncated.len() - 1];
+        for data in [
+            header("0", "1"),
+            header("2", "-1"),
+            // far too large to allocate
+            header("4000000000", "4000000000"),
+            // the camera renders 2x1
+            header("1", "2"),
+            trailing,
+            truncated.to_vec(),
+        ] {
+            let error = load(&data).err().expect("damaged checkpoint loads");
+            assert!(
+                matches!(
+                    error.kind(),
+                    io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+                ),
+                "{}",
+                error
+            );
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,34 +1,234 @@
-use crate::interval;
+use crate::utility;
 use crate::vector;
 
-pub fn linear_to_gamma(linear_comp: f64) -> f64 {
-    return linear_comp.sqrt();
+use std::io;
+
+use vector::Vec3 as Color;
+
+// The exact sRGB transfer function for a linear component in [0, 1].
+pub fn linear_to_srgb(linear_comp: f64) -> f64 {
+    if linear_comp <= 0.0031308 {
+        return 12.92 * linear_comp;
+    }
+    return 1.055 * linear_comp.powf(1.0 / 2.4) - 0.055;
+}
+
+pub fn srgb_to_linear(srgb_comp: f64) -> f64 {
+    if srgb_comp <= 0.04045 {
+        return srgb_comp / 12.92;
+    }
+    return ((srgb_comp + 0.055) / 1.055).powf(2.4);
+}
+
+// Operators that compress scene-referred radiance into the displayable range.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ToneMap {
+    // clips everything above 1
+    Clamp,
+    // c / (1 + c) per channel
+    Reinhard,
+    // Reinhard that reaches white at white_point instead of at infinity
+    ExtendedReinhard { white_point: f64 },
+    // Narkowicz's fit of the ACES filmic reference rendering transform
+    AcesFilmic,
+    // minimal AgX: log encoding in a desaturated working space followed by a
+    // sigmoid, which rolls bright saturated colors off towards white
+    AgX,
+}
+
+impl ToneMap {
+    pub fn from_name(name: &str) -> Option<ToneMap> {
+        match name {
+            "clamp" => Some(ToneMap::Clamp),
+            "reinhard" => Some(ToneMap::Reinhard),
+            "extended-reinhard" => Some(ToneMap::ExtendedReinhard { white_point: 4.0 }),
+            "aces" => Some(ToneMap::AcesFilmic),
+            "agx" => Some(ToneMap::AgX),
+            _ => None,
+        }
+    }
+
+    // Maps a linear color to display-linear values in [0, 1].
+    pub fn apply(&self, c: Color) -> Color {
+        match self {
+            ToneMap::Clamp => Self::per_channel(c, |x| x.clamp(0.0, 1.0)),
+            ToneMap::Reinhard => Self::per_channel(c, |x| x / (1.0 + x)),
+            ToneMap::ExtendedReinhard { white_point } => {
+                let white2 = white_point * white_point;
+                Self::per_channel(c, |x| (x * (1.0 + x / white2) / (1.0 + x)).min(1.0))
+            }
+            ToneMap::AcesFilmic => Self::per_channel(c, |x| {
+                let x = x * 0.6;
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }),
+            ToneMap::AgX => Self::agx(c),
+        }
+    }
+
+    fn per_channel(c: Color, f: impl Fn(f64) -> f64) -> Color {
+        return Color::new(f(c.x().max(0.0)), f(c.y().max(0.0)), f(c.z().max(0.0)));
+    }
+
+    fn agx(c: Color) -> Color {
+        const MIN_EV: f64 = -12.47393;
+        const MAX_EV: f64 = 4.026069;
+
+        let inset = Color::new(
+            0.842479062253094 * c.x() + 0.0784335999999992 * c.y() + 0.0792237451477643 * c.z(),
+            0.0423282422610123 * c.x() + 0.878468636469772 * c.y() + 0.0791661274605434 * c.z(),
+            0.0423756549057051 * c.x() + 0.0784336 * c.y() + 0.879142973793104 * c.z(),
+        );
+        let curve = |x: f64| {
+            let v = (x.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+            let v2 = v * v;
+            let v4 = v2 * v2;
+            15.5 * v4 * v2 - 40.14 * v4 * v + 31.96 * v4 - 6.868 * v2 * v + 0.4298 * v2 + 0.1191 * v
+                - 0.00232
+        };
+        let s = Color::new(curve(inset.x()), curve(inset.y()), curve(inset.z()));
+        let outset = Color::new(
+            1.19687900512017 * s.x() - 0.0980208811401368 * s.y() - 0.0990297440797205 * s.z(),
+            -0.0528968517574562 * s.x() + 1.15190312990417 * s.y() - 0.0989611768448433 * s.z(),
+            -0.0529716355144438 * s.x() - 0.0980434501171241 * s.y() + 1.15107367264116 * s.z(),
+        );
+        // the sigmoid output is display encoded with a 2.2 gamma
+        return Self::per_channel(outset, |x| x.clamp(0.0, 1.0).powf(2.2));
+    }
+}
+
+// Turns linear scene radiance into 8-bit sRGB: exposure, tone mapping, the
+// sRGB transfer function and optional dithering before quantization.
+#[derive(Clone, Copy)]
+pub struct DisplayTransform {
+    pub exposure_stops: f64,
+    pub tone_map: ToneMap,
+    // adds triangular noise of +-1 LSB to break up banding in gradients
+    pub dither: bool,
+}
+
+impl DisplayTransform {
+    pub fn new() -> Self {
+        DisplayTransform {
+            exposure_stops: 0.0,
+            tone_map: ToneMap::Clamp,
+            dither: false,
+        }
+    }
+
+    // Display-linear color in [0, 1].
+    pub fn tone_map(&self, pixel_color: Color) -> Color {
+        return self
+            .tone_map
+            .apply(pixel_color * 2.0f64.powf(self.exposure_stops));
+    }
+
+    pub fn encode(&self, pixel_color: Color) -> [u8; 3] {
+        let display = self.tone_map(pixel_color);
+        let quantize = |c: f64| {
+            let mut v = linear_to_srgb(c) * 255.0;
+            if self.dither {
+                v += utility::random_double(0.0, 1.0) - utility::random_double(0.0, 1.0);
+            }
+            v.round().clamp(0.0, 255.0) as u8
+        };
+        return [
+            quantize(display.x()),
+            quantize(display.y()),
+            quantize(display.z()),
+        ];
+    }
+}
+
+pub fn write_color(
+    out: &mut impl io::Write,
+    pixel_color: Color,
+    display: &DisplayTransform,
+) -> io::Result<()> {
+    let [r, g, b] = display.encode(pixel_color);
+    writeln!(out, "{} {} {}", r, g, b)
 }
 
-pub fn write_color(pixel_color: vector::Vec3, samples_per_pixel: i32) {
-    let mut r = pixel_color.x();
-    let mut g = pixel_color.y();
-    let mut b = pixel_color.z();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // apply MSAA
-    let scale = 1.0 / samples_per_pixel as f64;
+    fn grey(c: f64) -> Color {
+        return Color::new(c, c, c);
+    }
 
-    r *= scale;
-    g *= scale;
-    b *= scale;
+    #[test]
+    fn srgb_encoding_round_trips() {
+        for i in 0..=1000 {
+            let c = i as f64 / 1000.0;
+            assert!((srgb_to_linear(linear_to_srgb(c)) - c).abs() < 1e-12);
+            assert!((linear_to_srgb(srgb_to_linear(c)) - c).abs() < 1e-12);
+        }
+        // every 8-bit code survives decoding and encoding again
+        let display = DisplayTransform::new();
+        for code in 0..=255u8 {
+            let linear = srgb_to_linear(code as f64 / 255.0);
+            assert!(display.encode(grey(linear)) == [code; 3]);
+        }
+        // the two pieces meet at the breakpoint, and mid grey is 0.735
+        assert!((linear_to_srgb(0.0031308) - 0.04045).abs() < 1e-6);
+        assert!((linear_to_srgb(0.5) - 0.7353569830524495).abs() < 1e-12);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-15 && linear_to_srgb(0.0) == 0.0);
+    }
 
-    // apply gamma correction
+    #[test]
+    fn tone_curves_compress_into_the_display_range() {
+        let names = ["clamp", "reinhard", "extended-reinhard", "aces", "agx"];
+        for name in names {
+            let tone_map = ToneMap::from_name(name).unwrap();
+            let mut previous = -1.0;
+            for i in 0..=400 {
+                let c = tone_map.apply(grey(i as f64 / 20.0));
+                assert!((0.0..=1.0).contains(&c.x()), "{} at {}", name, i);
+                assert!(c.x() >= previous, "{} is not monotonic at {}", name, i);
+                previous = c.x();
+            }
+            assert!(tone_map.apply(grey(-1.0)).x() <= 1e-4, "{}", name);
+        }
+        assert!(ToneMap::from_name("filmic").is_none());
 
-    r = linear_to_gamma(r);
-    g = linear_to_gamma(g);
-    b = linear_to_gamma(b);
+        assert!(ToneMap::Clamp.apply(Color::new(0.25, 2.0, -1.0)).e == [0.25, 1.0, 0.0]);
+        assert!(ToneMap::Reinhard.apply(grey(1.0)).x() == 0.5);
+        let extended = ToneMap::ExtendedReinhard { white_point: 4.0 };
+        assert!(extended.apply(grey(4.0)).x() == 1.0);
+        assert!((extended.apply(grey(1.0)).x() - 0.53125).abs() < 1e-12);
+        // Narkowicz's fit passes through 0 and saturates above about 10 / 0.6
+        assert!(ToneMap::AcesFilmic.apply(grey(0.0)).x() == 0.0);
+        assert!(ToneMap::AcesFilmic.apply(grey(20.0)).x() == 1.0);
+        // AgX keeps mid grey near the middle and rolls saturated colors
+        // towards white
+        let agx = ToneMap::AgX.apply(grey(0.18)).x();
+        assert!((0.1..0.3).contains(&agx));
+        let red = ToneMap::AgX.apply(Color::new(64.0, 0.0, 0.0));
+        assert!(red.y() > 0.5 && red.z() > 0.5);
 
-    let intensity = interval::Interval::new(0.000, 0.999);
+        let mut display = DisplayTransform::new();
+        display.exposure_stops = 1.0;
+        assert!(display.tone_map(grey(0.25)).x() == 0.5);
+    }
 
-    println!(
-        "{} {} {}\n",
-        (260.0 * intensity.clamps(r)) as i32,
-        (260.0 * intensity.clamps(g)) as i32,
-        (260.0 * intensity.clamps(b)) as i32,
-    );
+    #[test]
+    fn dither_stays_within_one_step() {
+        let mut display = DisplayTransform::new();
+        display.dither = true;
+        for (code, low, high) in [(0u8, 0u8, 1u8), (128, 127, 129), (255, 254, 255)] {
+            let linear = srgb_to_linear(code as f64 / 255.0);
+            let mut sum = 0.0;
+            for _ in 0..4000 {
+                let [r, g, b] = display.encode(grey(linear));
+                for value in [r, g, b] {
+                    assert!((low..=high).contains(&value));
+                    sum += value as f64;
+                }
+            }
+            // triangular noise is unbiased away from the ends of the range
+            if code == 128 {
+                assert!((sum / 12000.0 - 128.0).abs() < 0.05);
+            }
+        }
+    }
 }
@@ -0,0 +1,554 @@
+use crate::color;
+use crate::image;
+use crate::vector;
+
+use std::io;
+use std::path::Path;
+
+use vector::Vec3 as Color;
+
+// Difference metrics between a reference and a test image. MSE, RMSE, PSNR
+// and SSIM are computed on display encoded values in [0, 1]; flip is the
+// mean of the per-pixel perceptual error, 0 for identical images and at
+// most 1.
+#[derive(Clone, Copy)]
+pub struct Metrics {
+    pub mse: f64,
+    pub rmse: f64,
+    pub psnr: f64,
+    pub ssim: f64,
+    pub flip: f64,
+}
+
+impl Metrics {
+    pub fn get(&self, name: &str) -> Option<f64> {
+        match name {
+            "mse" => Some(self.mse),
+            "rmse" => Some(self.rmse),
+            "psnr" => Some(self.psnr),
+            "ssim" => Some(self.ssim),
+            "flip" => Some(self.flip),
+            _ => None,
+        }
+    }
+
+    // PSNR and SSIM grow with similarity, the other metrics with difference.
+    pub fn higher_is_better(name: &str) -> bool {
+        return name == "psnr" || name == "ssim";
+    }
+}
+
+// Loads an image as display encoded values in [0, 1]. Linear formats are
+// clipped and sRGB encoded.
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<image::Image> {
+    let path = path.as_ref();
+    let mut img = image::Image::open(path)?;
+    let linear = image::Image::is_linear_format(path);
+    for y in 0..img.height {
+        for x in 0..img.width {
+            let c = img.get(x, y);
+            let encode = |v: f64| {
+                let v = v.clamp(0.0, 1.0);
+                if linear {
+                    color::linear_to_srgb(v)
+                } else {
+                    v
+                }
+            };
+            img.set(
+                x,
+                y,
+                Color::new(encode(c.x()), encode(c.y()), encode(c.z())),
+            );
+        }
+    }
+    return Ok(img);
+}
+
+// Compares two display encoded images of the same size, returning the
+// metrics and the per-pixel perceptual error.
+pub fn compare(reference: &image::Image, test: &image::Image) -> (Metrics, Vec<f64>) {
+    assert!(
+        reference.width == test.width && reference.height == test.height,
+        "compared images differ in size"
+    );
+    let count = (reference.width * reference.height) as f64;
+
+    let mut squared_error = 0.0;
+    for y in 0..reference.height {
+        for x in 0..reference.width {
+            squared_error += (reference.get(x, y) - test.get(x, y)).length_squared() / 3.0;
+        }
+    }
+    let mse = squared_error / count;
+    let psnr = if mse > 0.0 {
+        10.0 * (1.0 / mse).log10()
+    } else {
+        f64::INFINITY
+    };
+
+    let error_map = flip_error(reference, test);
+    let flip = error_map.iter().sum::<f64>() / count;
+
+    let metrics = Metrics {
+        mse,
+        rmse: mse.sqrt(),
+        psnr,
+        ssim: ssim(reference, test),
+        flip,
+    };
+    return (metrics, error_map);
+}
+
+// Renders an error map in [0, 1] with the magma colormap, from black for no
+// error to pale yellow for the largest.
+pub fn false_color(error_map: &[f64], width: usize, height: usize) -> image::Image {
+    let mut img = image::Image::new(width, height);
+    for (i, error) in error_map.iter().enumerate() {
+        img.set(i % width, i / width, magma(*error));
+    }
+    return img;
+}
+
+// Linear color of t in [0, 1] on an approximation of the magma colormap.
+pub fn magma(t: f64) -> Color {
+    const STOPS: [[f64; 3]; 6] = [
+        [0.001, 0.000, 0.014],
+        [0.232, 0.060, 0.438],
+        [0.550, 0.161, 0.506],
+        [0.868, 0.288, 0.409],
+        [0.994, 0.624, 0.427],
+        [0.987, 0.991, 0.750],
+    ];
+    let position = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let k = (position as usize).min(STOPS.len() - 2);
+    let f = position - k as f64;
+    let (a, b) = (STOPS[k], STOPS[k + 1]);
+    // the colormap is display encoded, images hold linear values
+    let channel = |c: usize| color::srgb_to_linear(a[c] * (1.0 - f) + b[c] * f);
+    return Color::new(channel(0), channel(1), channel(2));
+}
+
+fn luma(c: Color) -> f64 {
+    return 0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z();
+}
+
+// Mean structural similarity of the luma channels, using the usual 11x11
+// Gaussian window with a standard deviation of 1.5 pixels.
+fn ssim(reference: &image::Image, test: &image::Image) -> f64 {
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+    let (width, height) = (reference.width, reference.height);
+
+    let channel = |img: &image::Image| -> Vec<f64> {
+        let mut values = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                values.push(luma(img.get(x, y)));
+            }
+        }
+        values
+    };
+    let a = channel(reference);
+    let b = channel(test);
+    let product =
+        |p: &[f64], q: &[f64]| -> Vec<f64> { p.iter().zip(q).map(|(u, v)| u * v).collect() };
+
+    let kernel = gaussian_kernel(1.5, 5);
+    let mu_a = blur(&a, width, height, &kernel);
+    let mu_b = blur(&b, width, height, &kernel);
+    let aa = blur(&product(&a, &a), width, height, &kernel);
+    let bb = blur(&product(&b, &b), width, height, &kernel);
+    let ab = blur(&product(&a, &b), width, height, &kernel);
+
+    let mut sum = 0.0;
+    for i in 0..a.len() {
+        let var_a = aa[i] - mu_a[i] * mu_a[i];
+        let var_b = bb[i] - mu_b[i] * mu_b[i];
+        let cov = ab[i] - mu_a[i] * mu_b[i];
+        sum += ((2.0 * mu_a[i] * mu_b[i] + C1) * (2.0 * cov + C2))
+            / ((mu_a[i] * mu_a[i] + mu_b[i] * mu_b[i] + C1) * (var_a + var_b + C2));
+    }
+    return sum / a.len() as f64;
+}
+
+// A simplified FLIP (Andersson et al. 2020): both images are low-pass
+// filtered in an opponent color space to mimic the eye's contrast
+// sensitivity, compared with the HyAB distance in CIELAB, and the color
+// error is amplified where the images' luminance edges differ.
+fn flip_error(reference: &image::Image, test: &image::Image) -> Vec<f64> {
+    const QC: f64 = 0.7;
+    const PC: f64 = 0.4;
+    const PT: f64 = 0.95;
+    const QF: f64 = 0.5;
+    let (width, height) = (reference.width, reference.height);
+
+    let lab_ref = filtered_lab(reference);
+    let lab_test = filtered_lab(test);
+
+    // the largest color difference the display can show, green against blue
+    let max_error = hyab(
+        lab(Color::new(0.0, 1.0, 0.0)),
+        lab(Color::new(0.0, 0.0, 1.0)),
+    )
+    .powf(QC);
+
+    let edges_ref = edge_magnitude(reference);
+    let edges_test = edge_magnitude(test);
+
+    let mut errors = Vec::with_capacity(width * height);
+    for i in 0..width * height {
+        let error = hyab(lab_ref[i], lab_test[i]).powf(QC);
+        // compress large differences, as FLIP does
+        let color_error = if error < PC * max_error {
+            PT / (PC * max_error) * error
+        } else {
+            PT + (error - PC * max_error) / (max_error - PC * max_error) * (1.0 - PT)
+        };
+        let feature_error = ((edges_ref[i] - edges_test[i]).abs() / 2.0f64.sqrt())
+            .powf(QF)
+            .clamp(0.0, 1.0);
+        errors.push(color_error.clamp(0.0, 1.0).powf(1.0 - feature_error));
+    }
+    return errors;
+}
+
+const WHITE: [f64; 3] = [0.950489, 1.0, 1.088840];
+
+fn linear_srgb_to_xyz(c: Color) -> Color {
+    return Color::new(
+        0.4124564 * c.x() + 0.3575761 * c.y() + 0.1804375 * c.z(),
+        0.2126729 * c.x() + 0.7151522 * c.y() + 0.0721750 * c.z(),
+        0.0193339 * c.x() + 0.1191920 * c.y() + 0.9503041 * c.z(),
+    );
+}
+
+fn xyz_to_linear_srgb(c: Color) -> Color {
+    return Color::new(
+        3.2404542 * c.x() - 1.5371385 * c.y() - 0.4985314 * c.z(),
+        -0.9692660 * c.x() + 1.8760108 * c.y() + 0.0415560 * c.z(),
+        0.0556434 * c.x() - 0.2040259 * c.y() + 1.0572252 * c.z(),
+    );
+}
+
+// CIELAB of a linear sRGB color.
+fn lab(c: Color) -> Color {
+    let xyz = linear_srgb_to_xyz(c);
+    let f = |t: f64| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let fx = f(xyz.x() / WHITE[0]);
+    let fy = f(xyz.y() / WHITE[1]);
+    let fz = f(xyz.z() / WHITE[2]);
+    return Color::new(116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz));
+}
+
+fn hyab(a: Color, b: Color) -> f64 {
+    let d = a - b;
+    return d.x().abs() + (d.y() * d.y() + d.z() * d.z()).sqrt();
+}
+
+// Linearizes the image, blurs it in YCxCz and returns CIELAB per pixel.
+fn filtered_lab(img: &image::Image) -> Vec<Color> {
+    let (width, height) = (img.width, img.height);
+    let mut channels = [
+        Vec::with_capacity(width * height),
+        Vec::with_capacity(width * height),
+        Vec::with_capacity(width * height),
+    ];
+    for y in 0..height {
+        for x in 0..width {
+            let c = img.get(x, y);
+            let linear = Color::new(
+                color::srgb_to_linear(c.x()),
+                color::srgb_to_linear(c.y()),
+                color::srgb_to_linear(c.z()),
+            );
+            let xyz = linear_srgb_to_xyz(linear);
+            let yn = xyz.y() / WHITE[1];
+            channels[0].push(116.0 * yn - 16.0);
+            channels[1].push(500.0 * (xyz.x() / WHITE[0] - yn));
+            channels[2].push(200.0 * (yn - xyz.z() / WHITE[2]));
+        }
+    }
+
+    // roughly the contrast sensitivity at 67 pixels per degree, a 0.7 m
+    // viewing distance from a 24 inch 4K monitor
+    let kernel = gaussian_kernel(0.75, 3);
+    let channels = channels.map(|channel| blur(&channel, width, height, &kernel));
+
+    let mut labs = Vec::with_capacity(width * height);
+    for ((y, cx), cz) in channels[0].iter().zip(&channels[1]).zip(&channels[2]) {
+        let yn = (y + 16.0) / 116.0;
+        let xyz = Color::new(
+            (cx / 500.0 + yn) * WHITE[0],
+            yn * WHITE[1],
+            (yn - cz / 200.0) * WHITE[2],
+        );
+        let rgb = xyz_to_linear_srgb(xyz);
+        let clamped = Color::new(
+            rgb.x().clamp(0.0, 1.0),
+            rgb.y().clamp(0.0, 1.0),
+            rgb.z().clamp(0.0, 1.0),
+        );
+        labs.push(lab(clamped));
+    }
+    return labs;
+}
+
+// Magnitude of the luminance gradient, from first derivatives of a Gaussian
+// normalized so that a full black to white step gives 1.
+fn edge_magnitude(img: &image::Image) -> Vec<f64> {
+    let (width, height) = (img.width, img.height);
+    let sigma = 1.0;
+    let radius = 3;
+
+    let mut derivative = Vec::new();
+    for i in -radius..=radius {
+        let x = i as f64;
+        derivative.push(-x * (-x * x / (2.0 * sigma * sigma)).exp());
+    }
+    let positive: f64 = derivative.iter().filter(|w| **w > 0.0).sum();
+    let derivative: Vec<f64> = derivative.iter().map(|w| w / positive).collect();
+    let smoothing = gaussian_kernel(sigma, radius as usize);
+
+    let mut luminance = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let c = img.get(x, y);
+            let linear = Color::new(
+                color::srgb_to_linear(c.x()),
+                color::srgb_to_linear(c.y()),
+                color::srgb_to_linear(c.z()),
+            );
+            luminance.push(lab(linear).x() / 100.0);
+        }
+    }
+
+    let dx = convolve(
+        &convolve(&luminance, width, height, &derivative, true),
+        width,
+        height,
+        &smoothing,
+        false,
+    );
+    let dy = convolve(
+        &convolve(&luminance, width, height, &smoothing, true),
+        width,
+        height,
+        &derivative,
+        false,
+    );
+    return dx.iter().zip(&dy).map(|(x, y)| x.hypot(*y)).collect();
+}
+
+fn gaussian_kernel(sigma: f64, radius: usize) -> Vec<f64> {
+    let radius = radius as i64;
+    let weights: Vec<f64> = (-radius..=radius)
+        .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f64 = weights.iter().sum();
+    return weights.iter().map(|w| w / total).collect();
+}
+
+fn blur(values: &[f64], width: usize, height: usize, kernel: &[f64]) -> Vec<f64> {
+    let horizontal = convolve(values, width, height, kernel, true);
+    return convolve(&horizontal, width, height, kernel, false);
+}
+
+// One dimensional convolution along rows or columns, clamping at the border.
+fn convolve(
+    values: &[f64],
+    width: usize,
+    height: usize,
+    kernel: &[f64],
+    horizontal: bool,
+) -> Vec<f64> {
+    let radius = (kernel.len() / 2) as i64;
+    let mut out = vec![0.0; values.len()];
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let mut sum = 0.0;
+            for (k, w) in kernel.iter().enumerate() {
+                let offset = k as i64 - radius;
+                let (sx, sy) = if horizontal {
+                    ((x + offset).clamp(0, width as i64 - 1), y)
+                } else {
+                    (x, (y + offset).clamp(0, height as i64 - 1))
+                };
+                sum += w * values[(sy * width as i64 + sx) as usize];
+            }
+            out[(y * width as i64 + x) as usize] = sum;
+        }
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    // A 5x3 gradient of display encoded values that are exact 8-bit codes.
+    fn gradient() -> image::Image {
+        let mut img = image::Image::new(5, 3);
+        for y in 0..3 {
+            for x in 0..5 {
+                let code = |k: usize| (k * 37 % 256) as f64 / 255.0;
+                img.set(
+                    x,
+                    y,
+                    Color::new(code(x + 5 * y), code(x * y + 100), code(200 - x - y)),
+                );
+            }
+        }
+        return img;
+    }
+
+    fn to_linear(img: &image::Image) -> image::Image {
+        let mut linear = image::Image::new(img.width, img.height);
+        for y in 0..img.height {
+            for x in 0..img.width {
+                let c = img.get(x, y);
+                let c = Color::new(
+                    color::srgb_to_linear(c.x()),
+                    color::srgb_to_linear(c.y()),
+                    color::srgb_to_linear(c.z()),
+                );
+                linear.set(x, y, c);
+            }
+        }
+        return linear;
+    }
+
+    fn assert_same(a: &image::Image, b: &image::Image, tolerance: f64) {
+        assert!(a.width == b.width && a.height == b.height);
+        for y in 0..a.height {
+            for x in 0..a.width {
+                let error = (a.get(x, y) - b.get(x, y)).length();
+                assert!(error <= tolerance, "pixel {},{} is off by {}", x, y, error);
+            }
+        }
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        return std::env::temp_dir().join(format!("raytracer_compare_{}", name));
+    }
+
+    #[test]
+    fn readers_round_trip_every_format() {
+        let expected = gradient();
+        let display = color::DisplayTransform::new();
+        for name in ["gradient.png", "gradient.ppm", "gradient.pfm"] {
+            let path = temp_path(name);
+            to_linear(&expected).save(&path, &display).unwrap();
+            // PFM keeps f32 linear values, the others 8-bit codes
+            assert_same(&load(&path).unwrap(), &expected, 1e-6);
+            fs::remove_file(&path).unwrap();
+        }
+
+        // binary graymap: 2x1 with maxval 15
+        let path = temp_path("gray.pgm");
+        fs::write(&path, b"P5\n# comment\n2 1\n15\n\x00\x0f").unwrap();
+        let img = load(&path).unwrap();
+        assert!(img.get(0, 0).e == [0.0; 3] && img.get(1, 0).e == [1.0; 3]);
+        fs::remove_file(&path).unwrap();
+
+        // Radiance HDR, 8 pixels wide so the second scanline can be run
+        // length encoded; both decode to the same linear values
+        let rgbe: Vec<[u8; 4]> = (0..8)
+            .map(|x| [127 + x as u8, 63, 31, if x < 4 { 129 } else { 128 }])
+            .collect();
+        let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 8\n".to_vec();
+        data.extend(rgbe.iter().flatten());
+        data.extend([2, 2, 0, 8]);
+        // red as literals, green and blue as one run, exponent as two runs
+        data.push(8);
+        data.extend(rgbe.iter().map(|p| p[0]));
+        data.extend([128 + 8, 63, 128 + 8, 31, 128 + 4, 129, 128 + 4, 128]);
+        let path = temp_path("ramp.hdr");
+        fs::write(&path, &data).unwrap();
+        let img = image::Image::open(&path).unwrap();
+        assert!(img.width == 8 && img.height == 2);
+        for x in 0..8 {
+            let scale = if x < 4 { 1.0 / 128.0 } else { 1.0 / 256.0 };
+            let expected = Color::new(127.5 + x as f64, 63.5, 31.5) * scale;
+            assert!((img.get(x, 0) - expected).length() < 1e-12);
+            assert!((img.get(x, 1) - expected).length() < 1e-12);
+        }
+        // load clips and encodes the linear values for display
+        let loaded = load(&path).unwrap();
+        assert!((loaded.get(1, 0).x() - 1.0).abs() < 1e-15);
+        assert!((loaded.get(7, 1).z() - color::linear_to_srgb(31.5 / 256.0)).abs() < 1e-12);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn readers_reject_damaged_headers() {
+        let cases: [(&str, &[u8]); 10] = [
+            // sizes whose pixel count overflows or needs far more data
+            ("huge.ppm", b"P6\n4294967296 4294967296\n255\n\x00"),
+            ("huge.pgm", b"P2\n100000 100000\n255\n0 0 0"),
+            ("huge.pfm", b"PF\n4294967296 4294967296\n-1.0\n\x00"),
+            ("huge.hdr", b"#?RADIANCE\n\n-Y 200000 +X 200000\n\x02\x02"),
+            ("negative.ppm", b"P6\n-2 2\n255\n\x00\x00\x00"),
+            // headers that end early
+            ("short.ppm", b"P6\n4 4\n"),
+            ("short.pfm", b"PF\n4"),
+            ("short.hdr", b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n"),
+            // pixel data shorter than the header promises
+            ("truncated.pfm", b"PF\n2 2\n-1.0\n\x00\x00\x00\x00"),
+            (
+                "truncated.hdr",
+                b"#?RADIANCE\n\n-Y 2 +X 2\n\x80\x80\x80\x81",
+            ),
+        ];
+        for (name, data) in cases {
+            let path = temp_path(name);
+            fs::write(&path, data).unwrap();
+            let result = load(&path);
+            fs::remove_file(&path).unwrap();
+            let error = result.err().unwrap_or_else(|| panic!("{} loaded", name));
+            assert!(
+                error.kind() == io::ErrorKind::InvalidData,
+                "{}: {}",
+                name,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn metrics_of_known_differences() {
+        let reference = gradient();
+        let (metrics, error_map) = compare(&reference, &reference);
+        assert!(metrics.mse == 0.0 && metrics.rmse == 0.0 && metrics.psnr == f64::INFINITY);
+        assert!((metrics.ssim - 1.0).abs() < 1e-12);
+        assert!(metrics.flip == 0.0 && error_map.iter().all(|&e| e == 0.0));
+
+        // a constant offset of 0.1 is an RMSE of 0.1 and a PSNR of 20 dB
+        let mut reference = image::Image::new(16, 16);
+        let mut test = image::Image::new(16, 16);
+        for y in 0..16 {
+            for x in 0..16 {
+                let v = 0.2 + 0.5 * ((x * 7 + y * 3) % 16) as f64 / 15.0;
+                reference.set(x, y, Color::new(v, v, v));
+                test.set(x, y, Color::new(v + 0.1, v + 0.1, v + 0.1));
+            }
+        }
+        let (metrics, _) = compare(&reference, &test);
+        assert!((metrics.mse - 0.01).abs() < 1e-12);
+        assert!((metrics.rmse - 0.1).abs() < 1e-12);
+        assert!((metrics.psnr - 20.0).abs() < 1e-9);
+        assert!(metrics.ssim < 1.0 && metrics.flip > 0.0 && metrics.flip <= 1.0);
+        for name in ["mse", "rmse", "psnr", "ssim", "flip"] {
+            assert!(metrics.get(name).is_some());
+        }
+        assert!(Metrics::higher_is_better("psnr") && !Metrics::higher_is_better("flip"));
+    }
+}
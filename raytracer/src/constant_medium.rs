@@ -0,0 +1,131 @@
+use crate::hittable;
+use crate::interval;
+use crate::material;
+use crate::ray;
+use crate::utility;
+use crate::vector;
+
+use std::boxed::Box;
+
+// A volume of uniform density filling a closed boundary. Rays travel an
+// exponentially distributed free-flight distance before scattering off the
+// phase function, which should be an isotropic material.
+pub struct ConstantMedium {
+    boundary: Box<dyn hittable::Hittable>,
+    neg_inv_density: f64,
+    phase_function: material::MaterialId,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Box<dyn hittable::Hittable>,
+        density: f64,
+        phase_function: material::MaterialId,
+    ) -> Self {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+impl hittable::Hittable for ConstantMedium {
+    fn hit(
+        &mut self,
+        r: &ray::Ray,
+        ray_t: interval::Interval,
+        rec: &mut hittable::HitRecord,
+    ) -> bool {
+        let mut rec1 = hittable::HitRecord::default();
+        let mut rec2 = hittable::HitRecord::default();
+
+        if !self.boundary.hit(r, interval::UNIVERSE, &mut rec1) {
+            return false;
+        }
+        if !self.boundary.hit(
+            r,
+            interval::Interval::new(rec1.t + 0.0001, f64::INFINITY),
+            &mut rec2,
+        ) {
+            return false;
+        }
+
+        rec1.t = rec1.t.max(ray_t.min);
+        rec2.t = rec2.t.min(ray_t.max);
+        if rec1.t >= rec2.t {
+            return false;
+        }
+        rec1.t = rec1.t.max(0.0);
+
+        let ray_length = r.direction().length();
+        let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
+        let hit_distance = self.neg_inv_density * utility::random_double(0.0, 1.0).ln();
+        if hit_distance > distance_inside_boundary {
+            return false;
+        }
+
+        rec.t = rec1.t + hit_distance / ray_length;
+        rec.p = r.at(rec.t);
+
+        // the normal and facing are arbitrary inside a volume
+        rec.normal = vector::Vec3::new(1.0, 0.0, 0.0);
+        rec.geometric_normal = rec.normal;
+        (rec.u, rec.v) = (0.0, 0.0);
+        rec.front_face = true;
+        rec.mat = self.phase_function;
+
+        return true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::hittable::Hittable;
+
+    use vector::Vec3 as Point3;
+
+    // Fraction of rays that cross the medium without scattering, which should
+    // be exp(-density * distance) for the distance the ray travels inside.
+    fn transmittance(medium: &mut ConstantMedium, r: &ray::Ray, inside: interval::Interval) -> f64 {
+        const RAYS: usize = 20000;
+        let mut rec = hittable::HitRecord::default();
+        let mut crossed = 0;
+        for _ in 0..RAYS {
+            if medium.hit(r, interval::Interval::new(0.001, f64::INFINITY), &mut rec) {
+                assert!(inside.contains(rec.t), "scattered at t = {}", rec.t);
+            } else {
+                crossed += 1;
+            }
+        }
+        return crossed as f64 / RAYS as f64;
+    }
+
+    #[test]
+    fn free_flights_follow_beer_lambert() {
+        utility::seed(7);
+        let density = 0.5;
+        let boundary = Box::new(hittable::Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            material::MaterialId::default(),
+        ));
+        let mut medium = ConstantMedium::new(boundary, density, material::MaterialId::default());
+
+        // through the centre, 2 units inside; the direction's length must not
+        // change the distance
+        let r = ray::Ray::new(
+            Point3::new(0.0, 0.0, -3.0),
+            vector::Vec3::new(0.0, 0.0, 2.0),
+        );
+        let t = transmittance(&mut medium, &r, interval::Interval::new(1.0, 2.0));
+        assert!((t - (-density * 2.0).exp()).abs() < 0.02, "{}", t);
+
+        // from the centre, 1 unit inside
+        let r = ray::Ray::new(Point3::new(0.0, 0.0, 0.0), vector::Vec3::new(1.0, 0.0, 0.0));
+        let t = transmittance(&mut medium, &r, interval::Interval::new(0.0, 1.0));
+        assert!((t - (-density).exp()).abs() < 0.02, "{}", t);
+    }
+}
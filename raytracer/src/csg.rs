@@ -0,0 +1,214 @@
+use crate::hittable;
+use crate::interval;
+use crate::ray;
+
+use std::boxed::Box;
+use std::vec::Vec;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOp {
+    fn inside(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOp::Union => in_left || in_right,
+            CsgOp::Intersection => in_left && in_right,
+            CsgOp::Difference => in_left && !in_right,
+        }
+    }
+}
+
+// Combines two closed hittables into a solid. Both operands must report
+// matching entry/exit pairs from hit_all for the result to be meaningful.
+pub struct Csg {
+    pub op: CsgOp,
+    left: Box<dyn hittable::Hittable>,
+    right: Box<dyn hittable::Hittable>,
+}
+
+impl Csg {
+    pub fn new(
+        op: CsgOp,
+        left: Box<dyn hittable::Hittable>,
+        right: Box<dyn hittable::Hittable>,
+    ) -> Self {
+        Csg { op, left, right }
+    }
+
+    pub fn union(left: Box<dyn hittable::Hittable>, right: Box<dyn hittable::Hittable>) -> Self {
+        Self::new(CsgOp::Union, left, right)
+    }
+
+    pub fn intersection(
+        left: Box<dyn hittable::Hittable>,
+        right: Box<dyn hittable::Hittable>,
+    ) -> Self {
+        Self::new(CsgOp::Intersection, left, right)
+    }
+
+    pub fn difference(
+        left: Box<dyn hittable::Hittable>,
+        right: Box<dyn hittable::Hittable>,
+    ) -> Self {
+        Self::new(CsgOp::Difference, left, right)
+    }
+
+    // Returns the boundary crossings of the combined solid along the whole
+    // ray line. Tracking starts outside both operands at t = -infinity.
+    fn boundaries(&mut self, r: &ray::Ray) -> Vec<hittable::HitRecord> {
+        let mut left_hits = Vec::new();
+        let mut right_hits = Vec::new();
+        self.left.hit_all(r, interval::UNIVERSE, &mut left_hits);
+        self.right.hit_all(r, interval::UNIVERSE, &mut right_hits);
+
+        let mut result = Vec::new();
+        let mut in_left = false;
+        let mut in_right = false;
+        let (mut i, mut j) = (0, 0);
+
+        while i < left_hits.len() || j < right_hits.len() {
+            let take_left =
+                j >= right_hits.len() || (i < left_hits.len() && left_hits[i].t <= right_hits[j].t);
+            let was_inside = self.op.inside(in_left, in_right);
+
+            let mut rec = if take_left {
+                let rec = left_hits[i];
+                in_left = rec.front_face;
+                i += 1;
+                rec
+            } else {
+                let rec = right_hits[j];
+                in_right = rec.front_face;
+                j += 1;
+                rec
+            };
+
+            let is_inside = self.op.inside(in_left, in_right);
+            if was_inside != is_inside {
+                // A subtracted surface is turned inside out. The stored normal
+                // already faces the ray, so only the entry/exit flag changes.
+                rec.front_face = is_inside;
+                result.push(rec);
+            }
+        }
+
+        return result;
+    }
+}
+
+impl hittable::Hittable for Csg {
+    fn hit(
+        &mut self,
+        r: &ray::Ray,
+        ray_t: interval::Interval,
+        rec: &mut hittable::HitRecord,
+    ) -> bool {
+        for boundary in self.boundaries(r) {
+            if ray_t.surrounds(boundary.t) {
+                *rec = boundary;
+                return true;
+            }
+        }
+        return false;
+    }
+
+    fn hit_all(
+        &mut self,
+        r: &ray::Ray,
+        ray_t: interval::Interval,
+        hits: &mut Vec<hittable::HitRecord>,
+    ) {
+        for boundary in self.boundaries(r) {
+            if ray_t.surrounds(boundary.t) {
+                hits.push(boundary);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::hittable::Hittable;
+    use crate::material;
+    use crate::vector;
+
+    use vector::Vec3 as Point3;
+
+    // Unit spheres at x = 0 and x = 1, crossed along the x axis from x = -5,
+    // so the left one spans t in [4, 6] and the right one t in [5, 7].
+    fn overlapping(op: CsgOp) -> Csg {
+        let mat = material::MaterialId::default();
+        let left = hittable::Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, mat);
+        let right = hittable::Sphere::new(Point3::new(1.0, 0.0, 0.0), 1.0, mat);
+        return Csg::new(op, Box::new(left), Box::new(right));
+    }
+
+    fn x_axis() -> ray::Ray {
+        return ray::Ray::new(
+            Point3::new(-5.0, 0.0, 0.0),
+            vector::Vec3::new(1.0, 0.0, 0.0),
+        );
+    }
+
+    fn crossings(op: CsgOp) -> Vec<(f64, bool)> {
+        let boundaries = overlapping(op).boundaries(&x_axis());
+        return boundaries
+            .iter()
+            .map(|rec| (rec.t, rec.front_face))
+            .collect();
+    }
+
+    fn assert_crossings(op: CsgOp, expected: &[(f64, bool)]) {
+        let actual = crossings(op);
+        assert!(actual.len() == expected.len(), "{:?}", actual);
+        for ((t, front_face), (expected_t, expected_front_face)) in actual.iter().zip(expected) {
+            assert!((t - expected_t).abs() < 1e-9, "{:?}", actual);
+            assert!(front_face == expected_front_face, "{:?}", actual);
+        }
+    }
+
+    #[test]
+    fn union_spans_both_spheres() {
+        assert_crossings(CsgOp::Union, &[(4.0, true), (7.0, false)]);
+    }
+
+    #[test]
+    fn intersection_spans_the_overlap() {
+        assert_crossings(CsgOp::Intersection, &[(5.0, true), (6.0, false)]);
+    }
+
+    #[test]
+    fn difference_exits_through_the_subtracted_surface() {
+        // the right sphere is entered at t = 5, which leaves the difference
+        assert_crossings(CsgOp::Difference, &[(4.0, true), (5.0, false)]);
+
+        let mut solid = overlapping(CsgOp::Difference);
+        let mut rec = hittable::HitRecord::default();
+        assert!(solid.hit(
+            &x_axis(),
+            interval::Interval::new(4.5, f64::INFINITY),
+            &mut rec
+        ));
+        assert!((rec.t - 5.0).abs() < 1e-9 && !rec.front_face);
+        // the normal still faces the ray
+        assert!((rec.normal - vector::Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-9);
+
+        assert!(solid.hit(
+            &x_axis(),
+            interval::Interval::new(0.0, f64::INFINITY),
+            &mut rec
+        ));
+        assert!((rec.t - 4.0).abs() < 1e-9 && rec.front_face);
+        assert!(!solid.hit(
+            &x_axis(),
+            interval::Interval::new(5.5, f64::INFINITY),
+            &mut rec
+        ));
+    }
+}
@@ -0,0 +1,283 @@
+use crate::compare;
+use crate::hittable;
+use crate::image;
+use crate::vector;
+
+use vector::Vec3 as Color;
+
+// Alternative shadings of the camera rays for finding out why an image looks
+// wrong. Surface views color the first hit and leave misses black. Scalar
+// views put a raw value in every channel of each sample and finish() maps the
+// filtered image onto a color scale spanning the values found in it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DebugView {
+    Surface(SurfaceView),
+    Scalar(ScalarView),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SurfaceView {
+    // the normal the materials shade with, facing the ray
+    ShadingNormal,
+    // the outward normal of the surface
+    GeometricNormal,
+    // blue where rays hit the outside of a surface, red for the inside
+    Facing,
+    Uv,
+    MaterialId,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ScalarView {
+    // inverse distance along the view direction, brightest nearest
+    Depth,
+    // heat map of the number of times the path scattered
+    Bounces,
+    // heat map of the primitive intersection tests along the whole path
+    IntersectionTests,
+}
+
+pub const NAMES: [&str; 8] = [
+    "shading-normal",
+    "geometric-normal",
+    "facing",
+    "uv",
+    "depth",
+    "material-id",
+    "bounces",
+    "intersections",
+];
+
+impl DebugView {
+    pub fn from_name(name: &str) -> Option<DebugView> {
+        let view = match name {
+            "shading-normal" => DebugView::Surface(SurfaceView::ShadingNormal),
+            "geometric-normal" => DebugView::Surface(SurfaceView::GeometricNormal),
+            "facing" => DebugView::Surface(SurfaceView::Facing),
+            "uv" => DebugView::Surface(SurfaceView::Uv),
+            "depth" => DebugView::Scalar(ScalarView::Depth),
+            "material-id" => DebugView::Surface(SurfaceView::MaterialId),
+            "bounces" => DebugView::Scalar(ScalarView::Bounces),
+            "intersections" => DebugView::Scalar(ScalarView::IntersectionTests),
+            _ => return None,
+        };
+        return Some(view);
+    }
+
+    // Maps the raw values of a scalar view onto its color scale.
+    pub fn finish(&self, img: image::Image) -> image::Image {
+        let DebugView::Scalar(view) = self else {
+            return img;
+        };
+        let mut largest = 0.0f64;
+        for y in 0..img.height {
+            for x in 0..img.width {
+                largest = largest.max(img.get(x, y).x());
+            }
+        }
+        let scale = if largest > 0.0 { 1.0 / largest } else { 0.0 };
+
+        let mut result = image::Image::new(img.width, img.height);
+        for y in 0..img.height {
+            for x in 0..img.width {
+                let value = img.get(x, y).x() * scale;
+                let mapped = match view {
+                    ScalarView::Depth => Color::new(value, value, value),
+                    ScalarView::Bounces | ScalarView::IntersectionTests => compare::magma(value),
+                };
+                result.set(x, y, mapped);
+            }
+        }
+        return result;
+    }
+}
+
+impl SurfaceView {
+    // Color of a first hit.
+    pub fn color(&self, rec: &hittable::HitRecord) -> Color {
+        match self {
+            SurfaceView::ShadingNormal => return (rec.normal + Color::new(1.0, 1.0, 1.0)) * 0.5,
+            SurfaceView::GeometricNormal => {
+                return (rec.geometric_normal + Color::new(1.0, 1.0, 1.0)) * 0.5
+            }
+            SurfaceView::Facing => {
+                if rec.front_face {
+                    return Color::new(0.1, 0.3, 1.0);
+                }
+                return Color::new(1.0, 0.1, 0.1);
+            }
+            SurfaceView::Uv => return Color::new(rec.u, rec.v, 0.0),
+            SurfaceView::MaterialId => return id_color(rec.mat.index()),
+        }
+    }
+}
+
+// A distinct, saturated color for every index, stepping the hue by the
+// golden angle so neighbouring ids differ clearly.
+fn id_color(index: usize) -> Color {
+    let hue = (index as f64 * 0.618033988749895).fract() * 6.0;
+    let f = hue.fract();
+    let (high, low) = (0.9, 0.15);
+    let rising = low + (high - low) * f;
+    let falling = high - (high - low) * f;
+    return match hue as usize {
+        0 => Color::new(high, rising, low),
+        1 => Color::new(falling, high, low),
+        2 => Color::new(low, high, rising),
+        3 => Color::new(low, falling, high),
+        4 => Color::new(rising, low, high),
+        _ => Color::new(high, low, falling),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::hittable::Hittable;
+    use crate::interval;
+    use crate::material;
+    use crate::ray;
+
+    use vector::Vec3 as Point3;
+
+    // Where a ray from origin along direction hits the unit sphere.
+    fn hit_unit_sphere(origin: Point3, direction: vector::Vec3) -> hittable::HitRecord {
+        let mut sphere = hittable::Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            material::MaterialId::default(),
+        );
+        let mut rec = hittable::HitRecord::default();
+        let r = ray::Ray::new(origin, direction);
+        assert!(sphere.hit(&r, interval::Interval::new(0.001, f64::INFINITY), &mut rec));
+        return rec;
+    }
+
+    fn assert_color(color: Color, expected: Color) {
+        assert!(
+            (color - expected).length() < 1e-9,
+            "({}, {}, {}) is not ({}, {}, {})",
+            color.x(),
+            color.y(),
+            color.z(),
+            expected.x(),
+            expected.y(),
+            expected.z()
+        );
+    }
+
+    #[test]
+    fn every_name_is_a_view() {
+        for name in NAMES {
+            assert!(DebugView::from_name(name).is_some(), "{}", name);
+        }
+        assert!(DebugView::from_name("albedo").is_none());
+    }
+
+    #[test]
+    fn normals_map_to_colors() {
+        // the front of the sphere faces +z, which maps to (0.5, 0.5, 1)
+        let outside = hit_unit_sphere(
+            Point3::new(0.0, 0.0, 5.0),
+            vector::Vec3::new(0.0, 0.0, -1.0),
+        );
+        let front = Color::new(0.5, 0.5, 1.0);
+        assert_color(SurfaceView::ShadingNormal.color(&outside), front);
+        assert_color(SurfaceView::GeometricNormal.color(&outside), front);
+
+        // from inside, the shading normal turns towards the ray and the
+        // geometric normal still points out
+        let inside = hit_unit_sphere(Point3::new(0.0, 0.0, 0.0), vector::Vec3::new(0.0, 0.0, 1.0));
+        assert_color(
+            SurfaceView::ShadingNormal.color(&inside),
+            Color::new(0.5, 0.5, 0.0),
+        );
+        assert_color(SurfaceView::GeometricNormal.color(&inside), front);
+
+        let top = hit_unit_sphere(
+            Point3::new(0.0, 5.0, 0.0),
+            vector::Vec3::new(0.0, -1.0, 0.0),
+        );
+        assert_color(
+            SurfaceView::ShadingNormal.color(&top),
+            Color::new(0.5, 1.0, 0.5),
+        );
+    }
+
+    #[test]
+    fn facing_tells_the_sides_apart() {
+        let outside = hit_unit_sphere(
+            Point3::new(0.0, 0.0, 5.0),
+            vector::Vec3::new(0.0, 0.0, -1.0),
+        );
+        assert_color(
+            SurfaceView::Facing.color(&outside),
+            Color::new(0.1, 0.3, 1.0),
+        );
+        let inside = hit_unit_sphere(Point3::new(0.0, 0.0, 0.0), vector::Vec3::new(0.0, 0.0, 1.0));
+        assert_color(
+            SurfaceView::Facing.color(&inside),
+            Color::new(1.0, 0.1, 0.1),
+        );
+    }
+
+    #[test]
+    fn uv_shows_the_surface_coordinates() {
+        // +z lies a quarter of the way around from -x, on the equator
+        let front = hit_unit_sphere(
+            Point3::new(0.0, 0.0, 5.0),
+            vector::Vec3::new(0.0, 0.0, -1.0),
+        );
+        assert_color(SurfaceView::Uv.color(&front), Color::new(0.25, 0.5, 0.0));
+        let top = hit_unit_sphere(
+            Point3::new(0.0, 5.0, 0.0),
+            vector::Vec3::new(0.0, -1.0, 0.0),
+        );
+        assert!((SurfaceView::Uv.color(&top).y() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn material_ids_get_distinct_colors() {
+        let mut materials = material::MaterialList::new();
+        let mut rec = hittable::HitRecord::default();
+        let mut colors: Vec<Color> = Vec::new();
+        for _ in 0..8 {
+            rec.mat = materials.add(material::Material::Lambertian(material::Lambertian::new(
+                Color::new(0.5, 0.5, 0.5),
+            )));
+            let color = SurfaceView::MaterialId.color(&rec);
+            for other in &colors {
+                assert!((color - *other).length() > 0.1);
+            }
+            colors.push(color);
+        }
+    }
+
+    #[test]
+    fn scalar_views_span_their_range() {
+        let mut raw = image::Image::new(3, 1);
+        raw.set(1, 0, Color::new(2.0, 2.0, 2.0));
+        raw.set(2, 0, Color::new(4.0, 4.0, 4.0));
+
+        let depth = DebugView::Scalar(ScalarView::Depth).finish(raw.clone());
+        assert_color(depth.get(0, 0), Color::new(0.0, 0.0, 0.0));
+        assert_color(depth.get(1, 0), Color::new(0.5, 0.5, 0.5));
+        assert_color(depth.get(2, 0), Color::new(1.0, 1.0, 1.0));
+
+        for view in [ScalarView::Bounces, ScalarView::IntersectionTests] {
+            let heat = DebugView::Scalar(view).finish(raw.clone());
+            for x in 0..3 {
+                assert_color(heat.get(x, 0), compare::magma(x as f64 / 2.0));
+            }
+        }
+
+        // surface views are colored already
+        let surface = DebugView::Surface(SurfaceView::Uv).finish(raw.clone());
+        assert_color(surface.get(2, 0), Color::new(4.0, 4.0, 4.0));
+
+        // an image of zeros stays at the bottom of the scale
+        let empty = DebugView::Scalar(ScalarView::Bounces).finish(image::Image::new(2, 2));
+        assert_color(empty.get(1, 1), compare::magma(0.0));
+    }
+}
@@ -0,0 +1,250 @@
+use crate::image;
+use crate::vector;
+
+use std::io;
+
+use vector::Vec3 as Color;
+
+// Edge-avoiding a-trous wavelet filter (Dammertz et al. 2010). Each pass
+// blurs with a 5x5 B3 spline kernel whose taps are spread 2^pass pixels
+// apart, and weights every tap by how similar its color, albedo, normal and
+// depth are to the centre pixel, so edges in the feature buffers are kept.
+#[derive(Clone, Copy)]
+pub struct Denoiser {
+    pub iterations: i32,
+    // falloffs of the edge-stopping functions; smaller keeps more edges
+    pub sigma_color: f64,
+    pub sigma_albedo: f64,
+    pub sigma_normal: f64,
+    // relative to the centre depth, per pixel of tap distance
+    pub sigma_depth: f64,
+}
+
+impl Denoiser {
+    const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+    pub fn new() -> Self {
+        Denoiser {
+            iterations: 5,
+            sigma_color: 0.6,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.3,
+            sigma_depth: 0.02,
+        }
+    }
+
+    // Filters beauty guided by the feature buffers, which must be the same
+    // size as the image.
+    pub fn apply(
+        &self,
+        beauty: &image::Image,
+        albedo: &image::Image,
+        normal: &image::Image,
+        depth: &image::Image,
+    ) -> io::Result<image::Image> {
+        for (name, layer) in [("albedo", albedo), ("normal", normal), ("depth", depth)] {
+            if (layer.width, layer.height) != (beauty.width, beauty.height) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "the {} buffer is {}x{} but the image is {}x{}",
+                        name, layer.width, layer.height, beauty.width, beauty.height
+                    ),
+                ));
+            }
+        }
+
+        let mut current = beauty.clone();
+        // the color falloff shrinks as the noise is filtered away
+        let mut sigma_color = self.sigma_color;
+        for pass in 0..self.iterations {
+            current = self.filter_pass(&current, albedo, normal, depth, 1 << pass, sigma_color);
+            sigma_color *= 0.5;
+        }
+        return Ok(current);
+    }
+
+    fn filter_pass(
+        &self,
+        input: &image::Image,
+        albedo: &image::Image,
+        normal: &image::Image,
+        depth: &image::Image,
+        step: i64,
+        sigma_color: f64,
+    ) -> image::Image {
+        let (width, height) = (input.width as i64, input.height as i64);
+        let mut output = image::Image::new(input.width, input.height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let p = (x as usize, y as usize);
+                let c_p = Self::compress(input.get(p.0, p.1));
+                let a_p = albedo.get(p.0, p.1);
+                let n_p = normal.get(p.0, p.1);
+                let d_p = depth.get(p.0, p.1).x();
+
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                let mut weight_sum = 0.0;
+                for (j, ky) in Self::KERNEL.iter().enumerate() {
+                    for (i, kx) in Self::KERNEL.iter().enumerate() {
+                        let qx = x + (i as i64 - 2) * step;
+                        let qy = y + (j as i64 - 2) * step;
+                        if qx < 0 || qy < 0 || qx >= width || qy >= height {
+                            continue;
+                        }
+                        let q = (qx as usize, qy as usize);
+                        let c_q = input.get(q.0, q.1);
+
+                        let dc = (Self::compress(c_q) - c_p).length_squared();
+                        let da = (albedo.get(q.0, q.1) - a_p).length_squared();
+                        let dn = (normal.get(q.0, q.1) - n_p).length_squared();
+                        let dd = (depth.get(q.0, q.1).x() - d_p).abs();
+                        let distance = (((qx - x).pow(2) + (qy - y).pow(2)) as f64).sqrt();
+
+                        let w = kx
+                            * ky
+                            * (-dc / (sigma_color * sigma_color)
+                                - da / (self.sigma_albedo * self.sigma_albedo)
+                                - dn / (self.sigma_normal * self.sigma_normal)
+                                - dd / (self.sigma_depth * d_p.abs() * distance + 1e-6))
+                                .exp();
+                        sum = sum + c_q * w;
+                        weight_sum += w;
+                    }
+                }
+                // the centre tap always has a positive weight
+                output.set(p.0, p.1, sum / weight_sum);
+            }
+        }
+        return output;
+    }
+
+    // Compares colors after a Reinhard curve so bright highlights do not
+    // dominate the color distance.
+    fn compress(c: Color) -> Color {
+        return Color::new(
+            c.x() / (1.0 + c.x()),
+            c.y() / (1.0 + c.y()),
+            c.z() / (1.0 + c.z()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::utility;
+
+    const SIZE: usize = 16;
+
+    // An image whose left and right halves get the given colors, as a guide
+    // buffer or, with noise, as the beauty image.
+    fn halves(left: Color, right: Color, noise: f64) -> image::Image {
+        let mut img = image::Image::new(SIZE, SIZE);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let base = if x < SIZE / 2 { left } else { right };
+                img.set(x, y, base * (1.0 + utility::random_double(-noise, noise)));
+            }
+        }
+        return img;
+    }
+
+    fn flat(value: Color) -> image::Image {
+        return halves(value, value, 0.0);
+    }
+
+    // Mean and standard deviation of the red channel in columns x0..x1.
+    fn column_stats(img: &image::Image, x0: usize, x1: usize) -> (f64, f64) {
+        let values: Vec<f64> = (0..SIZE)
+            .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+            .map(|(x, y)| img.get(x, y).x())
+            .collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance =
+            values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / values.len() as f64;
+        return (mean, variance.sqrt());
+    }
+
+    // Checks that denoised keeps the edge between the halves of the image
+    // while its noise goes down.
+    fn assert_edge_kept(noisy: &image::Image, denoised: &image::Image) {
+        for (x0, x1) in [(0, SIZE / 2), (SIZE / 2, SIZE)] {
+            let (noisy_mean, noisy_deviation) = column_stats(noisy, x0, x1);
+            let (mean, deviation) = column_stats(denoised, x0, x1);
+            assert!(
+                deviation < 0.5 * noisy_deviation,
+                "noise {} of columns {}..{} is not below {}",
+                deviation,
+                x0,
+                x1,
+                0.5 * noisy_deviation
+            );
+            assert!((mean - noisy_mean).abs() < 0.02 * noisy_mean);
+        }
+        // the columns either side of the edge keep their own level
+        let (left, _) = column_stats(denoised, SIZE / 2 - 1, SIZE / 2);
+        let (right, _) = column_stats(denoised, SIZE / 2, SIZE / 2 + 1);
+        assert!(
+            left < 0.25 && right > 0.75,
+            "edge blurred to {} and {}",
+            left,
+            right
+        );
+    }
+
+    #[test]
+    fn albedo_edges_survive() {
+        utility::seed(5);
+        let (dark, light) = (Color::new(0.2, 0.2, 0.2), Color::new(0.8, 0.8, 0.8));
+        let noisy = halves(dark, light, 0.3);
+        let albedo = halves(dark, light, 0.0);
+        let normal = flat(Color::new(0.0, 0.0, 1.0));
+        let depth = flat(Color::new(1.0, 1.0, 1.0));
+        let denoised = Denoiser::new()
+            .apply(&noisy, &albedo, &normal, &depth)
+            .unwrap();
+        assert_edge_kept(&noisy, &denoised);
+
+        // without the guide the same edge is blurred
+        let unguided = Denoiser::new()
+            .apply(&noisy, &flat(Color::new(0.5, 0.5, 0.5)), &normal, &depth)
+            .unwrap();
+        let (left, _) = column_stats(&unguided, SIZE / 2 - 1, SIZE / 2);
+        assert!(left > 0.25, "edge kept without a guide at {}", left);
+    }
+
+    #[test]
+    fn normal_edges_survive() {
+        // a white fold lit from one side
+        utility::seed(6);
+        let noisy = halves(Color::new(0.2, 0.2, 0.2), Color::new(0.8, 0.8, 0.8), 0.3);
+        let albedo = flat(Color::new(1.0, 1.0, 1.0));
+        let normal = halves(Color::new(1.0, 0.0, 0.0), Color::new(0.0, 0.0, 1.0), 0.0);
+        let depth = flat(Color::new(1.0, 1.0, 1.0));
+        let denoised = Denoiser::new()
+            .apply(&noisy, &albedo, &normal, &depth)
+            .unwrap();
+        assert_edge_kept(&noisy, &denoised);
+    }
+
+    #[test]
+    fn buffers_must_match_the_image() {
+        let beauty = image::Image::new(4, 4);
+        let small = image::Image::new(4, 3);
+        let denoiser = Denoiser::new();
+        for (albedo, normal, depth) in [
+            (&small, &beauty, &beauty),
+            (&beauty, &small, &beauty),
+            (&beauty, &beauty, &small),
+        ] {
+            let e = denoiser
+                .apply(&beauty, albedo, normal, depth)
+                .err()
+                .unwrap();
+            assert!(e.kind() == io::ErrorKind::InvalidData);
+        }
+    }
+}
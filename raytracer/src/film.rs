@@ -0,0 +1,250 @@
+use crate::image;
+use crate::vector;
+
+use std::f64::consts::PI;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use vector::Vec3 as Color;
+
+// Pixel reconstruction filters. Each is separable and extends radius pixels
+// from the pixel centre along both axes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Filter {
+    Box { radius: f64 },
+    Tent { radius: f64 },
+    // shifted down so it reaches zero at the radius
+    Gaussian { radius: f64, alpha: f64 },
+    // Mitchell-Netravali cubic; b = c = 1/3 is the recommended balance of
+    // blurring and ringing
+    Mitchell { radius: f64, b: f64, c: f64 },
+    // sinc windowed by a wider sinc reaching zero at the radius
+    Lanczos { radius: f64 },
+}
+
+impl Filter {
+    // A filter by name, with its default radius unless one is given.
+    pub fn from_name(name: &str, radius: Option<f64>) -> Option<Filter> {
+        let filter = match name {
+            "box" => Filter::Box {
+                radius: radius.unwrap_or(0.5),
+            },
+            "tent" => Filter::Tent {
+                radius: radius.unwrap_or(1.0),
+            },
+            "gaussian" => Filter::Gaussian {
+                radius: radius.unwrap_or(1.5),
+                alpha: 2.0,
+            },
+            "mitchell" => Filter::Mitchell {
+                radius: radius.unwrap_or(2.0),
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            },
+            "lanczos" => Filter::Lanczos {
+                radius: radius.unwrap_or(3.0),
+            },
+            _ => return None,
+        };
+        return Some(filter);
+    }
+
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => radius,
+        }
+    }
+
+    // Weight of a sample at offset (x, y) from a pixel centre.
+    pub fn evaluate(&self, x: f64, y: f64) -> f64 {
+        return self.evaluate_1d(x) * self.evaluate_1d(y);
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        match *self {
+            Filter::Box { radius } => {
+                if x <= radius {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Filter::Tent { radius } => (radius - x).max(0.0),
+            Filter::Gaussian { radius, alpha } => {
+                ((-alpha * x * x).exp() - (-alpha * radius * radius).exp()).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                if x >= radius {
+                    return 0.0;
+                }
+                // the cubic is defined over [-2, 2]
+                let x = 2.0 * x / radius;
+                let w = if x > 1.0 {
+                    (-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c)
+                } else {
+                    (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b)
+                };
+                w / 6.0
+            }
+            Filter::Lanczos { radius } => {
+                if x >= radius {
+                    return 0.0;
+                }
+                Self::sinc(x) * Self::sinc(x / radius)
+            }
+        }
+    }
+
+    fn sinc(x: f64) -> f64 {
+        if x < 1e-5 {
+            return 1.0;
+        }
+        return (PI * x).sin() / (PI * x);
+    }
+}
+
+// Accumulates filter weighted samples. Each sample is splatted into every
+// pixel whose centre lies within the filter radius, and a pixel's value is
+// its weighted sum divided by the sum of the weights.
+#[derive(Clone)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub filter: Filter,
+    sums: Vec<Color>,
+    weights: Vec<f64>,
+}
+
+impl Film {
+    // negligible next to a sample near the pixel centre, which every filter
+    // weighs by about 1
+    const MIN_WEIGHT: f64 = 1e-8;
+
+    pub fn new(width: usize, height: usize, filter: Filter) -> Self {
+        Film {
+            width,
+            height,
+            filter,
+            sums: vec![Color::new(0.0, 0.0, 0.0); width * height],
+            weights: vec![0.0; width * height],
+        }
+    }
+
+    // Adds a sample at continuous image position (x, y), where pixel (i, j)
+    // covers [i, i + 1) x [j, j + 1).
+    pub fn add_sample(&mut self, x: f64, y: f64, sample_color: Color) {
+        let radius = self.filter.radius();
+        let x0 = (x - 0.5 - radius).ceil().max(0.0) as usize;
+        let y0 = (y - 0.5 - radius).ceil().max(0.0) as usize;
+        let x1 = (x - 0.5 + radius).floor().min(self.width as f64 - 1.0);
+        let y1 = (y - 0.5 + radius).floor().min(self.height as f64 - 1.0);
+        if x1 < 0.0 || y1 < 0.0 {
+            return;
+        }
+
+        for j in y0..=(y1 as usize) {
+            for i in x0..=(x1 as usize) {
+                let w = self.filter.evaluate(i as f64 + 0.5 - x, j as f64 + 0.5 - y);
+                if w == 0.0 {
+                    continue;
+                }
+                let index = j * self.width + i;
+                self.sums[index] = self.sums[index] + sample_color * w;
+                self.weights[index] += w;
+            }
+        }
+    }
+
+    // Writes the weighted sums and the weights, pixel by pixel, as
+    // little-endian f64s.
+    pub fn write_state(&self, out: &mut impl Write) -> io::Result<()> {
+        for (sum, weight) in self.sums.iter().zip(&self.weights) {
+            for value in [sum.x(), sum.y(), sum.z(), *weight] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+        return Ok(());
+    }
+
+    // Replaces the accumulated samples with ones written by write_state for a
+    // film of the same size.
+    pub fn read_state(&mut self, input: &mut impl Read) -> io::Result<()> {
+        let mut read_f64 = || -> io::Result<f64> {
+            let mut bytes = [0u8; 8];
+            input.read_exact(&mut bytes)?;
+            return Ok(f64::from_le_bytes(bytes));
+        };
+        for index in 0..self.sums.len() {
+            self.sums[index] = Color::new(read_f64()?, read_f64()?, read_f64()?);
+            self.weights[index] = read_f64()?;
+        }
+        return Ok(());
+    }
+
+    // Adds the samples of a film of the same size and filter.
+    pub fn merge(&mut self, other: &Film) {
+        for index in 0..self.sums.len() {
+            self.sums[index] = self.sums[index] + other.sums[index];
+            self.weights[index] += other.weights[index];
+        }
+    }
+
+    // Pixels that received no net weight are black. So are pixels whose
+    // weight is negative or vanishingly small, which filters with negative
+    // lobes give where few samples land: dividing by it would turn rounding
+    // error into arbitrarily bright or negative values.
+    pub fn to_image(&self, scale: f64) -> image::Image {
+        let mut image = image::Image::new(self.width, self.height);
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let index = j * self.width + i;
+                if self.weights[index] > Self::MIN_WEIGHT {
+                    image.set(i, j, self.sums[index] * (scale / self.weights[index]));
+                }
+            }
+        }
+        return image;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vanishing_weights_develop_to_black() {
+        // Lanczos is zero at every whole pixel offset, up to rounding, so
+        // pixels 0 and 3 only get rounding error, of either sign, from the
+        // samples at the centres of pixels 1 and 2
+        let mut film = Film::new(4, 1, Filter::from_name("lanczos", None).unwrap());
+        film.add_sample(1.5, 0.5, Color::new(0.0, 0.0, 0.0));
+        film.add_sample(2.5, 0.5, Color::new(1.0, 1.0, 1.0));
+        let image = film.to_image(1.0);
+        assert!(image.get(0, 0).e == [0.0; 3] && image.get(3, 0).e == [0.0; 3]);
+        assert!(image.get(1, 0).x().abs() < 1e-12);
+        assert!((image.get(2, 0).x() - 1.0).abs() < 1e-12);
+
+        // a lone sample keeps its color wherever the weight is usable
+        let mut film = Film::new(8, 8, Filter::from_name("mitchell", None).unwrap());
+        film.add_sample(4.3, 4.7, Color::new(0.25, 0.5, 1.0));
+        let image = film.to_image(1.0);
+        for y in 0..8 {
+            for x in 0..8 {
+                let c = image.get(x, y);
+                assert!(c.e == [0.0; 3] || (c - Color::new(0.25, 0.5, 1.0)).length() < 1e-12);
+            }
+        }
+        assert!(image.get(4, 4).x() == 0.25);
+    }
+}
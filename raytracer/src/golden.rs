@@ -0,0 +1,198 @@
+use crate::image;
+use crate::progress;
+use crate::scenes;
+use crate::utility;
+#[cfg(test)]
+use crate::vector;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[cfg(test)]
+use vector::Vec3 as Color;
+
+// Golden image regression checks. Every preset is rendered small with fixed
+// seeds and compared with a reference in tests/golden that was rendered with
+// many more samples by `raytracer bless`. The images in images/ predate the
+// current output transform, so they only serve as a visual guide.
+pub const WIDTH: i32 = 64;
+#[cfg(test)]
+pub const TEST_SAMPLES: i32 = 16;
+pub const GOLDEN_SAMPLES: i32 = 512;
+
+// Seeds the random spheres of the final scene, so every render sees the same
+// layout.
+const SCENE_SEED: u64 = 2023;
+const GOLDEN_SEED: u64 = 1;
+#[cfg(test)]
+const TEST_SEEDS: [u64; 2] = [2, 3];
+
+pub fn golden_dir() -> PathBuf {
+    return Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden");
+}
+
+// Renders a preset at the harness resolution. Returns None for an unknown
+// preset.
+pub fn render_preset(name: &str, samples_per_pixel: i32, seed: u64) -> Option<image::Image> {
+    utility::seed(SCENE_SEED);
+    let mut scene = scenes::preset(name)?;
+    scene.camera.image_width = WIDTH;
+    scene.camera.samples_per_pixel = samples_per_pixel;
+    scene.camera.progress.verbosity = progress::Verbosity::Quiet;
+
+    utility::seed(seed);
+    return Some(
+        scene
+            .camera
+            .render_image(&mut scene.world, &scene.materials),
+    );
+}
+
+// Writes a reference image for every preset into output_dir.
+pub fn bless<P: AsRef<Path>>(output_dir: P) -> io::Result<()> {
+    let output_dir = output_dir.as_ref();
+    fs::create_dir_all(output_dir)?;
+    for name in scenes::PRESETS {
+        eprintln!("Rendering golden image for {}", name);
+        let img = render_preset(name, GOLDEN_SAMPLES, GOLDEN_SEED).expect("every preset renders");
+        img.write_pfm(output_dir.join(format!("{}.pfm", name)))?;
+    }
+    return Ok(());
+}
+
+// Outcome of checking a render against its reference. The render's noise is
+// estimated from two independently seeded halves: with A and B rendered at
+// half the samples each, E[(A - B)^2] is four times the variance of their
+// average.
+#[cfg(test)]
+pub struct Verdict {
+    // per channel, over all pixels
+    pub mse: f64,
+    // the mse that noise alone explains
+    pub noise_mse: f64,
+    pub mean_difference: Color,
+    // largest mean difference that noise alone explains
+    pub mean_tolerance: f64,
+}
+
+#[cfg(test)]
+impl Verdict {
+    // How far the observed error may exceed what the noise explains. It
+    // absorbs the error of the noise estimate itself and of rare fireflies.
+    const MSE_MARGIN: f64 = 2.0;
+    // standard errors the mean of each channel may move
+    const MEAN_SIGMAS: f64 = 5.0;
+
+    pub fn passed(&self) -> bool {
+        let mean_ok = (0..3).all(|c| self.mean_difference[c].abs() <= self.mean_tolerance);
+        return self.mse <= Self::MSE_MARGIN * self.noise_mse + 1e-6 && mean_ok;
+    }
+}
+
+#[cfg(test)]
+pub fn check(name: &str, reference: &image::Image) -> Verdict {
+    let half = TEST_SAMPLES / 2;
+    let a = render_preset(name, half, TEST_SEEDS[0]).expect("unknown preset");
+    let b = render_preset(name, half, TEST_SEEDS[1]).expect("unknown preset");
+    assert!(
+        a.width == reference.width && a.height == reference.height,
+        "reference for {} is {}x{}, expected {}x{}",
+        name,
+        reference.width,
+        reference.height,
+        a.width,
+        a.height
+    );
+
+    let count = (a.width * a.height * 3) as f64;
+    let mut half_difference = 0.0;
+    let mut squared_error = 0.0;
+    let mut difference = Color::new(0.0, 0.0, 0.0);
+    for y in 0..a.height {
+        for x in 0..a.width {
+            let average = (a.get(x, y) + b.get(x, y)) * 0.5;
+            let error = average - reference.get(x, y);
+            half_difference += (a.get(x, y) - b.get(x, y)).length_squared();
+            squared_error += error.length_squared();
+            difference = difference + error;
+        }
+    }
+
+    let test_variance = half_difference / count / 4.0;
+    // the reference adds its own, smaller, noise
+    let reference_variance = test_variance * TEST_SAMPLES as f64 / GOLDEN_SAMPLES as f64;
+    let noise_mse = test_variance + reference_variance;
+    let pixels = (a.width * a.height) as f64;
+
+    return Verdict {
+        mse: squared_error / count,
+        noise_mse,
+        mean_difference: difference / pixels,
+        mean_tolerance: Verdict::MEAN_SIGMAS * (noise_mse / pixels).sqrt() + 1e-4,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_preset(name: &str) {
+        let path = golden_dir().join(format!("{}.pfm", name));
+        let reference = image::Image::read_pfm(&path).unwrap_or_else(|e| {
+            panic!(
+                "missing reference {} ({}); run `raytracer bless` to create it",
+                path.display(),
+                e
+            )
+        });
+        let verdict = check(name, &reference);
+        assert!(
+            verdict.passed(),
+            "{} differs from its reference: mse {:.3e} against {:.3e} from noise, \
+             mean difference ({:.4}, {:.4}, {:.4}) against a tolerance of {:.4}",
+            name,
+            verdict.mse,
+            verdict.noise_mse,
+            verdict.mean_difference.x(),
+            verdict.mean_difference.y(),
+            verdict.mean_difference.z(),
+            verdict.mean_tolerance
+        );
+    }
+
+    macro_rules! golden_tests {
+        ($($name:ident),*) => {
+            $(
+                #[test]
+                fn $name() {
+                    check_preset(stringify!($name));
+                }
+            )*
+        };
+    }
+
+    golden_tests!(
+        lambertian_sphere,
+        metal_spheres,
+        fuzz,
+        glass,
+        more_glass,
+        hollow,
+        wide_fov,
+        distant_view,
+        zoomed,
+        depth_of_field,
+        random_spheres,
+        hollow_csg,
+        csg_shapes,
+        sdf_shapes,
+        sdf_combinators,
+        fog,
+        cloud,
+        dispersion
+    );
+}
@@ -0,0 +1,370 @@
+use crate::hittable;
+use crate::interval;
+use crate::material;
+use crate::perlin;
+use crate::ray;
+use crate::utility;
+use crate::vector;
+
+use std::boxed::Box;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use vector::Vec3 as Point3;
+
+// Voxel densities stretched over the box [min, max]. Values are stored with
+// x varying fastest, then y, then z. Points outside the box have no density.
+pub struct DensityGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    values: Vec<f64>,
+    min: Point3,
+    max: Point3,
+}
+
+impl DensityGrid {
+    pub fn new(
+        nx: usize,
+        ny: usize,
+        nz: usize,
+        values: Vec<f64>,
+        min: Point3,
+        max: Point3,
+    ) -> io::Result<Self> {
+        let expected = nx.checked_mul(ny).and_then(|n| n.checked_mul(nz));
+        if nx == 0 || ny == 0 || nz == 0 || expected != Some(values.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "density grid of {}x{}x{} needs {} values, got {}",
+                    nx,
+                    ny,
+                    nz,
+                    nx as u128 * ny as u128 * nz as u128,
+                    values.len()
+                ),
+            ));
+        }
+        Ok(DensityGrid {
+            nx,
+            ny,
+            nz,
+            values,
+            min,
+            max,
+        })
+    }
+
+    // Text grids start with "nx ny nz" followed by the values, separated by
+    // any whitespace. Lines starting with '#' are comments.
+    pub fn from_text_file<P: AsRef<Path>>(path: P, min: Point3, max: Point3) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut tokens = text
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(|line| line.split_whitespace());
+
+        let mut dims = [0usize; 3];
+        for dim in dims.iter_mut() {
+            *dim = match tokens.next().map(|token| token.parse()) {
+                Some(Ok(n)) => n,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "density grid is missing its dimensions",
+                    ))
+                }
+            };
+        }
+
+        let values = tokens
+            .map(|token| {
+                token
+                    .parse::<f64>()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .collect::<io::Result<Vec<f64>>>()?;
+
+        return Self::new(dims[0], dims[1], dims[2], values, min, max);
+    }
+
+    // Raw grids are headerless little-endian f32 values.
+    pub fn from_raw_file<P: AsRef<Path>>(
+        path: P,
+        nx: usize,
+        ny: usize,
+        nz: usize,
+        min: Point3,
+        max: Point3,
+    ) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let expected = nx
+            .checked_mul(ny)
+            .and_then(|n| n.checked_mul(nz))
+            .and_then(|n| n.checked_mul(4));
+        if expected != Some(bytes.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "raw density grid of {}x{}x{} needs {} bytes, got {}",
+                    nx,
+                    ny,
+                    nz,
+                    nx as u128 * ny as u128 * nz as u128 * 4,
+                    bytes.len()
+                ),
+            ));
+        }
+        let values = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+            .collect();
+        return Self::new(nx, ny, nz, values, min, max);
+    }
+
+    pub fn max_value(&self) -> f64 {
+        return self.values.iter().cloned().fold(0.0, f64::max);
+    }
+
+    fn voxel(&self, i: usize, j: usize, k: usize) -> f64 {
+        return self.values[(k * self.ny + j) * self.nx + i];
+    }
+
+    // Trilinear lookup with voxel values at cell centres.
+    pub fn lookup(&self, p: Point3) -> f64 {
+        let extent = self.max - self.min;
+        let dims = [self.nx, self.ny, self.nz];
+        let mut base = [0usize; 3];
+        let mut frac = [0.0; 3];
+
+        for axis in 0..3 {
+            let u = (p[axis] - self.min[axis]) / extent[axis];
+            if !(0.0..=1.0).contains(&u) {
+                return 0.0;
+            }
+            let g = (u * dims[axis] as f64 - 0.5).clamp(0.0, (dims[axis] - 1) as f64);
+            base[axis] = (g.floor() as usize).min(dims[axis].saturating_sub(2));
+            frac[axis] = g - base[axis] as f64;
+        }
+
+        let mut accum = 0.0;
+        for dk in 0..2 {
+            for dj in 0..2 {
+                for di in 0..2 {
+                    let i = (base[0] + di).min(self.nx - 1);
+                    let j = (base[1] + dj).min(self.ny - 1);
+                    let k = (base[2] + dk).min(self.nz - 1);
+                    let weight = (if di == 1 { frac[0] } else { 1.0 - frac[0] })
+                        * (if dj == 1 { frac[1] } else { 1.0 - frac[1] })
+                        * (if dk == 1 { frac[2] } else { 1.0 - frac[2] });
+                    accum += weight * self.voxel(i, j, k);
+                }
+            }
+        }
+        return accum;
+    }
+}
+
+// Procedural density: turbulent Perlin noise scaled to [0, density].
+pub struct NoiseDensity {
+    noise: perlin::Perlin,
+    pub scale: f64,
+    pub density: f64,
+    pub octaves: i32,
+}
+
+impl NoiseDensity {
+    pub fn new(scale: f64, density: f64) -> Self {
+        NoiseDensity {
+            noise: perlin::Perlin::new(),
+            scale,
+            density,
+            octaves: 7,
+        }
+    }
+}
+
+pub enum Density {
+    Grid(DensityGrid),
+    Noise(NoiseDensity),
+}
+
+impl Density {
+    pub fn value(&self, p: Point3) -> f64 {
+        match self {
+            Density::Grid(grid) => grid.lookup(p),
+            Density::Noise(noise) => {
+                let n = noise.noise.turb(p * noise.scale, noise.octaves);
+                noise.density * n.clamp(0.0, 1.0)
+            }
+        }
+    }
+
+    // Upper bound on value, used as the majorant for delta tracking.
+    pub fn max_value(&self) -> f64 {
+        match self {
+            Density::Grid(grid) => grid.max_value(),
+            Density::Noise(noise) => noise.density,
+        }
+    }
+}
+
+// A volume whose density varies inside a closed boundary. Collisions are
+// found with delta tracking against the density majorant, so no bias is
+// introduced by stepping through the grid.
+pub struct HeterogeneousMedium {
+    boundary: Box<dyn hittable::Hittable>,
+    density: Density,
+    majorant: f64,
+    phase_function: material::MaterialId,
+}
+
+impl HeterogeneousMedium {
+    pub fn new(
+        boundary: Box<dyn hittable::Hittable>,
+        density: Density,
+        phase_function: material::MaterialId,
+    ) -> Self {
+        let majorant = density.max_value();
+        HeterogeneousMedium {
+            boundary,
+            density,
+            majorant,
+            phase_function,
+        }
+    }
+}
+
+impl hittable::Hittable for HeterogeneousMedium {
+    fn hit(
+        &mut self,
+        r: &ray::Ray,
+        ray_t: interval::Interval,
+        rec: &mut hittable::HitRecord,
+    ) -> bool {
+        if self.majorant <= 0.0 {
+            return false;
+        }
+
+        let mut rec1 = hittable::HitRecord::default();
+        let mut rec2 = hittable::HitRecord::default();
+
+        if !self.boundary.hit(r, interval::UNIVERSE, &mut rec1) {
+            return false;
+        }
+        if !self.boundary.hit(
+            r,
+            interval::Interval::new(rec1.t + 0.0001, f64::INFINITY),
+            &mut rec2,
+        ) {
+            return false;
+        }
+
+        let t_enter = rec1.t.max(ray_t.min).max(0.0);
+        let t_exit = rec2.t.min(ray_t.max);
+        if t_enter >= t_exit {
+            return false;
+        }
+
+        let ray_length = r.direction().length();
+        let mut t = t_enter;
+        loop {
+            // sample a tentative collision against the majorant, then accept it
+            // as real with probability density / majorant
+            t -= (1.0 - utility::random_double(0.0, 1.0)).ln() / (self.majorant * ray_length);
+            if t >= t_exit {
+                return false;
+            }
+            let p = r.at(t);
+            if utility::random_double(0.0, 1.0) * self.majorant < self.density.value(p) {
+                rec.t = t;
+                rec.p = p;
+                rec.normal = vector::Vec3::new(1.0, 0.0, 0.0);
+                rec.geometric_normal = rec.normal;
+                (rec.u, rec.v) = (0.0, 0.0);
+                rec.front_face = true;
+                rec.mat = self.phase_function;
+                return true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> (Point3, Point3) {
+        return (Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn lookup_interpolates_between_cell_centres() {
+        // v = i + 2j + 4k is linear, so trilinear interpolation reproduces it
+        // wherever the point lies between the cell centres 0.25 and 0.75
+        let (min, max) = unit_box();
+        let values = (0..8)
+            .map(|n| ((n & 1) + 2 * (n >> 1 & 1) + 4 * (n >> 2 & 1)) as f64)
+            .collect();
+        let grid = DensityGrid::new(2, 2, 2, values, min, max).unwrap();
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+        assert!(close(grid.lookup(Point3::new(0.25, 0.25, 0.25)), 0.0));
+        assert!(close(grid.lookup(Point3::new(0.75, 0.75, 0.75)), 7.0));
+        assert!(close(grid.lookup(Point3::new(0.5, 0.5, 0.5)), 3.5));
+        assert!(close(
+            grid.lookup(Point3::new(0.6, 0.3, 0.75)),
+            0.7 + 0.2 + 4.0
+        ));
+        // beyond the outer cell centres the value is held, outside it is 0
+        assert!(close(grid.lookup(Point3::new(0.1, 0.9, 0.0)), 2.0));
+        assert!(close(grid.lookup(Point3::new(1.1, 0.5, 0.5)), 0.0));
+
+        // along a longer axis the lookup picks the cells around the point
+        let grid = DensityGrid::new(3, 1, 1, vec![0.0, 10.0, 40.0], min, max).unwrap();
+        assert!(close(grid.lookup(Point3::new(0.5, 0.5, 0.5)), 10.0));
+        assert!(close(grid.lookup(Point3::new(4.0 / 6.0, 0.5, 0.5)), 25.0));
+        assert!(close(grid.lookup(Point3::new(5.0 / 6.0, 0.5, 0.5)), 40.0));
+    }
+
+    #[test]
+    fn raw_grid_must_match_its_size() {
+        let path = std::env::temp_dir().join(format!("density-test-{}.raw", std::process::id()));
+        let bytes: Vec<u8> = [0.5f32, 2.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let (min, max) = unit_box();
+
+        fs::write(&path, &bytes).unwrap();
+        let grid = DensityGrid::from_raw_file(&path, 1, 1, 2, min, max).unwrap();
+        assert!(grid.max_value() == 2.0);
+
+        fs::write(&path, &bytes[..7]).unwrap();
+        let truncated = DensityGrid::from_raw_file(&path, 1, 1, 2, min, max);
+        fs::write(&path, [bytes.as_slice(), &[0u8; 4]].concat()).unwrap();
+        let trailing = DensityGrid::from_raw_file(&path, 1, 1, 2, min, max);
+        fs::remove_file(&path).unwrap();
+        assert!(truncated.is_err() && trailing.is_err());
+    }
+
+    #[test]
+    fn text_grid_must_match_its_size() {
+        let path = std::env::temp_dir().join(format!("density-test-{}.txt", std::process::id()));
+        let (min, max) = unit_box();
+        let load = |text: &str| {
+            fs::write(&path, text).unwrap();
+            return DensityGrid::from_text_file(&path, min, max);
+        };
+
+        let grid = load("# a 2x1x1 grid\n2 1 1\n0.5 2.0\n").unwrap();
+        assert!(grid.max_value() == 2.0);
+        let short = load("2 1 1\n0.5\n");
+        let long = load("2 1 1\n0.5 2.0 1.0\n");
+        // the voxel count wraps around to 0 without checked arithmetic
+        let overflowing = load("4294967296 4294967296 1\n");
+        let empty = load("0 1 1\n");
+        fs::remove_file(&path).unwrap();
+        for grid in [short, long, overflowing, empty] {
+            assert!(grid.err().unwrap().kind() == io::ErrorKind::InvalidData);
+        }
+    }
+}
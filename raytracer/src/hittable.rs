@@ -1,33 +1,46 @@
 use crate::interval;
 use crate::material;
 use crate::ray;
+use crate::stats;
 use crate::vector;
 
 use vector::Vec3 as Point3;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub struct HitRecord {
     pub p: Point3,
+    // shading normal, facing against the ray
     pub normal: vector::Vec3,
-    pub mat: material::Material,
+    // outward normal of the surface itself, whichever side was hit
+    pub geometric_normal: vector::Vec3,
+    // surface coordinates in [0, 1], zero for surfaces without them
+    pub u: f64,
+    pub v: f64,
+    pub mat: material::MaterialId,
     pub t: f64,
     pub front_face: bool,
+    // index of the top-level object in the scene list that was hit
+    pub object_id: usize,
 }
 
 impl HitRecord {
     pub fn new(
         p: Point3,
         normal: vector::Vec3,
-        mat: material::Material,
+        mat: material::MaterialId,
         t: f64,
         front_face: bool,
     ) -> Self {
         HitRecord {
             p,
             normal,
+            geometric_normal: normal,
+            u: 0.0,
+            v: 0.0,
             mat,
             t,
             front_face,
+            object_id: 0,
         }
     }
 
@@ -35,6 +48,7 @@ impl HitRecord {
         //Sets the hit record normal vector.
         // NOTE: the param outward_normal is assumed to have unit length
 
+        self.geometric_normal = *outward_normal;
         self.front_face = vector::dot(&r.direction(), outward_normal) < 0.0;
         self.normal = if self.front_face {
             *outward_normal
@@ -46,26 +60,50 @@ impl HitRecord {
 
 pub trait Hittable {
     fn hit(&mut self, r: &ray::Ray, ray_t: interval::Interval, rec: &mut HitRecord) -> bool;
+
+    // Pushes every surface crossing inside ray_t onto hits, ordered by t. For a
+    // closed object front_face marks where the ray enters it and !front_face
+    // where it leaves. The default walks the crossings with repeated nearest hits.
+    fn hit_all(&mut self, r: &ray::Ray, ray_t: interval::Interval, hits: &mut Vec<HitRecord>) {
+        let mut rec = HitRecord::default();
+        let mut t_min = ray_t.min;
+        while self.hit(r, interval::Interval::new(t_min, ray_t.max), &mut rec) {
+            hits.push(rec);
+            t_min = rec.t;
+        }
+    }
 }
 
 pub struct Sphere {
     pub center: Point3,
     pub radius: f64,
-    pub mat: material::Material,
+    pub mat: material::MaterialId,
 }
 
 impl Sphere {
-    pub fn new(center: Point3, radius: f64, mat: material::Material) -> Sphere {
+    pub fn new(center: Point3, radius: f64, mat: material::MaterialId) -> Sphere {
         Sphere {
             center,
             radius,
             mat,
         }
     }
+
+    // Maps a point of the unit sphere to longitude u, starting at -x and
+    // running around the y axis, and latitude v from the south pole.
+    fn uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z()).atan2(p.x()) + std::f64::consts::PI;
+        return (
+            phi / (2.0 * std::f64::consts::PI),
+            theta / std::f64::consts::PI,
+        );
+    }
 }
 
 impl Hittable for Sphere {
     fn hit(&mut self, r: &ray::Ray, ray_t: interval::Interval, rec: &mut HitRecord) -> bool {
+        stats::count(stats::Counter::IntersectionTest);
         let oc = r.origin() - self.center;
         let a = r.direction().length_squared();
         let half_b = vector::dot(&oc, &r.direction());
@@ -89,6 +127,7 @@ impl Hittable for Sphere {
         rec.p = r.at(rec.t);
         let outward_normal: vector::Vec3 = (rec.p - self.center) / self.radius;
         rec.set_normal_face(r, &outward_normal);
+        (rec.u, rec.v) = Sphere::uv(&((rec.p - self.center) / self.radius.abs()));
         rec.mat = self.mat;
 
         return true;
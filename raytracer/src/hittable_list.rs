@@ -16,6 +16,7 @@ impl HittableList {
         HittableList { objects }
     }
 
+    #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.objects.clear();
     }
@@ -35,16 +36,14 @@ impl hittable::Hittable for HittableList {
         let mut temp_rec = hittable::HitRecord::new(
             vector::Vec3 { e: [0.0; 3] },
             vector::Vec3 { e: [0.0; 3] },
-            material::Material::Lambertian(material::Lambertian::new(vector::Vec3::new(
-                0.0, 0.0, 0.0,
-            ))),
+            material::MaterialId::default(),
             0.0,
             true,
         );
         let mut hit_anything = false;
         let mut closest_so_far = ray_t.max;
 
-        for object in self.objects.iter_mut() {
+        for (index, object) in self.objects.iter_mut().enumerate() {
             if object.hit(
                 r,
                 interval::Interval::new(ray_t.min, closest_so_far),
@@ -53,6 +52,7 @@ impl hittable::Hittable for HittableList {
                 hit_anything = true;
                 closest_so_far = temp_rec.t;
                 *rec = temp_rec;
+                rec.object_id = index;
             }
         }
 
@@ -0,0 +1,490 @@
+use crate::color;
+use crate::vector;
+
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+use vector::Vec3 as Color;
+
+// A rendered frame of linear colors, stored row by row from the top left.
+#[derive(Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![Color::new(0.0, 0.0, 0.0); width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        return self.pixels[y * self.width + x];
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, pixel_color: Color) {
+        self.pixels[y * self.width + x] = pixel_color;
+    }
+
+    // Copies other into this image with its top left corner at (x0, y0).
+    pub fn blit(&mut self, other: &Image, x0: usize, y0: usize) {
+        for y in 0..other.height {
+            for x in 0..other.width {
+                self.set(x0 + x, y0 + y, other.get(x, y));
+            }
+        }
+    }
+
+    // Reads a PBM-family image (P2, P3, P5 or P6). Values are scaled by the
+    // file's maxval but otherwise kept as stored, so they are display encoded.
+    // ASCII files may contain decimal or out-of-range values.
+    pub fn read_pnm<P: AsRef<Path>>(path: P) -> io::Result<Image> {
+        let data = fs::read(path)?;
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+        // header tokens, skipping comments
+        let mut pos = 0;
+        let mut header = Vec::new();
+        while header.len() < 4 {
+            while pos < data.len() && data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos < data.len() && data[pos] == b'#' {
+                while pos < data.len() && data[pos] != b'\n' {
+                    pos += 1;
+                }
+                continue;
+            }
+            let start = pos;
+            while pos < data.len() && !data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if start == pos {
+                return Err(invalid("truncated PNM header"));
+            }
+            header.push(String::from_utf8_lossy(&data[start..pos]).to_string());
+        }
+
+        let magic = header[0].as_str();
+        let channels = match magic {
+            "P2" | "P5" => 1,
+            "P3" | "P6" => 3,
+            _ => return Err(invalid("unsupported PNM format")),
+        };
+        let parse_size = |token: &str| {
+            token
+                .parse::<usize>()
+                .map_err(|_| invalid("invalid PNM size"))
+        };
+        let width = parse_size(&header[1])?;
+        let height = parse_size(&header[2])?;
+        let maxval = header[3]
+            .parse::<f64>()
+            .map_err(|_| invalid("invalid number in PNM header"))?;
+        if maxval <= 0.0 {
+            return Err(invalid("invalid PNM maxval"));
+        }
+
+        // Every sample takes at least one byte, two in binary files with a
+        // large maxval, so a size the data cannot hold is rejected before
+        // anything is allocated.
+        let sample_bytes = if magic == "P5" && maxval >= 256.0 {
+            2
+        } else {
+            1
+        };
+        let count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(channels))
+            .ok_or_else(|| invalid("PNM size too large"))?;
+        if count
+            .checked_mul(sample_bytes)
+            .is_none_or(|bytes| bytes > data.len() - pos)
+        {
+            return Err(invalid("truncated PNM pixel data"));
+        }
+        let samples: Vec<f64> = if magic == "P2" || magic == "P3" {
+            String::from_utf8_lossy(&data[pos..])
+                .split_whitespace()
+                .take(count)
+                .map(|token| token.parse::<f64>().unwrap_or(0.0))
+                .collect()
+        } else {
+            // a single whitespace byte separates the header from binary data
+            let body = &data[(pos + 1).min(data.len())..];
+            if maxval < 256.0 {
+                body.iter().take(count).map(|b| *b as f64).collect()
+            } else {
+                body.chunks_exact(2)
+                    .take(count)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]) as f64)
+                    .collect()
+            }
+        };
+        if samples.len() < count {
+            return Err(invalid("truncated PNM pixel data"));
+        }
+
+        let mut image = Image::new(width, height);
+        for (i, pixel) in samples.chunks_exact(channels).enumerate() {
+            let pixel_color = if channels == 1 {
+                Color::new(pixel[0], pixel[0], pixel[0])
+            } else {
+                Color::new(pixel[0], pixel[1], pixel[2])
+            };
+            image.pixels[i] = pixel_color / maxval;
+        }
+        return Ok(image);
+    }
+
+    // Reads a color (PF) or greyscale (Pf) PFM image. The sign of the scale
+    // line gives the byte order.
+    pub fn read_pfm<P: AsRef<Path>>(path: P) -> io::Result<Image> {
+        let data = fs::read(path)?;
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+        let mut pos = 0;
+        let mut header = Vec::new();
+        while header.len() < 4 {
+            while pos < data.len() && data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            let start = pos;
+            while pos < data.len() && !data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if start == pos {
+                return Err(invalid("truncated PFM header"));
+            }
+            header.push(String::from_utf8_lossy(&data[start..pos]).to_string());
+        }
+
+        let channels = match header[0].as_str() {
+            "PF" => 3,
+            "Pf" => 1,
+            _ => return Err(invalid("unsupported PFM format")),
+        };
+        let parse_size = |token: &str| {
+            token
+                .parse::<usize>()
+                .map_err(|_| invalid("invalid PFM size"))
+        };
+        let width = parse_size(&header[1])?;
+        let height = parse_size(&header[2])?;
+        let little_endian = header[3]
+            .parse::<f64>()
+            .map_err(|_| invalid("invalid number in PFM header"))?
+            < 0.0;
+
+        let body = &data[(pos + 1).min(data.len())..];
+        let count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(channels))
+            .ok_or_else(|| invalid("PFM size too large"))?;
+        if count.checked_mul(4).is_none_or(|bytes| body.len() < bytes) {
+            return Err(invalid("truncated PFM pixel data"));
+        }
+        let samples: Vec<f64> = body
+            .chunks_exact(4)
+            .take(count)
+            .map(|b| {
+                let bytes = [b[0], b[1], b[2], b[3]];
+                if little_endian {
+                    f32::from_le_bytes(bytes) as f64
+                } else {
+                    f32::from_be_bytes(bytes) as f64
+                }
+            })
+            .collect();
+
+        let mut image = Image::new(width, height);
+        for (i, pixel) in samples.chunks_exact(channels).enumerate() {
+            let pixel_color = if channels == 1 {
+                Color::new(pixel[0], pixel[0], pixel[0])
+            } else {
+                Color::new(pixel[0], pixel[1], pixel[2])
+            };
+            // rows are stored from the bottom up
+            image.set(i % width, height - 1 - i / width, pixel_color);
+        }
+        return Ok(image);
+    }
+
+    // Reads a PNG of any bit depth or color type. Values are scaled to [0, 1]
+    // and stay display encoded; alpha is dropped.
+    pub fn read_png<P: AsRef<Path>>(path: P) -> io::Result<Image> {
+        let mut decoder = png::Decoder::new(io::BufReader::new(fs::File::open(path)?));
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info().map_err(io::Error::other)?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).map_err(io::Error::other)?;
+
+        let channels = info.color_type.samples();
+        let (sample_bytes, maxval) = if info.bit_depth == png::BitDepth::Sixteen {
+            (2, 65535.0)
+        } else {
+            (1, 255.0)
+        };
+        let sample = |row: &[u8], index: usize| {
+            let value = if sample_bytes == 2 {
+                u16::from_be_bytes([row[2 * index], row[2 * index + 1]]) as f64
+            } else {
+                row[index] as f64
+            };
+            value / maxval
+        };
+
+        let (width, height) = (info.width as usize, info.height as usize);
+        let mut image = Image::new(width, height);
+        for (y, row) in data.chunks(info.line_size).take(height).enumerate() {
+            for x in 0..width {
+                let base = x * channels;
+                let pixel_color = if channels < 3 {
+                    let v = sample(row, base);
+                    Color::new(v, v, v)
+                } else {
+                    Color::new(
+                        sample(row, base),
+                        sample(row, base + 1),
+                        sample(row, base + 2),
+                    )
+                };
+                image.set(x, y, pixel_color);
+            }
+        }
+        return Ok(image);
+    }
+
+    // Reads a Radiance RGBE (.hdr) image with flat or run-length encoded
+    // scanlines. Values are linear.
+    pub fn read_hdr<P: AsRef<Path>>(path: P) -> io::Result<Image> {
+        let data = fs::read(path)?;
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+        // header lines end with an empty line, followed by the resolution
+        let mut pos = 0;
+        fn next_line(data: &[u8], pos: &mut usize) -> String {
+            let start = (*pos).min(data.len());
+            while *pos < data.len() && data[*pos] != b'\n' {
+                *pos += 1;
+            }
+            let line = String::from_utf8_lossy(&data[start..*pos]).to_string();
+            *pos += 1;
+            return line;
+        }
+        let magic = next_line(&data, &mut pos);
+        if !magic.starts_with("#?") {
+            return Err(invalid("not a Radiance HDR file"));
+        }
+        loop {
+            let line = next_line(&data, &mut pos);
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+                return Err(invalid("unsupported HDR pixel format"));
+            }
+            if pos >= data.len() {
+                return Err(invalid("truncated HDR header"));
+            }
+        }
+        let resolution = next_line(&data, &mut pos);
+        let tokens: Vec<&str> = resolution.split_whitespace().collect();
+        if tokens.len() != 4 || tokens[0] != "-Y" || tokens[2] != "+X" {
+            return Err(invalid("unsupported HDR orientation"));
+        }
+        let parse = |token: &str| {
+            token
+                .parse::<usize>()
+                .map_err(|_| invalid("invalid HDR resolution"))
+        };
+        let height = parse(tokens[1])?;
+        let width = parse(tokens[3])?;
+
+        // A flat scanline takes 4 bytes a pixel. A run length encoded one
+        // takes at least its 4 byte marker and, for each component, 2 bytes
+        // per run of up to 127 pixels. A size the data cannot hold is
+        // rejected before anything is allocated.
+        let rle_width = (8..32768).contains(&width);
+        let scanline_bytes = if rle_width {
+            Some(4 + 8 * width.div_ceil(127))
+        } else {
+            width.checked_mul(4)
+        };
+        if scanline_bytes
+            .and_then(|bytes| bytes.checked_mul(height))
+            .is_none_or(|bytes| bytes > data.len().saturating_sub(pos))
+        {
+            return Err(invalid("truncated HDR data"));
+        }
+
+        let mut image = Image::new(width, height);
+        let mut scanline = vec![[0u8; 4]; width];
+        for y in 0..height {
+            let rle = rle_width
+                && pos + 4 <= data.len()
+                && data[pos] == 2
+                && data[pos + 1] == 2
+                && data[pos + 2] & 0x80 == 0;
+            if rle {
+                // each of the four components is stored as its own run of
+                // literal and repeated bytes
+                pos += 4;
+                for component in 0..4 {
+                    let mut x = 0;
+                    while x < width {
+                        let count = *data.get(pos).ok_or_else(|| invalid("truncated HDR data"))?;
+                        pos += 1;
+                        if count > 128 {
+                            let count = (count - 128) as usize;
+                            let value =
+                                *data.get(pos).ok_or_else(|| invalid("truncated HDR data"))?;
+                            pos += 1;
+                            if x + count > width {
+                                return Err(invalid("bad HDR run length"));
+                            }
+                            for pixel in &mut scanline[x..x + count] {
+                                pixel[component] = value;
+                            }
+                            x += count;
+                        } else {
+                            let count = count as usize;
+                            if count == 0 || x + count > width || pos + count > data.len() {
+                                return Err(invalid("bad HDR run length"));
+                            }
+                            for (pixel, value) in scanline[x..x + count]
+                                .iter_mut()
+                                .zip(&data[pos..pos + count])
+                            {
+                                pixel[component] = *value;
+                            }
+                            pos += count;
+                            x += count;
+                        }
+                    }
+                }
+            } else {
+                if pos + 4 * width > data.len() {
+                    return Err(invalid("truncated HDR data"));
+                }
+                for (pixel, rgbe) in scanline.iter_mut().zip(data[pos..].chunks_exact(4)) {
+                    pixel.copy_from_slice(rgbe);
+                }
+                pos += 4 * width;
+            }
+
+            for (x, rgbe) in scanline.iter().enumerate() {
+                if rgbe[3] == 0 {
+                    continue;
+                }
+                let scale = 2.0f64.powi(rgbe[3] as i32 - 136);
+                let pixel_color = Color::new(rgbe[0] as f64, rgbe[1] as f64, rgbe[2] as f64);
+                image.set(x, y, (pixel_color + Color::new(0.5, 0.5, 0.5)) * scale);
+            }
+        }
+        return Ok(image);
+    }
+
+    // Reads an image in any supported format, chosen by the file extension.
+    // PFM and HDR files hold linear values, the others display encoded ones.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Image> {
+        let path = path.as_ref();
+        match Self::extension(path).as_deref() {
+            Some("png") => Self::read_png(path),
+            Some("pfm") => Self::read_pfm(path),
+            Some("hdr") => Self::read_hdr(path),
+            _ => Self::read_pnm(path),
+        }
+    }
+
+    pub fn is_linear_format<P: AsRef<Path>>(path: P) -> bool {
+        return matches!(
+            Self::extension(path.as_ref()).as_deref(),
+            Some("pfm") | Some("hdr")
+        );
+    }
+
+    fn extension(path: &Path) -> Option<String> {
+        return path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+    }
+
+    pub fn write_ppm(
+        &self,
+        out: &mut impl io::Write,
+        display: &color::DisplayTransform,
+    ) -> io::Result<()> {
+        writeln!(out, "P3\n{} {}\n255\n", self.width, self.height)?;
+        for pixel_color in &self.pixels {
+            color::write_color(out, *pixel_color, display)?;
+        }
+        return Ok(());
+    }
+
+    pub fn write_png<P: AsRef<Path>>(
+        &self,
+        path: P,
+        display: &color::DisplayTransform,
+    ) -> io::Result<()> {
+        let file = io::BufWriter::new(fs::File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+
+        let data: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|pixel_color| display.encode(*pixel_color))
+            .collect();
+        writer.write_image_data(&data).map_err(io::Error::other)?;
+        return Ok(());
+    }
+
+    // Writes linear values as a little-endian color PFM, which stores rows
+    // from the bottom up.
+    pub fn write_pfm<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = io::BufWriter::new(fs::File::create(path)?);
+        write!(out, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let pixel_color = self.get(x, y);
+                for i in 0..3 {
+                    out.write_all(&(pixel_color[i] as f32).to_le_bytes())?;
+                }
+            }
+        }
+        return out.flush();
+    }
+
+    // Writes a PNG, a PFM or a PPM depending on the file extension. PFM files
+    // keep the linear values and ignore the display transform.
+    pub fn save<P: AsRef<Path>>(
+        &self,
+        path: P,
+        display: &color::DisplayTransform,
+    ) -> io::Result<()> {
+        let path = path.as_ref();
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("png") => self.write_png(path, display),
+            Some("pfm") => self.write_pfm(path),
+            _ => {
+                let mut out = io::BufWriter::new(fs::File::create(path)?);
+                self.write_ppm(&mut out, display)?;
+                out.flush()
+            }
+        }
+    }
+}
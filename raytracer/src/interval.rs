@@ -1,6 +1,3 @@
-use core::f64::INFINITY;
-use core::f64::NEG_INFINITY;
-
 pub struct Interval {
     pub min: f64,
     pub max: f64,
@@ -11,6 +8,7 @@ impl Interval {
         Interval { min, max }
     }
 
+    #[allow(dead_code)]
     pub fn contains(&self, x: f64) -> bool {
         return self.min <= x && x <= self.max;
     }
@@ -19,6 +17,7 @@ impl Interval {
         return self.min < x && x < self.max;
     }
 
+    #[allow(dead_code)]
     pub fn clamps(&self, x: f64) -> f64 {
         if x < self.min {
             return self.min;
@@ -30,12 +29,13 @@ impl Interval {
     }
 }
 
-const EMPTY: Interval = Interval {
-    min: INFINITY,
-    max: NEG_INFINITY,
+#[allow(dead_code)]
+pub const EMPTY: Interval = Interval {
+    min: f64::INFINITY,
+    max: f64::NEG_INFINITY,
 };
 
-const UNIVERSE: Interval = Interval {
-    min: NEG_INFINITY,
-    max: INFINITY,
+pub const UNIVERSE: Interval = Interval {
+    min: f64::NEG_INFINITY,
+    max: f64::INFINITY,
 };
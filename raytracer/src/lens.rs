@@ -0,0 +1,300 @@
+use crate::vector;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use vector::Vec3 as Point3;
+
+// One refracting surface (or the aperture stop) of a lens prescription.
+// Lengths are in scene units; lens tables are converted from millimetres
+// assuming the scene is measured in metres.
+#[derive(Clone, Copy)]
+pub struct LensElement {
+    // positive when the centre of curvature lies on the film side, 0 for the stop
+    pub curvature_radius: f64,
+    // axial distance to the next surface towards the film
+    pub thickness: f64,
+    // index of refraction of the medium behind this surface
+    pub eta: f64,
+    pub aperture_radius: f64,
+}
+
+// A sequence of spherical lens surfaces, listed from the object side to the
+// film side. Lens space puts the film at z = 0 with the lens towards -z, so
+// the thickness of the last element is the distance from the rear vertex to
+// the film; focus moves the whole group by changing it.
+#[derive(Clone)]
+pub struct LensSystem {
+    elements: Vec<LensElement>,
+    pub film_diagonal: f64,
+}
+
+impl LensSystem {
+    pub const SCENE_UNITS_PER_MM: f64 = 0.001;
+
+    pub fn new(elements: Vec<LensElement>, film_diagonal: f64) -> Self {
+        LensSystem {
+            elements,
+            film_diagonal,
+        }
+    }
+
+    // Reads a lens table with one surface per line:
+    //     curvature_radius thickness eta aperture_diameter
+    // all lengths in millimetres. A radius of 0 marks the aperture stop and an
+    // eta of 0 means air. Blank lines and lines starting with '#' are skipped.
+    pub fn from_file<P: AsRef<Path>>(path: P, film_diagonal_mm: f64) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut elements = Vec::new();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values = line
+                .split_whitespace()
+                .map(|token| token.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("lens table line {}: {}", line_number + 1, e),
+                    )
+                })?;
+            if values.len() != 4 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "lens table line {}: expected 4 values, got {}",
+                        line_number + 1,
+                        values.len()
+                    ),
+                ));
+            }
+            elements.push(LensElement {
+                curvature_radius: values[0] * Self::SCENE_UNITS_PER_MM,
+                thickness: values[1] * Self::SCENE_UNITS_PER_MM,
+                eta: if values[2] == 0.0 { 1.0 } else { values[2] },
+                aperture_radius: values[3] * Self::SCENE_UNITS_PER_MM / 2.0,
+            });
+        }
+
+        if elements.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "lens table has no elements",
+            ));
+        }
+        return Ok(Self::new(
+            elements,
+            film_diagonal_mm * Self::SCENE_UNITS_PER_MM,
+        ));
+    }
+
+    pub fn rear_z(&self) -> f64 {
+        return -self.elements[self.elements.len() - 1].thickness;
+    }
+
+    pub fn rear_aperture_radius(&self) -> f64 {
+        return self.elements[self.elements.len() - 1].aperture_radius;
+    }
+
+    // Traces a lens space ray leaving the film through every surface. Returns
+    // the ray leaving the front element, or None if an aperture blocks it or
+    // it is totally internally reflected.
+    pub fn trace_from_film(
+        &self,
+        origin: Point3,
+        direction: vector::Vec3,
+    ) -> Option<(Point3, vector::Vec3)> {
+        let mut o = origin;
+        let mut d = direction;
+        let mut element_z = 0.0;
+
+        for i in (0..self.elements.len()).rev() {
+            let element = &self.elements[i];
+            element_z -= element.thickness;
+
+            let is_stop = element.curvature_radius == 0.0;
+            let (t, normal) = if is_stop {
+                if d.z() >= 0.0 {
+                    return None;
+                }
+                ((element_z - o.z()) / d.z(), vector::Vec3::default())
+            } else {
+                let z_center = element_z + element.curvature_radius;
+                Self::intersect_spherical_element(element.curvature_radius, z_center, o, d)?
+            };
+
+            let p_hit = o + d * t;
+            let r2 = p_hit.x() * p_hit.x() + p_hit.y() * p_hit.y();
+            if r2 > element.aperture_radius * element.aperture_radius {
+                return None;
+            }
+            o = p_hit;
+
+            if !is_stop {
+                let eta_i = element.eta;
+                let eta_t = if i > 0 { self.elements[i - 1].eta } else { 1.0 };
+                d = Self::refract(-d.unit_vector(), normal, eta_i / eta_t)?;
+            }
+        }
+
+        return Some((o, d));
+    }
+
+    // Film point in lens space for a pixel position in [0, 1]^2 measured from
+    // the top left. The lens inverts the image, so the film is flipped.
+    pub fn film_point(&self, s: f64, t: f64, aspect_ratio: f64) -> Point3 {
+        let film_height = self.film_diagonal / (1.0 + aspect_ratio * aspect_ratio).sqrt();
+        let film_width = film_height * aspect_ratio;
+        return Point3::new(-(s - 0.5) * film_width, (t - 0.5) * film_height, 0.0);
+    }
+
+    // Moves the lens group so that a point focus_distance in front of the film
+    // is in focus, by bisecting on the rear vertex to film distance. Returns
+    // false, leaving the lens unchanged, if no such position is found.
+    pub fn focus(&mut self, focus_distance: f64) -> bool {
+        let last = self.elements.len() - 1;
+        let original = self.elements[last].thickness;
+        let target = 1.0 / focus_distance;
+
+        let mut lo = 1e-6;
+        let mut hi = original.max(1e-3);
+        let mut expansions = 0;
+        loop {
+            match self.focus_vergence(hi) {
+                Some(v) if v > target => break,
+                _ if expansions < 32 => {
+                    hi *= 2.0;
+                    expansions += 1;
+                }
+                _ => {
+                    self.elements[last].thickness = original;
+                    return false;
+                }
+            }
+        }
+
+        for _ in 0..64 {
+            let mid = 0.5 * (lo + hi);
+            match self.focus_vergence(mid) {
+                Some(v) if v > target => hi = mid,
+                _ => lo = mid,
+            }
+        }
+
+        self.elements[last].thickness = 0.5 * (lo + hi);
+        return true;
+    }
+
+    // Inverse distance from the film to where a near-axis ray from the film
+    // centre crosses the axis in front of the lens, with the rear vertex at
+    // film_distance. Zero when the lens is focused at infinity, negative for
+    // a virtual focus.
+    fn focus_vergence(&mut self, film_distance: f64) -> Option<f64> {
+        let last = self.elements.len() - 1;
+        self.elements[last].thickness = film_distance;
+
+        let height = 0.05 * self.rear_aperture_radius();
+        let (o, d) = self.trace_from_film(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(height, 0.0, self.rear_z()),
+        )?;
+        if d.x().abs() < 1e-12 {
+            return Some(0.0);
+        }
+        let z_cross = o.z() - o.x() * d.z() / d.x();
+        return Some(-1.0 / z_cross);
+    }
+
+    fn intersect_spherical_element(
+        radius: f64,
+        z_center: f64,
+        origin: Point3,
+        direction: vector::Vec3,
+    ) -> Option<(f64, vector::Vec3)> {
+        let o = origin - Point3::new(0.0, 0.0, z_center);
+        let a = direction.length_squared();
+        let half_b = vector::dot(&o, &direction);
+        let c = o.length_squared() - radius * radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let sqrtd = discriminant.sqrt();
+        let t0 = (-half_b - sqrtd) / a;
+        let t1 = (-half_b + sqrtd) / a;
+
+        // pick the intersection on the cap facing the vertex
+        let use_closer_t = (direction.z() > 0.0) ^ (radius < 0.0);
+        let t = if use_closer_t { t0.min(t1) } else { t0.max(t1) };
+        if t < 0.0 {
+            return None;
+        }
+
+        let mut normal = (o + direction * t).unit_vector();
+        if vector::dot(&normal, &-direction) < 0.0 {
+            normal = -normal;
+        }
+        return Some((t, normal));
+    }
+
+    // Refracts wi (pointing away from the surface, on the same side as n) with
+    // relative index eta = eta_i / eta_t.
+    fn refract(wi: vector::Vec3, n: vector::Vec3, eta: f64) -> Option<vector::Vec3> {
+        let cos_theta_i = vector::dot(&n, &wi);
+        let sin2_theta_i = (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+        let sin2_theta_t = eta * eta * sin2_theta_i;
+        if sin2_theta_t >= 1.0 {
+            return None;
+        }
+        let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+        return Some(-wi * eta + n * (eta * cos_theta_i - cos_theta_t));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn double_gauss() -> LensSystem {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("lenses/dgauss_50mm.txt");
+        return LensSystem::from_file(path, 43.27).unwrap();
+    }
+
+    #[test]
+    fn reads_lens_tables() {
+        let lens = double_gauss();
+        assert!(lens.elements.len() == 11);
+        // the stop keeps air behind it
+        assert!(lens.elements[5].curvature_radius == 0.0 && lens.elements[5].eta == 1.0);
+        assert!((lens.elements[0].aperture_radius - 0.0126).abs() < 1e-12);
+        assert!((lens.film_diagonal - 0.04327).abs() < 1e-12);
+
+        let path = std::env::temp_dir().join("raytracer_short_lens_table.txt");
+        fs::write(&path, "# radius thickness eta\n29.475 3.76 1.67\n").unwrap();
+        let error = LensSystem::from_file(&path, 43.27).err().unwrap();
+        assert!(error.kind() == io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn focus_images_the_focus_distance_onto_the_film() {
+        for distance in [0.5, 3.4, 100.0] {
+            let mut lens = double_gauss();
+            assert!(lens.focus(distance));
+            // a near-axis ray from the film centre crosses the axis at the
+            // focus distance
+            let vergence = lens.focus_vergence(-lens.rear_z()).unwrap();
+            assert!((1.0 / vergence - distance).abs() < 1e-6 * distance.max(1.0));
+        }
+        // focusing nearer moves the lens away from the film
+        let (mut near, mut far) = (double_gauss(), double_gauss());
+        near.focus(0.5);
+        far.focus(100.0);
+        assert!(near.rear_z() < far.rear_z());
+    }
+}
@@ -1,88 +1,774 @@
+// Functions end in an explicit `return` throughout the code base.
+#![allow(clippy::needless_return)]
+
+mod animation;
+mod aov;
+mod aperture;
 mod camera;
+mod checkpoint;
 mod color;
+mod compare;
+mod constant_medium;
+mod csg;
+mod debug_view;
+mod denoise;
+mod film;
+mod golden;
+mod heterogeneous_medium;
 mod hittable;
 mod hittable_list;
+mod image;
 mod interval;
+mod lens;
 mod material;
+mod path_debug;
+mod perlin;
+mod physical_camera;
+mod progress;
+mod progressive;
 mod ray;
+mod scenes;
+mod sdf;
+mod spectrum;
+mod stats;
+mod stereo;
 mod utility;
+mod validation;
 mod vector;
 
-use core::f64::INFINITY;
-use core::f64::NEG_INFINITY;
-use std::boxed::Box;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
 use std::vec::Vec;
 
-use utility::random_double;
-use vector::Vec3 as Color;
 use vector::Vec3 as Point3;
 
 fn main() {
-    // Materials
-    let mut mat_ground =
-        material::Material::Lambertian(material::Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    // World
-    let world_list = Vec::new();
-    let mut world = hittable_list::HittableList::new(world_list);
-
-    world.add(Box::new(hittable::Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        mat_ground,
-    )));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = utility::random_double(0.0, 1.0);
-            let center = Point3::new(
-                a as f64 + 0.9 * random_double(0.0, 1.0),
-                0.2,
-                b as f64 + 0.9 * random_double(0.0, 1.0),
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if args.first().map(|arg| arg.as_str()) == Some("compare") {
+        process::exit(compare_command(&args[1..]));
+    }
+    if args.first().map(|arg| arg.as_str()) == Some("denoise") {
+        if let Err(e) = denoise_command(&args[1..]) {
+            eprintln!("Denoising failed: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if args.first().map(|arg| arg.as_str()) == Some("bless") {
+        // raytracer bless [DIR] writes the golden images of the test harness
+        let output_dir = args.get(1).map_or(golden::golden_dir(), PathBuf::from);
+        if let Err(e) = golden::bless(&output_dir) {
+            eprintln!("Failed to write golden images: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if args.first().map(|arg| arg.as_str()) == Some("validate") {
+        // raytracer validate [--seed N] checks the sampling of every material
+        process::exit(validate_command(&args[1..]));
+    }
+
+    if let Some(seed) = option_value(&args, "--seed") {
+        utility::seed(seed.parse().unwrap_or_else(|_| {
+            eprintln!("Invalid seed: {}", seed);
+            process::exit(2);
+        }));
+    }
+    // the image goes to stdout, so --stats always names a file
+    if option_value(&args, "--stats") == Some("-") {
+        eprintln!("--stats needs a file path, stdout carries the image");
+        process::exit(2);
+    }
+    let mut summary = stats::Summary::new();
+    // --density-grid PATH fills the cloud with a grid file instead of noise;
+    // raw grids also need --grid-size NX,NY,NZ
+    let density_grid = option_value(&args, "--density-grid");
+    let scene_name = option_value(&args, "--scene").unwrap_or(if density_grid.is_some() {
+        "cloud"
+    } else {
+        "random_spheres"
+    });
+    if density_grid.is_some() && scene_name != "cloud" {
+        eprintln!("A density grid only applies to the cloud scene");
+        process::exit(2);
+    }
+    let grid_size = option_value(&args, "--grid-size").map(|size| {
+        let dims: Vec<usize> = size
+            .split(',')
+            .map(|dim| dim.trim().parse())
+            .collect::<Result<_, _>>()
+            .unwrap_or_default();
+        if dims.len() != 3 {
+            eprintln!("Invalid grid size: {} (expected NX,NY,NZ)", size);
+            process::exit(2);
+        }
+        [dims[0], dims[1], dims[2]]
+    });
+    let built = summary.time("scene build", || match density_grid {
+        Some(path) => scenes::cloud_from_grid(path, grid_size).map(Some),
+        None => Ok(scenes::preset(scene_name)),
+    });
+    let scene = built.unwrap_or_else(|e| {
+        eprintln!(
+            "Failed to read density grid {}: {}",
+            density_grid.unwrap_or_default(),
+            e
+        );
+        process::exit(1);
+    });
+    let Some(scene) = scene else {
+        eprintln!(
+            "Unknown scene: {} (expected one of {})",
+            scene_name,
+            scenes::PRESETS.join(", ")
+        );
+        process::exit(2);
+    };
+    let mut world = scene.world;
+    let materials = scene.materials;
+    let mut cam = scene.camera;
+    if args.iter().any(|arg| arg == "--quiet") {
+        cam.progress.verbosity = progress::Verbosity::Quiet;
+    } else if args.iter().any(|arg| arg == "--verbose") {
+        cam.progress.verbosity = progress::Verbosity::Verbose;
+    }
+    // one JSON object per line on stderr, for wrappers that track renders
+    cam.progress.json = args.iter().any(|arg| arg == "--progress-json");
+
+    if let Some(samples) = option_value(&args, "--samples") {
+        cam.samples_per_pixel = samples
+            .parse()
+            .ok()
+            .filter(|&samples| samples > 0)
+            .unwrap_or_else(|| {
+                eprintln!("Invalid sample count: {}", samples);
+                process::exit(2);
+            });
+    }
+    // trace sampled wavelengths, so dispersive glass splits light
+    if args.iter().any(|arg| arg == "--spectral") {
+        cam.spectral = true;
+    }
+    if let Some(stops) = option_value(&args, "--exposure") {
+        cam.display.exposure_stops = stops.parse().unwrap_or_else(|_| {
+            eprintln!("Invalid exposure: {}", stops);
+            process::exit(2);
+        });
+    }
+    if let Some(name) = option_value(&args, "--tonemap") {
+        cam.display.tone_map = color::ToneMap::from_name(name).unwrap_or_else(|| {
+            eprintln!(
+                "Unknown tone mapping operator: {} (expected clamp, reinhard, \
+                 extended-reinhard, aces or agx)",
+                name
+            );
+            process::exit(2);
+        });
+    }
+    cam.display.dither = args.iter().any(|arg| arg == "--dither");
+    // --projection NAME[:SIZE], where SIZE is the view width of an
+    // orthographic projection and the field of view of a fisheye
+    if let Some(projection) = option_value(&args, "--projection") {
+        let (name, size) = match projection.split_once(':') {
+            Some((name, size)) => (
+                name,
+                Some(
+                    size.parse()
+                        .ok()
+                        .filter(|&size: &f64| size > 0.0)
+                        .unwrap_or_else(|| {
+                            eprintln!("Invalid projection size: {}", size);
+                            process::exit(2);
+                        }),
+                ),
+            ),
+            None => (projection, None),
+        };
+        cam.projection = camera::Projection::from_name(name, size).unwrap_or_else(|| {
+            eprintln!(
+                "Unknown projection: {} (expected perspective, orthographic, fisheye, \
+                 fisheye-equisolid or equirectangular)",
+                name
             );
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.8 {
-                    let albedo = Color::random(0.0, 1.0) * Color::random(0.0, 1.0);
-                    let mat = material::Material::Lambertian(material::Lambertian::new(albedo));
-                    world.add(Box::new(hittable::Sphere::new(center, 0.2, mat)));
-                } else if choose_mat < 0.95 {
-                    let albedo = Color::random(0.5, 1.0);
-                    let fuzz = random_double(0.0, 0.5);
-                    let mat = material::Material::Metal(material::Metal::new(albedo, fuzz));
-                    world.add(Box::new(hittable::Sphere::new(center, 0.2, mat)));
-                } else {
-                    let mat = material::Material::Dielectric(material::Dielectric::new(1.5));
-                    world.add(Box::new(hittable::Sphere::new(center, 0.2, mat)));
+            process::exit(2);
+        });
+    }
+    // --aperture BLADES[:ROTATION] shapes the defocus blur of the thin lens
+    // as a polygon, rotated by ROTATION degrees, and --aperture-mask PATH as a
+    // grayscale PNM image
+    if let Some(aperture) = option_value(&args, "--aperture") {
+        let (blades, rotation) = aperture.split_once(':').unwrap_or((aperture, "0"));
+        let blades = blades.parse().ok().filter(|&blades: &u32| blades >= 3);
+        let (Some(blades), Ok(rotation)) = (blades, rotation.parse()) else {
+            eprintln!(
+                "Invalid aperture: {} (expected BLADES[:ROTATION] with at least 3 blades)",
+                aperture
+            );
+            process::exit(2);
+        };
+        cam.aperture = aperture::Aperture::Polygon { blades, rotation };
+    }
+    if let Some(path) = option_value(&args, "--aperture-mask") {
+        let mask = aperture::ApertureMask::from_file(path).unwrap_or_else(|e| {
+            eprintln!("Failed to read aperture mask {}: {}", path, e);
+            process::exit(1);
+        });
+        cam.aperture = aperture::Aperture::Mask(mask);
+    }
+    // --camera-exposure ISO,SHUTTER,F_STOP exposes the image the way a
+    // photographer would, with the f-stop setting the defocus blur, and
+    // --vignetting, --distortion K1[,K2,K3,P1,P2] and --chromatic-aberration
+    // AMOUNT add the imperfections of a real lens
+    let numbers = |list: &str| -> Option<Vec<f64>> {
+        return list
+            .split(',')
+            .map(|field| field.trim().parse().ok())
+            .collect();
+    };
+    if let Some(settings) = option_value(&args, "--camera-exposure") {
+        let values = numbers(settings).unwrap_or_default();
+        if values.len() != 3 || values.iter().any(|&value| value <= 0.0) {
+            eprintln!(
+                "Invalid camera exposure: {} (expected ISO,SHUTTER,F_STOP)",
+                settings
+            );
+            process::exit(2);
+        }
+        cam.exposure = Some(physical_camera::Exposure::new(
+            values[0], values[1], values[2],
+        ));
+    }
+    cam.vignetting = args.iter().any(|arg| arg == "--vignetting");
+    if let Some(terms) = option_value(&args, "--distortion") {
+        let values = numbers(terms).unwrap_or_default();
+        if values.is_empty() || values.len() > 5 {
+            eprintln!("Invalid distortion: {} (expected K1[,K2,K3,P1,P2])", terms);
+            process::exit(2);
+        }
+        let term = |index: usize| values.get(index).copied().unwrap_or(0.0);
+        cam.distortion = physical_camera::LensDistortion {
+            k1: term(0),
+            k2: term(1),
+            k3: term(2),
+            p1: term(3),
+            p2: term(4),
+        };
+    }
+    if let Some(amount) = option_value(&args, "--chromatic-aberration") {
+        cam.chromatic_aberration = amount.parse().unwrap_or_else(|_| {
+            eprintln!("Invalid chromatic aberration: {}", amount);
+            process::exit(2);
+        });
+    }
+    // --lens PATH traces rays through a lens table, see LensSystem::from_file,
+    // in front of a film of --film-diagonal millimetres (35 mm full frame by
+    // default)
+    if let Some(path) = option_value(&args, "--lens") {
+        let film_diagonal = option_value(&args, "--film-diagonal").map_or(43.27, |s| {
+            s.parse()
+                .ok()
+                .filter(|&diagonal: &f64| diagonal > 0.0)
+                .unwrap_or_else(|| {
+                    eprintln!("Invalid film diagonal: {}", s);
+                    process::exit(2);
+                })
+        });
+        cam.lens = Some(
+            lens::LensSystem::from_file(path, film_diagonal).unwrap_or_else(|e| {
+                eprintln!("Failed to read lens {}: {}", path, e);
+                process::exit(1);
+            }),
+        );
+    }
+    let radius = option_value(&args, "--filter-radius").map(|radius| {
+        radius
+            .parse()
+            .ok()
+            .filter(|&radius: &f64| radius > 0.0 && radius.is_finite())
+            .unwrap_or_else(|| {
+                eprintln!("Invalid filter radius: {}", radius);
+                process::exit(2);
+            })
+    });
+    if radius.is_some() && option_value(&args, "--filter").is_none() {
+        eprintln!("--filter-radius needs --filter to choose the filter it sizes");
+        process::exit(2);
+    }
+    if let Some(name) = option_value(&args, "--filter") {
+        cam.filter = film::Filter::from_name(name, radius).unwrap_or_else(|| {
+            eprintln!(
+                "Unknown filter: {} (expected box, tent, gaussian, mitchell or lanczos)",
+                name
+            );
+            process::exit(2);
+        });
+    }
+    if let Some(name) = option_value(&args, "--debug-view") {
+        cam.debug_view = Some(debug_view::DebugView::from_name(name).unwrap_or_else(|| {
+            eprintln!(
+                "Unknown debug view: {} (expected one of {})",
+                name,
+                debug_view::NAMES.join(", ")
+            );
+            process::exit(2);
+        }));
+    }
+
+    // --checkpoint PATH saves the progress of the render there, --resume PATH
+    // continues from a saved one, writing back to it unless --checkpoint
+    // names another file
+    let resume = option_value(&args, "--resume");
+    let checkpoint_path = option_value(&args, "--checkpoint").or(resume);
+    let checkpoint_interval = option_value(&args, "--checkpoint-interval").map_or(60.0, |s| {
+        s.parse()
+            .ok()
+            .filter(|&seconds: &f64| seconds >= 0.0)
+            .unwrap_or_else(|| {
+                eprintln!("Invalid checkpoint interval: {}", s);
+                process::exit(2);
+            })
+    });
+    if checkpoint_path.is_some()
+        && ["--animate", "--aov-dir", "--denoise"]
+            .iter()
+            .any(|flag| args.iter().any(|arg| arg == flag))
+    {
+        eprintln!("Checkpoints only cover still renders without --aov-dir or --denoise");
+        process::exit(2);
+    }
+
+    // --time-budget SECONDS and --noise-target NOISE render in passes until
+    // either is reached, or --samples if given, writing the image to
+    // --preview PATH after every pass
+    let parse_limit = |name: &str| {
+        return option_value(&args, name).map(|s| {
+            s.parse()
+                .ok()
+                .filter(|&limit: &f64| limit > 0.0)
+                .unwrap_or_else(|| {
+                    eprintln!("Invalid {}: {}", &name[2..].replace('-', " "), s);
+                    process::exit(2);
+                })
+        });
+    };
+    let time_budget = parse_limit("--time-budget");
+    let noise_target = parse_limit("--noise-target");
+    let budget = (time_budget.is_some() || noise_target.is_some()).then(|| progressive::Budget {
+        time: time_budget.map(Duration::from_secs_f64),
+        noise: noise_target,
+        samples: option_value(&args, "--samples").map(|_| cam.samples_per_pixel),
+    });
+    let preview = option_value(&args, "--preview").unwrap_or("preview.png");
+    if budget.is_some()
+        && (checkpoint_path.is_some()
+            || ["--animate", "--aov-dir", "--denoise"]
+                .iter()
+                .any(|flag| args.iter().any(|arg| arg == flag)))
+    {
+        eprintln!("Progressive renders only cover still images without checkpoints, --aov-dir or --denoise");
+        process::exit(2);
+    }
+
+    // --stereo side-by-side|top-bottom renders an image per eye, --ipd apart
+    // and toed in to meet at --convergence, or with --ods an omni-directional
+    // stereo panorama per eye
+    let stereo = option_value(&args, "--stereo").map(|name| {
+        stereo::StereoLayout::from_name(name).unwrap_or_else(|| {
+            eprintln!(
+                "Unknown stereo layout: {} (expected side-by-side or top-bottom)",
+                name
+            );
+            process::exit(2);
+        })
+    });
+    let interpupillary_distance = parse_limit("--ipd");
+    let convergence_distance = parse_limit("--convergence");
+    if stereo.is_some()
+        && (checkpoint_path.is_some()
+            || budget.is_some()
+            || ["--animate", "--aov-dir", "--denoise"]
+                .iter()
+                .any(|flag| args.iter().any(|arg| arg == flag)))
+    {
+        eprintln!("Stereo renders only cover still images without checkpoints, progressive passes, --aov-dir or --denoise");
+        process::exit(2);
+    }
+
+    if let Some(pixel) = option_value(&args, "--debug-pixel") {
+        // --debug-pixel X,Y[,SAMPLE] prints the paths of every sample of the
+        // pixel, or of one, as JSON instead of rendering
+        let fields: Vec<i32> = pixel
+            .split(',')
+            .map(|field| field.trim().parse())
+            .collect::<Result<_, _>>()
+            .unwrap_or_default();
+        if fields.len() != 2 && fields.len() != 3 || fields.get(2).is_some_and(|&s| s < 0) {
+            eprintln!("Invalid pixel: {} (expected X,Y or X,Y,SAMPLE)", pixel);
+            process::exit(2);
+        }
+        // the dump follows the RGB paths, which a spectral render never takes
+        if cam.spectral {
+            eprintln!("--debug-pixel traces RGB paths and cannot be combined with --spectral");
+            process::exit(2);
+        }
+        let (x, y) = (fields[0], fields[1]);
+        let samples = match fields.get(2) {
+            Some(&sample) => sample..sample + 1,
+            None => 0..cam.samples_per_pixel,
+        };
+        let Some(paths) = cam.trace_pixel(&mut world, &materials, x, y, samples) else {
+            eprintln!("Pixel {},{} is outside the image", x, y);
+            process::exit(2);
+        };
+        print!("{}", path_debug::to_json(x, y, &paths));
+        return;
+    }
+
+    if let Some(output_dir) = option_value(&args, "--animate") {
+        // a half orbit around the scene, two seconds long, interpolated as
+        // --interpolation says and blurred over the --shutter fraction of
+        // every frame
+        let interpolation = option_value(&args, "--interpolation").map_or(
+            animation::Interpolation::CatmullRom,
+            |name| {
+                animation::Interpolation::from_name(name).unwrap_or_else(|| {
+                    eprintln!(
+                        "Unknown interpolation: {} (expected linear or catmull-rom)",
+                        name
+                    );
+                    process::exit(2);
+                })
+            },
+        );
+        let mut animation = animation::CameraAnimation::new(24.0, interpolation);
+        if let Some(shutter) = option_value(&args, "--shutter") {
+            animation.shutter = shutter
+                .parse()
+                .ok()
+                .filter(|shutter: &f64| (0.0..=1.0).contains(shutter))
+                .unwrap_or_else(|| {
+                    eprintln!("Invalid shutter: {} (expected 0 to 1)", shutter);
+                    process::exit(2);
+                });
+        }
+        let radius = (13.0f64 * 13.0 + 3.0 * 3.0).sqrt();
+        let start = 3.0f64.atan2(13.0);
+        for key in 0..5 {
+            let angle = start + key as f64 * std::f64::consts::PI / 4.0;
+            cam.look_from = Point3::new(radius * angle.cos(), 2.0, radius * angle.sin());
+            animation.add_keyframe(animation::Keyframe::from_camera(key as f64 * 0.5, &cam));
+        }
+        // frames are written as they finish, so this counts as rendering
+        let written = summary.time("render", || {
+            animation.render_sequence(&mut cam, &mut world, &materials, output_dir)
+        });
+        if let Err(e) = written {
+            eprintln!("Failed to write image sequence: {}", e);
+            process::exit(1);
+        }
+        report_stats(&mut summary, cam.progress, option_value(&args, "--stats"));
+        return;
+    }
+
+    let aov_dir = option_value(&args, "--aov-dir");
+    let denoise = args.iter().any(|arg| arg == "--denoise");
+    if aov_dir.is_some() || denoise {
+        // the final image still goes to stdout; beauty.pfm keeps it unclipped
+        let (image, aovs) = summary.time("render", || {
+            cam.render_image_with_aovs(&mut world, &materials)
+        });
+        let output = if denoise {
+            summary
+                .time("denoise", || {
+                    denoise::Denoiser::new().apply(&image, &aovs.albedo, &aovs.normal, &aovs.depth)
+                })
+                .unwrap_or_else(|e| {
+                    eprintln!("Failed to denoise: {}", e);
+                    process::exit(1);
+                })
+        } else {
+            image.clone()
+        };
+        let written = summary.time("output", || {
+            if let Some(output_dir) = aov_dir {
+                aovs.save(output_dir)?;
+                image.write_pfm(Path::new(output_dir).join("beauty.pfm"))?;
+            }
+            return output.write_ppm(&mut io::stdout().lock(), &cam.display);
+        });
+        if let Err(e) = written {
+            eprintln!("Failed to write output buffers: {}", e);
+            process::exit(1);
+        }
+        report_stats(&mut summary, cam.progress, option_value(&args, "--stats"));
+        return;
+    }
+
+    let image = match (checkpoint_path, budget, stereo) {
+        (Some(path), _, _) => {
+            let (mut checkpoint, film) = match resume {
+                Some(resume) => {
+                    let (checkpoint, film) =
+                        checkpoint::Checkpoint::load(resume, cam.filter, cam.image_size())
+                            .unwrap_or_else(|e| {
+                                eprintln!("Failed to read checkpoint {}: {}", resume, e);
+                                process::exit(1);
+                            });
+                    if checkpoint.scene != scene_name {
+                        eprintln!(
+                            "Checkpoint {} belongs to the scene {}",
+                            resume, checkpoint.scene
+                        );
+                        process::exit(2);
+                    }
+                    (checkpoint, Some(film))
+                }
+                None => (checkpoint::Checkpoint::new(scene_name), None),
+            };
+            if let Err(e) = checkpoint::catch_interrupts() {
+                eprintln!("Failed to catch Ctrl-C: {}", e);
+                process::exit(1);
+            }
+            let rendered = summary.time("render", || {
+                cam.render_checkpointed(
+                    &mut world,
+                    &materials,
+                    &mut checkpoint,
+                    film,
+                    Path::new(path),
+                    Duration::from_secs_f64(checkpoint_interval),
+                )
+            });
+            match rendered {
+                Ok(Some(image)) => image,
+                Ok(None) => {
+                    eprintln!(
+                        "Interrupted; continue with --resume {} (and the same options)",
+                        path
+                    );
+                    process::exit(130);
+                }
+                Err(e) => {
+                    eprintln!("Checkpointed render failed: {}", e);
+                    process::exit(1);
                 }
             }
         }
+        (None, Some(budget), _) => {
+            if let Err(e) = checkpoint::catch_interrupts() {
+                eprintln!("Failed to catch Ctrl-C: {}", e);
+                process::exit(1);
+            }
+            let display = cam.display;
+            let rendered = summary.time("render", || {
+                cam.render_progressive(&mut world, &materials, &budget, &mut |image, _| {
+                    return image.save(preview, &display);
+                })
+            });
+            match rendered {
+                Ok((image, stop)) => {
+                    stop.report(cam.progress);
+                    image
+                }
+                Err(e) => {
+                    eprintln!("Failed to write preview {}: {}", preview, e);
+                    process::exit(1);
+                }
+            }
+        }
+        (None, None, Some(layout)) => {
+            let mut rig = stereo::StereoCamera::new(cam);
+            rig.layout = layout;
+            if args.iter().any(|arg| arg == "--ods") {
+                rig.mode = stereo::StereoMode::OmniDirectional;
+            }
+            if let Some(distance) = interpupillary_distance {
+                rig.interpupillary_distance = distance;
+            }
+            if let Some(distance) = convergence_distance {
+                rig.convergence_distance = distance;
+            }
+            let image = summary.time("render", || rig.render_image(&mut world, &materials));
+            cam = rig.camera;
+            image
+        }
+        (None, None, None) => summary.time("render", || cam.render_image(&mut world, &materials)),
+    };
+    let written = summary.time("output", || {
+        image.write_ppm(&mut io::stdout().lock(), &cam.display)
+    });
+    if let Err(e) = written {
+        eprintln!("Failed to write image to stdout: {}", e);
+        process::exit(1);
     }
+    report_stats(&mut summary, cam.progress, option_value(&args, "--stats"));
+}
+
+// Prints the statistics of the run to stderr unless quiet and, given a path,
+// writes them there as JSON.
+fn report_stats(summary: &mut stats::Summary, style: progress::Style, json_path: Option<&str>) {
+    summary.finish();
+    if style.verbosity > progress::Verbosity::Quiet {
+        if style.json {
+            // the same object as --stats, on a single line
+            let json = summary.to_json();
+            let stats: Vec<&str> = json.lines().map(str::trim).collect();
+            eprintln!("{{\"event\": \"summary\", \"stats\": {}}}", stats.join(" "));
+        } else {
+            eprint!("{}", summary.to_text());
+        }
+    }
+    if let Some(path) = json_path {
+        if let Err(e) = fs::write(path, summary.to_json()) {
+            eprintln!("Failed to write {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+// raytracer compare REFERENCE TEST [--diff PATH] [--metric NAME] [--threshold VALUE]
+// Prints the difference metrics of two images and optionally writes a false
+// color map of the perceptual error. Returns the exit code: 1 when the metric
+// (flip by default) is worse than the threshold, 2 for invalid arguments or
+// unreadable images.
+fn compare_command(args: &[String]) -> i32 {
+    let paths: Vec<&String> = args
+        .iter()
+        .enumerate()
+        .filter(|(i, arg)| !arg.starts_with("--") && (*i == 0 || !args[i - 1].starts_with("--")))
+        .map(|(_, arg)| arg)
+        .collect();
+    if paths.len() != 2 {
+        eprintln!(
+            "usage: raytracer compare REFERENCE TEST [--diff PATH] [--metric NAME] \
+             [--threshold VALUE]"
+        );
+        return 2;
+    }
+
+    let load =
+        |path: &str| compare::load(path).map_err(|e| eprintln!("Failed to read {}: {}", path, e));
+    let (Ok(reference), Ok(test)) = (load(paths[0]), load(paths[1])) else {
+        return 2;
+    };
+    if reference.width != test.width || reference.height != test.height {
+        eprintln!(
+            "Image sizes differ: {}x{} and {}x{}",
+            reference.width, reference.height, test.width, test.height
+        );
+        return 2;
+    }
+
+    let (metrics, error_map) = compare::compare(&reference, &test);
+    println!("MSE   {:.6e}", metrics.mse);
+    println!("RMSE  {:.6}", metrics.rmse);
+    println!("PSNR  {:.3} dB", metrics.psnr);
+    println!("SSIM  {:.6}", metrics.ssim);
+    println!("FLIP  {:.6}", metrics.flip);
+
+    if let Some(path) = option_value(args, "--diff") {
+        let diff = compare::false_color(&error_map, reference.width, reference.height);
+        if let Err(e) = diff.save(path, &color::DisplayTransform::new()) {
+            eprintln!("Failed to write {}: {}", path, e);
+            return 2;
+        }
+    }
+
+    let Some(threshold) = option_value(args, "--threshold") else {
+        return 0;
+    };
+    let Ok(threshold) = threshold.parse::<f64>() else {
+        eprintln!("Invalid threshold: {}", threshold);
+        return 2;
+    };
+    let metric = option_value(args, "--metric").unwrap_or("flip");
+    let Some(value) = metrics.get(metric) else {
+        eprintln!(
+            "Unknown metric: {} (expected mse, rmse, psnr, ssim or flip)",
+            metric
+        );
+        return 2;
+    };
+    let failed = if compare::Metrics::higher_is_better(metric) {
+        value < threshold
+    } else {
+        value > threshold
+    };
+    if failed {
+        eprintln!(
+            "{} of {} is outside the threshold of {}",
+            metric, value, threshold
+        );
+        return 1;
+    }
+    return 0;
+}
+
+// raytracer denoise DIR [OUTPUT]
+// Filters DIR/beauty.pfm guided by the buffers written with --aov-dir, saving
+// the result to OUTPUT (DIR/denoised.pfm by default).
+fn denoise_command(args: &[String]) -> io::Result<()> {
+    let Some(dir) = args.first() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "usage: raytracer denoise DIR [OUTPUT]",
+        ));
+    };
+    let dir = Path::new(dir);
+    let beauty = image::Image::read_pfm(dir.join("beauty.pfm"))?;
+    let albedo = image::Image::read_pfm(dir.join("albedo.pfm"))?;
+    let normal = image::Image::read_pfm(dir.join("normal.pfm"))?;
+    let depth = image::Image::read_pfm(dir.join("depth.pfm"))?;
+
+    let denoised = denoise::Denoiser::new().apply(&beauty, &albedo, &normal, &depth)?;
+    let output = args.get(1).map_or(dir.join("denoised.pfm"), PathBuf::from);
+    return denoised.save(output, &color::DisplayTransform::new());
+}
+
+// raytracer validate [--seed N]
+// Prints the white furnace, chi-square and reciprocity checks of every
+// material. Exits with 1 when any check fails.
+fn validate_command(args: &[String]) -> i32 {
+    let seed = option_value(args, "--seed").unwrap_or("1");
+    let Ok(seed) = seed.parse() else {
+        eprintln!("Invalid seed: {}", seed);
+        return 2;
+    };
+    utility::seed(seed);
+
+    let reports = validation::Validator::new().run();
+    let mut failures = 0;
+    for report in &reports {
+        let status = match report.status {
+            validation::Status::Passed => "pass",
+            validation::Status::Failed => "FAIL",
+            validation::Status::Skipped => "skip",
+        };
+        if report.status == validation::Status::Failed {
+            failures += 1;
+        }
+        println!(
+            "{:<4}  {:<28} {:<14} {}",
+            status, report.material, report.check, report.detail
+        );
+    }
+    if failures > 0 {
+        eprintln!("{} of {} checks failed", failures, reports.len());
+        return 1;
+    }
+    return 0;
+}
 
-    let mat1 = material::Material::Dielectric(material::Dielectric::new(1.5));
-    world.add(Box::new(hittable::Sphere::new(
-        Point3::new(0.0, 1.0, 0.0),
-        1.0,
-        mat1,
-    )));
-
-    let mat2 = material::Material::Lambertian(material::Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.add(Box::new(hittable::Sphere::new(
-        Point3::new(-4.0, 1.0, 0.0),
-        1.0,
-        mat2,
-    )));
-
-    let mat3 = material::Material::Metal(material::Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Box::new(hittable::Sphere::new(
-        Point3::new(4.0, 1.0, 0.0),
-        1.0,
-        mat3,
-    )));
-
-    // Camera
-    let mut cam = camera::Camera::new();
-    cam.look_from = Point3::new(13.0, 2.0, 3.0);
-    cam.look_at = Point3::new(0.0, 0.0, 0.0);
-    cam.defocus_angle = 0.6;
-    cam.focus_dist = 10.0;
-    cam.render(&mut world);
+// Returns the value following a "--name value" option.
+fn option_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let index = args.iter().position(|arg| arg == name)?;
+    return args.get(index + 1).map(|value| value.as_str());
 }
@@ -3,13 +3,41 @@ use crate::ray;
 use crate::utility;
 use crate::vector;
 
+use std::f64::consts::PI;
+
 use vector::Vec3 as Color;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub enum Material {
     Lambertian(Lambertian),
     Metal(Metal),
     Dielectric(Dielectric),
+    Isotropic(Isotropic),
+    HenyeyGreenstein(HenyeyGreenstein),
+}
+
+// How a scatter call picked its direction, reported to the path debugger.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ScatterEvent {
+    Diffuse,
+    Reflection,
+    Refraction,
+    // sampled from the phase function of a medium
+    Phase,
+    // the sampled direction points into the surface, ending the path
+    Absorbed,
+}
+
+impl ScatterEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScatterEvent::Diffuse => "diffuse",
+            ScatterEvent::Reflection => "reflection",
+            ScatterEvent::Refraction => "refraction",
+            ScatterEvent::Phase => "phase",
+            ScatterEvent::Absorbed => "absorbed",
+        }
+    }
 }
 
 impl Material {
@@ -20,15 +48,112 @@ impl Material {
         attenuation: &mut Color,
         scattered: &mut ray::Ray,
     ) -> bool {
-        match self {
+        return self.scatter_event(r_in, rec, attenuation, scattered) != ScatterEvent::Absorbed;
+    }
+
+    // scatter, also telling how the direction was chosen. attenuation and
+    // scattered are filled in even when the path is absorbed.
+    pub fn scatter_event(
+        &self,
+        r_in: &ray::Ray,
+        rec: &hittable::HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut ray::Ray,
+    ) -> ScatterEvent {
+        let event = match self {
             Material::Lambertian(mat) => mat.scatter(r_in, rec, attenuation, scattered),
             Material::Metal(mat) => mat.scatter(r_in, rec, attenuation, scattered),
             Material::Dielectric(mat) => mat.scatter(r_in, rec, attenuation, scattered),
+            Material::Isotropic(mat) => mat.scatter(r_in, rec, attenuation, scattered),
+            Material::HenyeyGreenstein(mat) => mat.scatter(r_in, rec, attenuation, scattered),
+        };
+        // a path keeps its wavelength through every bounce
+        scattered.wavelength = r_in.wavelength;
+        return event;
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Material::Lambertian(_) => "lambertian",
+            Material::Metal(_) => "metal",
+            Material::Dielectric(_) => "dielectric",
+            Material::Isotropic(_) => "isotropic",
+            Material::HenyeyGreenstein(_) => "henyey-greenstein",
+        }
+    }
+
+    // Density per unit solid angle with which scatter picks direction, over
+    // the whole sphere and including directions it then rejects. None for
+    // materials that scatter into a few discrete directions.
+    pub fn pdf(
+        &self,
+        r_in: &ray::Ray,
+        rec: &hittable::HitRecord,
+        direction: vector::Vec3,
+    ) -> Option<f64> {
+        match self {
+            Material::Lambertian(mat) => Some(mat.pdf(rec, direction)),
+            Material::Metal(mat) => mat.pdf(r_in, rec, direction),
+            Material::Dielectric(_) => None,
+            Material::Isotropic(_) => Some(1.0 / (4.0 * PI)),
+            Material::HenyeyGreenstein(mat) => Some(mat.pdf(r_in, direction)),
+        }
+    }
+
+    // Reflectance of the surface, as used for the albedo output buffer.
+    // Specular transmission counts as white.
+    pub fn albedo(&self) -> Color {
+        match self {
+            Material::Lambertian(mat) => mat.albedo,
+            Material::Metal(mat) => mat.albedo,
+            Material::Dielectric(_) => Color::new(1.0, 1.0, 1.0),
+            Material::Isotropic(mat) => mat.albedo,
+            Material::HenyeyGreenstein(mat) => mat.albedo,
+        }
+    }
+
+    // True when the scattered direction depends on the ray's wavelength.
+    pub fn is_dispersive(&self) -> bool {
+        match self {
+            Material::Dielectric(mat) => mat.is_dispersive(),
+            _ => false,
         }
     }
 }
 
-#[derive(Copy, Clone)]
+// Materials live in a scene-level registry and are referenced by id from
+// primitives and hit records, so they never have to be copied per hit.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct MaterialId(usize);
+
+impl MaterialId {
+    pub fn index(&self) -> usize {
+        return self.0;
+    }
+}
+
+pub struct MaterialList {
+    materials: Vec<Material>,
+}
+
+impl MaterialList {
+    pub fn new() -> Self {
+        MaterialList {
+            materials: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, mat: Material) -> MaterialId {
+        self.materials.push(mat);
+        return MaterialId(self.materials.len() - 1);
+    }
+
+    pub fn get(&self, id: MaterialId) -> &Material {
+        return &self.materials[id.0];
+    }
+}
+
+#[derive(Clone)]
 pub struct Lambertian {
     albedo: Color,
 }
@@ -40,21 +165,27 @@ impl Lambertian {
 
     pub fn scatter(
         &self,
-        r_in: &ray::Ray,
+        _r_in: &ray::Ray,
         rec: &hittable::HitRecord,
         attenuation: &mut Color,
         scattered: &mut ray::Ray,
-    ) -> bool {
+    ) -> ScatterEvent {
         let mut scatter_direction = rec.normal + vector::Vec3::random_unit_vector();
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
         *scattered = ray::Ray::new(rec.p, scatter_direction);
         *attenuation = self.albedo;
-        return true;
+        return ScatterEvent::Diffuse;
+    }
+
+    // normal + a random unit vector is cosine distributed about the normal
+    pub fn pdf(&self, rec: &hittable::HitRecord, direction: vector::Vec3) -> f64 {
+        let cosine = vector::dot(&direction.unit_vector(), &rec.normal);
+        return cosine.max(0.0) / PI;
     }
 }
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Metal {
     albedo: Color,
     fuzz: f64,
@@ -75,24 +206,119 @@ impl Metal {
         rec: &hittable::HitRecord,
         attenuation: &mut Color,
         scattered: &mut ray::Ray,
-    ) -> bool {
+    ) -> ScatterEvent {
         let reflected = vector::reflect(&r_in.direction().unit_vector(), &rec.normal);
         *scattered = ray::Ray::new(
             rec.p,
             reflected + vector::Vec3::random_unit_vector() * self.fuzz,
         );
         *attenuation = self.albedo;
-        return vector::dot(&scattered.direction(), &rec.normal) > 0.0;
+        if vector::dot(&scattered.direction(), &rec.normal) > 0.0 {
+            return ScatterEvent::Reflection;
+        }
+        return ScatterEvent::Absorbed;
     }
+
+    // The fuzzed direction points at a uniform point on a sphere of radius
+    // fuzz around the unit mirror direction. A direction at angle alpha from
+    // the mirror direction meets that sphere at distances t, and each
+    // crossing contributes t^2 / (4 pi fuzz sqrt(fuzz^2 - sin^2 alpha)).
+    pub fn pdf(
+        &self,
+        r_in: &ray::Ray,
+        rec: &hittable::HitRecord,
+        direction: vector::Vec3,
+    ) -> Option<f64> {
+        if self.fuzz <= 0.0 {
+            return None;
+        }
+        let reflected = vector::reflect(&r_in.direction().unit_vector(), &rec.normal);
+        let cos_alpha = vector::dot(&direction.unit_vector(), &reflected);
+        let discriminant = self.fuzz * self.fuzz - (1.0 - cos_alpha * cos_alpha);
+        if discriminant <= 0.0 {
+            return Some(0.0);
+        }
+        let root = discriminant.sqrt();
+        let mut density = 0.0;
+        for t in [cos_alpha - root, cos_alpha + root] {
+            if t > 0.0 {
+                density += t * t / (4.0 * PI * self.fuzz * root);
+            }
+        }
+        return Some(density);
+    }
+}
+// How a dielectric's index of refraction varies with wavelength. Both
+// models take the wavelength in micrometres.
+#[derive(Clone)]
+pub enum Dispersion {
+    None,
+    // n = a + b / lambda^2
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum(b_i * lambda^2 / (lambda^2 - c_i))
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
 }
-#[derive(Clone, Copy)]
+
+#[derive(Clone)]
 pub struct Dielectric {
     ir: f64,
+    dispersion: Dispersion,
 }
 
 impl Dielectric {
+    // Wavelength used for the index of refraction of RGB rays (sodium d-line).
+    pub const REFERENCE_WAVELENGTH: f64 = 587.6;
+
     pub fn new(ir: f64) -> Self {
-        Dielectric { ir }
+        Dielectric {
+            ir,
+            dispersion: Dispersion::None,
+        }
+    }
+
+    pub fn cauchy(a: f64, b: f64) -> Self {
+        let dispersion = Dispersion::Cauchy { a, b };
+        Dielectric {
+            ir: Self::dispersed_ir(&dispersion, Self::REFERENCE_WAVELENGTH).unwrap_or(a),
+            dispersion,
+        }
+    }
+
+    pub fn sellmeier(b: [f64; 3], c: [f64; 3]) -> Self {
+        let dispersion = Dispersion::Sellmeier { b, c };
+        Dielectric {
+            ir: Self::dispersed_ir(&dispersion, Self::REFERENCE_WAVELENGTH).unwrap_or(1.5),
+            dispersion,
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        return !matches!(self.dispersion, Dispersion::None);
+    }
+
+    // Index of refraction at the given wavelength in nm, or at the reference
+    // wavelength for RGB rays.
+    pub fn index_of_refraction(&self, wavelength: Option<f64>) -> f64 {
+        match wavelength {
+            Some(lambda) => Self::dispersed_ir(&self.dispersion, lambda).unwrap_or(self.ir),
+            None => self.ir,
+        }
+    }
+
+    fn dispersed_ir(dispersion: &Dispersion, lambda_nm: f64) -> Option<f64> {
+        let l = lambda_nm / 1000.0;
+        let l2 = l * l;
+        match dispersion {
+            Dispersion::None => None,
+            Dispersion::Cauchy { a, b } => Some(a + b / l2),
+            Dispersion::Sellmeier { b, c } => {
+                let mut n2 = 1.0;
+                for i in 0..3 {
+                    n2 += b[i] * l2 / (l2 - c[i]);
+                }
+                Some(n2.sqrt())
+            }
+        }
     }
 
     pub fn scatter(
@@ -101,28 +327,25 @@ impl Dielectric {
         rec: &hittable::HitRecord,
         attenuation: &mut Color,
         scattered: &mut ray::Ray,
-    ) -> bool {
+    ) -> ScatterEvent {
         *attenuation = Color::new(1.0, 1.0, 1.0);
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
-        };
+        let ir = self.index_of_refraction(r_in.wavelength);
+        let refraction_ratio = if rec.front_face { 1.0 / ir } else { ir };
         let unit_direction = r_in.direction().unit_vector();
         let cos_theta = vector::dot(&-unit_direction, &rec.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction = if cannot_refract
+        if cannot_refract
             || Self::reflectance(cos_theta, refraction_ratio) > utility::random_double(0.0, 1.0)
         {
-            vector::reflect(&unit_direction, &rec.normal)
-        } else {
-            vector::refract(&unit_direction, &rec.normal, refraction_ratio)
-        };
-
+            let direction = vector::reflect(&unit_direction, &rec.normal);
+            *scattered = ray::Ray::new(rec.p, direction);
+            return ScatterEvent::Reflection;
+        }
+        let direction = vector::refract(&unit_direction, &rec.normal, refraction_ratio);
         *scattered = ray::Ray::new(rec.p, direction);
-        return true;
+        return ScatterEvent::Refraction;
     }
 
     fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
@@ -131,3 +354,116 @@ impl Dielectric {
         return r0 + (1.0 - r0) * (1.0 - cosine).powi(5);
     }
 }
+
+// Phase function for participating media: scatters uniformly over the sphere.
+#[derive(Clone)]
+pub struct Isotropic {
+    albedo: Color,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Isotropic { albedo }
+    }
+
+    pub fn scatter(
+        &self,
+        _r_in: &ray::Ray,
+        rec: &hittable::HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut ray::Ray,
+    ) -> ScatterEvent {
+        *scattered = ray::Ray::new(rec.p, vector::Vec3::random_unit_vector());
+        *attenuation = self.albedo;
+        return ScatterEvent::Phase;
+    }
+}
+
+// Anisotropic phase function. g in (-1, 1) is the mean cosine between the
+// incoming and scattered directions: positive values scatter forward,
+// negative values backward and zero matches Isotropic.
+#[derive(Clone)]
+pub struct HenyeyGreenstein {
+    albedo: Color,
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Color, g: f64) -> Self {
+        HenyeyGreenstein {
+            albedo,
+            g: g.clamp(-0.999, 0.999),
+        }
+    }
+
+    pub fn scatter(
+        &self,
+        r_in: &ray::Ray,
+        rec: &hittable::HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut ray::Ray,
+    ) -> ScatterEvent {
+        let u = utility::random_double(0.0, 1.0);
+        let cos_theta = if self.g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let sqr_term = (1.0 - self.g * self.g) / (1.0 - self.g + 2.0 * self.g * u);
+            (1.0 + self.g * self.g - sqr_term * sqr_term) / (2.0 * self.g)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * utility::random_double(0.0, 1.0);
+
+        // build a frame around the direction of travel
+        let w = r_in.direction().unit_vector();
+        let a = if w.x().abs() > 0.9 {
+            vector::Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            vector::Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = vector::cross(&w, &a).unit_vector();
+        let u_axis = vector::cross(&w, &v);
+        let direction =
+            u_axis * (sin_theta * phi.cos()) + v * (sin_theta * phi.sin()) + w * cos_theta;
+
+        *scattered = ray::Ray::new(rec.p, direction);
+        *attenuation = self.albedo;
+        return ScatterEvent::Phase;
+    }
+
+    pub fn pdf(&self, r_in: &ray::Ray, direction: vector::Vec3) -> f64 {
+        let cos_theta = vector::dot(&r_in.direction().unit_vector(), &direction.unit_vector());
+        let denom = 1.0 + self.g * self.g - 2.0 * self.g * cos_theta;
+        return (1.0 - self.g * self.g) / (4.0 * PI * denom * denom.sqrt());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cauchy_glass_follows_its_formula() {
+        let glass = Dielectric::cauchy(1.5046, 0.0042);
+        let n = |lambda: f64| glass.index_of_refraction(Some(lambda));
+        assert!((n(500.0) - (1.5046 + 0.0042 / 0.25)).abs() < 1e-12);
+        // RGB rays see the index at the sodium d-line
+        assert!(
+            (glass.index_of_refraction(None) - n(Dielectric::REFERENCE_WAVELENGTH)).abs() < 1e-12
+        );
+        assert!(n(450.0) > n(550.0) && n(550.0) > n(650.0));
+    }
+
+    #[test]
+    fn sellmeier_bk7_matches_its_catalogue_indices() {
+        let bk7 = Dielectric::sellmeier(
+            [1.03961212, 0.231792344, 1.01046945],
+            [0.00600069867, 0.0200179144, 103.560653],
+        );
+        // the Fraunhofer F, d and C lines
+        for (lambda, n) in [(486.13, 1.52238), (587.56, 1.51680), (656.27, 1.51432)] {
+            assert!((bk7.index_of_refraction(Some(lambda)) - n).abs() < 1e-4);
+        }
+        assert!((bk7.index_of_refraction(None) - 1.51680).abs() < 1e-4);
+        assert!(bk7.is_dispersive() && !Dielectric::new(1.5).is_dispersive());
+    }
+}
@@ -0,0 +1,257 @@
+use crate::hittable;
+use crate::material;
+use crate::vector;
+
+use std::fmt::Write;
+
+use vector::Vec3 as Color;
+use vector::Vec3 as Point3;
+
+// Why a traced path stopped.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    // left the scene and picked up the sky
+    Miss,
+    // a material absorbed it, see the event of the last vertex
+    Absorbed,
+    // reached the camera's max_depth
+    MaxDepth,
+    // the film point lies outside the projection, so no ray was traced
+    OutsideProjection,
+}
+
+impl Termination {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Termination::Miss => "miss",
+            Termination::Absorbed => "absorbed",
+            Termination::MaxDepth => "max_depth",
+            Termination::OutsideProjection => "outside_projection",
+        }
+    }
+}
+
+// A surface or medium interaction along a path.
+pub struct Vertex {
+    pub position: Point3,
+    pub normal: vector::Vec3,
+    pub geometric_normal: vector::Vec3,
+    pub material: material::MaterialId,
+    pub material_type: &'static str,
+    pub object_id: usize,
+    pub front_face: bool,
+    pub event: material::ScatterEvent,
+    // factor the path's throughput was multiplied by here
+    pub attenuation: Color,
+    // sampled direction, also recorded when it was rejected
+    pub direction: vector::Vec3,
+}
+
+// Everything that happened to one camera sample.
+pub struct PathLog {
+    pub sample: i32,
+    // continuous image position the ray was traced through
+    pub film: (f64, f64),
+    pub origin: Point3,
+    pub direction: vector::Vec3,
+    // color weight of the camera sample, from vignetting and aberration
+    pub weight: Color,
+    pub vertices: Vec<Vertex>,
+    pub termination: Termination,
+    // sky radiance where the path escaped, black otherwise
+    pub environment: Color,
+    // contribution of the sample to the pixel, before filtering
+    pub radiance: Color,
+}
+
+impl PathLog {
+    pub fn new(sample: i32, film: (f64, f64)) -> Self {
+        PathLog {
+            sample,
+            film,
+            origin: Point3::new(0.0, 0.0, 0.0),
+            direction: vector::Vec3::new(0.0, 0.0, 0.0),
+            weight: Color::new(1.0, 1.0, 1.0),
+            vertices: Vec::new(),
+            termination: Termination::OutsideProjection,
+            environment: Color::new(0.0, 0.0, 0.0),
+            radiance: Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn add_vertex(
+        &mut self,
+        rec: &hittable::HitRecord,
+        mat: &material::Material,
+        event: material::ScatterEvent,
+        attenuation: Color,
+        direction: vector::Vec3,
+    ) {
+        self.vertices.push(Vertex {
+            position: rec.p,
+            normal: rec.normal,
+            geometric_normal: rec.geometric_normal,
+            material: rec.mat,
+            material_type: mat.name(),
+            object_id: rec.object_id,
+            front_face: rec.front_face,
+            event,
+            attenuation,
+            direction: direction.unit_vector(),
+        });
+    }
+}
+
+// Serializes the paths traced through pixel (x, y).
+pub fn to_json(x: i32, y: i32, paths: &[PathLog]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "{{");
+    let _ = writeln!(out, "  \"pixel\": [{}, {}],", x, y);
+    let _ = writeln!(out, "  \"paths\": [");
+    for (i, path) in paths.iter().enumerate() {
+        let _ = writeln!(out, "    {{");
+        let _ = writeln!(out, "      \"sample\": {},", path.sample);
+        let _ = writeln!(
+            out,
+            "      \"film\": [{}, {}],",
+            number(path.film.0),
+            number(path.film.1)
+        );
+        let _ = writeln!(out, "      \"origin\": {},", vec3(path.origin));
+        let _ = writeln!(out, "      \"direction\": {},", vec3(path.direction));
+        let _ = writeln!(out, "      \"weight\": {},", vec3(path.weight));
+        let _ = writeln!(out, "      \"vertices\": [");
+        for (j, vertex) in path.vertices.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "        {{\"position\": {}, \"normal\": {}, \"geometric_normal\": {}, \
+                 \"material\": {}, \"material_type\": \"{}\", \"object\": {}, \
+                 \"front_face\": {}, \"event\": \"{}\", \"attenuation\": {}, \
+                 \"direction\": {}}}{}",
+                vec3(vertex.position),
+                vec3(vertex.normal),
+                vec3(vertex.geometric_normal),
+                vertex.material.index(),
+                vertex.material_type,
+                vertex.object_id,
+                vertex.front_face,
+                vertex.event.name(),
+                vec3(vertex.attenuation),
+                vec3(vertex.direction),
+                if j + 1 < path.vertices.len() { "," } else { "" }
+            );
+        }
+        let _ = writeln!(out, "      ],");
+        let _ = writeln!(
+            out,
+            "      \"termination\": \"{}\",",
+            path.termination.name()
+        );
+        let _ = writeln!(out, "      \"environment\": {},", vec3(path.environment));
+        let _ = writeln!(out, "      \"radiance\": {}", vec3(path.radiance));
+        let _ = writeln!(out, "    }}{}", if i + 1 < paths.len() { "," } else { "" });
+    }
+    let _ = writeln!(out, "  ]");
+    let _ = writeln!(out, "}}");
+    return out;
+}
+
+// JSON has no infinities or NaN, so those become null.
+fn number(x: f64) -> String {
+    if x.is_finite() {
+        return format!("{}", x);
+    }
+    return "null".to_string();
+}
+
+fn vec3(v: vector::Vec3) -> String {
+    return format!("[{}, {}, {}]", number(v.x()), number(v.y()), number(v.z()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A path that scattered once off a Lambertian surface and escaped.
+    fn escaped_path() -> PathLog {
+        let mut materials = material::MaterialList::new();
+        let grey = materials.add(material::Material::Lambertian(material::Lambertian::new(
+            Color::new(0.5, 0.5, 0.5),
+        )));
+        let mut path = PathLog::new(3, (10.25, 5.5));
+        path.direction = vector::Vec3::new(0.0, 0.0, -1.0);
+        path.vertices.push(Vertex {
+            position: Point3::new(0.0, 0.0, -1.5),
+            normal: vector::Vec3::new(0.0, 0.0, 1.0),
+            geometric_normal: vector::Vec3::new(0.0, 0.0, 1.0),
+            material: grey,
+            material_type: "lambertian",
+            object_id: 0,
+            front_face: true,
+            event: material::ScatterEvent::Diffuse,
+            attenuation: Color::new(0.5, 0.5, 0.5),
+            direction: vector::Vec3::new(0.0, 1.0, 0.0),
+        });
+        path.termination = Termination::Miss;
+        path.environment = Color::new(0.5, 0.75, 1.0);
+        path.radiance = Color::new(0.25, 0.375, 0.5);
+        return path;
+    }
+
+    #[test]
+    fn json_lists_every_vertex() {
+        let mut outside = PathLog::new(4, (f64::INFINITY, 5.5));
+        outside.weight = Color::new(f64::NAN, 1.0, 1.0);
+        let json = to_json(10, 5, &[escaped_path(), outside]);
+        let expected = "{
+  \"pixel\": [10, 5],
+  \"paths\": [
+    {
+      \"sample\": 3,
+      \"film\": [10.25, 5.5],
+      \"origin\": [0, 0, 0],
+      \"direction\": [0, 0, -1],
+      \"weight\": [1, 1, 1],
+      \"vertices\": [
+        {\"position\": [0, 0, -1.5], \"normal\": [0, 0, 1], \"geometric_normal\": [0, 0, 1], \
+\"material\": 0, \"material_type\": \"lambertian\", \"object\": 0, \"front_face\": true, \
+\"event\": \"diffuse\", \"attenuation\": [0.5, 0.5, 0.5], \"direction\": [0, 1, 0]}
+      ],
+      \"termination\": \"miss\",
+      \"environment\": [0.5, 0.75, 1],
+      \"radiance\": [0.25, 0.375, 0.5]
+    },
+    {
+      \"sample\": 4,
+      \"film\": [null, 5.5],
+      \"origin\": [0, 0, 0],
+      \"direction\": [0, 0, 0],
+      \"weight\": [null, 1, 1],
+      \"vertices\": [
+      ],
+      \"termination\": \"outside_projection\",
+      \"environment\": [0, 0, 0],
+      \"radiance\": [0, 0, 0]
+    }
+  ]
+}
+";
+        assert!(json == expected, "{}", json);
+    }
+
+    #[test]
+    fn json_names_every_termination() {
+        for (termination, name) in [
+            (Termination::Miss, "miss"),
+            (Termination::Absorbed, "absorbed"),
+            (Termination::MaxDepth, "max_depth"),
+            (Termination::OutsideProjection, "outside_projection"),
+        ] {
+            let mut path = escaped_path();
+            path.termination = termination;
+            let json = to_json(0, 0, &[path]);
+            let line = format!("\"termination\": \"{}\",", name);
+            assert!(json.contains(&line), "{}", json);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::utility;
+use crate::vector;
+
+use vector::Vec3 as Point3;
+
+// Gradient noise with random unit vectors on the lattice and Hermite
+// smoothed trilinear interpolation. noise returns values in roughly [-1, 1].
+pub struct Perlin {
+    ranvec: Vec<vector::Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    const POINT_COUNT: usize = 256;
+
+    pub fn new() -> Self {
+        let ranvec = (0..Self::POINT_COUNT)
+            .map(|_| vector::Vec3::random(-1.0, 1.0).unit_vector())
+            .collect();
+        Perlin {
+            ranvec,
+            perm_x: Self::generate_perm(),
+            perm_y: Self::generate_perm(),
+            perm_z: Self::generate_perm(),
+        }
+    }
+
+    pub fn noise(&self, p: Point3) -> f64 {
+        let u = p.x() - p.x().floor();
+        let v = p.y() - p.y().floor();
+        let w = p.z() - p.z().floor();
+
+        let i = p.x().floor() as i64;
+        let j = p.y().floor() as i64;
+        let k = p.z().floor() as i64;
+
+        let mask = (Self::POINT_COUNT - 1) as i64;
+        let mut c = [[[vector::Vec3::default(); 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = self.perm_x[((i + di as i64) & mask) as usize]
+                        ^ self.perm_y[((j + dj as i64) & mask) as usize]
+                        ^ self.perm_z[((k + dk as i64) & mask) as usize];
+                    *corner = self.ranvec[index];
+                }
+            }
+        }
+
+        return Self::perlin_interp(&c, u, v, w);
+    }
+
+    // Sum of octaves of absolute noise, in roughly [0, 1].
+    pub fn turb(&self, p: Point3, depth: i32) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = p;
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            accum += weight * self.noise(temp_p).abs();
+            weight *= 0.5;
+            temp_p = temp_p * 2.0;
+        }
+
+        return accum;
+    }
+
+    fn generate_perm() -> Vec<usize> {
+        let mut p: Vec<usize> = (0..Self::POINT_COUNT).collect();
+        for i in (1..Self::POINT_COUNT).rev() {
+            let target = utility::random_double(0.0, (i + 1) as f64) as usize;
+            p.swap(i, target.min(i));
+        }
+        return p;
+    }
+
+    fn perlin_interp(c: &[[[vector::Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+        let mut accum = 0.0;
+
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, corner) in row.iter().enumerate() {
+                    let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                    let weight_v = vector::Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * vector::dot(corner, &weight_v);
+                }
+            }
+        }
+
+        return accum;
+    }
+}
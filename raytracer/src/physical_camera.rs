@@ -0,0 +1,122 @@
+// Photographic controls for the thin-lens camera: exposure settings and the
+// lens imperfections applied when generating camera rays.
+
+// Exposure calibrated with the "sunny 16" rule: at f/16 with a shutter time
+// of 1 / ISO, scene radiance is passed through unscaled, which suits this
+// renderer's daylight sky of radiance around 1.
+#[derive(Clone, Copy)]
+pub struct Exposure {
+    pub iso: f64,
+    // seconds
+    pub shutter_time: f64,
+    pub f_stop: f64,
+}
+
+impl Exposure {
+    pub fn new(iso: f64, shutter_time: f64, f_stop: f64) -> Self {
+        Exposure {
+            iso,
+            shutter_time,
+            f_stop,
+        }
+    }
+
+    pub fn scale(&self) -> f64 {
+        return self.shutter_time * self.iso * (16.0 / self.f_stop).powi(2);
+    }
+
+    // Full cone angle in degrees subtended at the focus plane by the entrance
+    // pupil of a lens of this f-stop. focal_length and focus_dist are in
+    // scene units.
+    pub fn defocus_angle(&self, focal_length: f64, focus_dist: f64) -> f64 {
+        let aperture_radius = focal_length / (2.0 * self.f_stop);
+        return 2.0 * (aperture_radius / focus_dist).atan().to_degrees();
+    }
+}
+
+// Brown-Conrady lens distortion in normalized image coordinates (image plane
+// at unit distance). k1..k3 are radial terms, positive for pincushion and
+// negative for barrel; p1 and p2 are tangential terms.
+#[derive(Clone, Copy, Default)]
+pub struct LensDistortion {
+    pub k1: f64,
+    pub k2: f64,
+    pub k3: f64,
+    pub p1: f64,
+    pub p2: f64,
+}
+
+impl LensDistortion {
+    pub fn is_identity(&self) -> bool {
+        return self.k1 == 0.0
+            && self.k2 == 0.0
+            && self.k3 == 0.0
+            && self.p1 == 0.0
+            && self.p2 == 0.0;
+    }
+
+    // Where the lens images an undistorted point (x, y) is
+    // (x * radial + dx, y * radial + dy).
+    fn terms(&self, x: f64, y: f64) -> (f64, f64, f64) {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        let dx = 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x);
+        let dy = self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y;
+        return (radial, dx, dy);
+    }
+
+    // Inverts the distortion by fixed-point iteration, giving the scene
+    // direction seen at a distorted image point.
+    pub fn undistort(&self, xd: f64, yd: f64) -> (f64, f64) {
+        let (mut x, mut y) = (xd, yd);
+        for _ in 0..20 {
+            let (radial, dx, dy) = self.terms(x, y);
+            x = (xd - dx) / radial;
+            y = (yd - dy) / radial;
+        }
+        return (x, y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sunny_sixteen_passes_radiance_through() {
+        assert!((Exposure::new(100.0, 0.01, 16.0).scale() - 1.0).abs() < 1e-12);
+        // one stop wider, one stop faster
+        assert!((Exposure::new(100.0, 0.005, 16.0 / 2f64.sqrt()).scale() - 1.0).abs() < 1e-12);
+        assert!((Exposure::new(400.0, 0.01, 16.0).scale() - 4.0).abs() < 1e-12);
+
+        // a 50 mm f/2 lens has a 25 mm entrance pupil
+        let angle = Exposure::new(100.0, 0.01, 2.0).defocus_angle(0.05, 1.0);
+        assert!((angle - 2.0 * 0.0125f64.atan().to_degrees()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn undistort_inverts_the_lens() {
+        let distortions = [
+            LensDistortion {
+                k1: -0.2,
+                k2: 0.05,
+                ..Default::default()
+            },
+            LensDistortion {
+                k1: 0.1,
+                p1: 0.01,
+                p2: -0.02,
+                ..Default::default()
+            },
+        ];
+        for distortion in distortions {
+            for (x, y) in [(0.0, 0.0), (0.3, -0.2), (-0.5, 0.4)] {
+                let (radial, dx, dy) = distortion.terms(x, y);
+                let (xd, yd) = (x * radial + dx, y * radial + dy);
+                let (xu, yu) = distortion.undistort(xd, yd);
+                assert!((xu - x).abs() < 1e-9 && (yu - y).abs() < 1e-9);
+            }
+        }
+        assert!(LensDistortion::default().undistort(0.3, -0.2) == (0.3, -0.2));
+    }
+}
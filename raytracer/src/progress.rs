@@ -0,0 +1,310 @@
+use std::io;
+use std::io::Write;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    // nothing but errors
+    Quiet,
+    // a progress line that updates in place, and the final summary
+    Normal,
+    // a new progress line every second, and notes about each step
+    Verbose,
+}
+
+// How progress is reported on stderr. With json set every report is one JSON
+// object per line, for wrappers that parse it.
+#[derive(Clone, Copy)]
+pub struct Style {
+    pub verbosity: Verbosity,
+    pub json: bool,
+}
+
+impl Style {
+    pub fn new() -> Self {
+        Style {
+            verbosity: Verbosity::Normal,
+            json: false,
+        }
+    }
+
+    // Reports a one-off event, such as starting a frame of an animation.
+    // Text notes are only shown when verbose.
+    pub fn note(&self, message: &str) {
+        self.write_note(&mut io::stderr().lock(), message);
+    }
+
+    fn write_note(&self, out: &mut dyn Write, message: &str) {
+        if self.verbosity == Verbosity::Quiet {
+            return;
+        }
+        if self.json {
+            let _ = writeln!(
+                out,
+                "{{\"event\": \"note\", \"message\": \"{}\"}}",
+                escape(message)
+            );
+        } else if self.verbosity == Verbosity::Verbose {
+            let _ = writeln!(out, "{}", message);
+        }
+    }
+}
+
+// Tracks the samples of a render. advance may be called from any thread with
+// whatever amount of work just finished, such as a row or a tile.
+pub struct Progress {
+    style: Style,
+    total: u64,
+    done: AtomicU64,
+    start: Instant,
+    // when the last report was printed
+    last_report: Mutex<Instant>,
+}
+
+impl Progress {
+    pub fn new(style: Style, total_samples: u64) -> Self {
+        let start = Instant::now();
+        Progress {
+            style,
+            total: total_samples,
+            done: AtomicU64::new(0),
+            start,
+            last_report: Mutex::new(start),
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        if self.style.verbosity == Verbosity::Normal && !self.style.json {
+            return Duration::from_millis(250);
+        }
+        return Duration::from_secs(1);
+    }
+
+    pub fn advance(&self, samples: u64) {
+        let done = self.done.fetch_add(samples, Ordering::Relaxed) + samples;
+        // skip the report if another thread is printing one
+        let Ok(mut last_report) = self.last_report.try_lock() else {
+            return;
+        };
+        let now = Instant::now();
+        if now.duration_since(*last_report) < self.interval() {
+            return;
+        }
+        *last_report = now;
+        self.report(&mut io::stderr().lock(), "progress", done, now);
+    }
+
+    // Prints the final report.
+    pub fn finish(&self) {
+        let _guard = self.last_report.lock();
+        let done = self.done.load(Ordering::Relaxed);
+        self.report(&mut io::stderr().lock(), "done", done, Instant::now());
+    }
+
+    // Writes the report of done samples as it stands at now.
+    fn report(&self, out: &mut dyn Write, event: &str, done: u64, now: Instant) {
+        if self.style.verbosity == Verbosity::Quiet {
+            return;
+        }
+        let elapsed = now.duration_since(self.start).as_secs_f64();
+        let fraction = if self.total > 0 {
+            (done as f64 / self.total as f64).min(1.0)
+        } else {
+            1.0
+        };
+        let rate = if elapsed > 0.0 {
+            done as f64 / elapsed
+        } else {
+            0.0
+        };
+        let eta = if rate > 0.0 {
+            Some(self.total.saturating_sub(done) as f64 / rate)
+        } else {
+            None
+        };
+
+        if self.style.json {
+            let _ = writeln!(
+                out,
+                "{{\"event\": \"{}\", \"samples\": {}, \"total_samples\": {}, \
+                 \"fraction\": {}, \"elapsed\": {}, \"samples_per_second\": {}, \
+                 \"eta\": {}}}",
+                event,
+                done,
+                self.total,
+                fraction,
+                elapsed,
+                rate,
+                eta.map_or("null".to_string(), |eta| eta.to_string())
+            );
+            return;
+        }
+
+        let line = format!(
+            "{:5.1}%  {:>10} samples/s  elapsed {}  ETA {}",
+            fraction * 100.0,
+            format_rate(rate),
+            format_duration(elapsed),
+            eta.map_or("--:--".to_string(), format_duration)
+        );
+        if self.style.verbosity == Verbosity::Verbose {
+            let _ = writeln!(out, "{}", line);
+        } else if event == "done" {
+            let _ = writeln!(out, "\r{}", line);
+        } else {
+            let _ = write!(out, "\r{}", line);
+        }
+    }
+}
+
+fn format_rate(rate: f64) -> String {
+    if rate >= 1e6 {
+        return format!("{:.2}M", rate / 1e6);
+    }
+    if rate >= 1e3 {
+        return format!("{:.1}k", rate / 1e3);
+    }
+    return format!("{:.0}", rate);
+}
+
+// h:mm:ss, or m:ss under an hour.
+fn format_duration(seconds: f64) -> String {
+    let total = seconds.round() as u64;
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        return format!("{}:{:02}:{:02}", hours, minutes, seconds);
+    }
+    return format!("{}:{:02}", minutes, seconds);
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(verbosity: Verbosity, json: bool) -> Style {
+        return Style { verbosity, json };
+    }
+
+    // What a render of total samples reports once done of them finished
+    // after the given seconds.
+    fn report_at(style: Style, event: &str, done: u64, total: u64, seconds: f64) -> String {
+        let progress = Progress::new(style, total);
+        let now = progress.start + Duration::from_secs_f64(seconds);
+        let mut out = Vec::new();
+        progress.report(&mut out, event, done, now);
+        return String::from_utf8(out).unwrap();
+    }
+
+    #[test]
+    fn json_reports_are_one_object_per_line() {
+        let line = report_at(style(Verbosity::Normal, true), "progress", 250, 1000, 2.0);
+        assert!(
+            line == "{\"event\": \"progress\", \"samples\": 250, \"total_samples\": 1000, \
+                     \"fraction\": 0.25, \"elapsed\": 2, \"samples_per_second\": 125, \
+                     \"eta\": 6}\n",
+            "{}",
+            line
+        );
+        // verbosity does not change the format
+        let verbose = report_at(style(Verbosity::Verbose, true), "progress", 250, 1000, 2.0);
+        assert!(verbose == line);
+
+        let mut note = Vec::new();
+        style(Verbosity::Normal, true).write_note(&mut note, "frame \"1\"\n");
+        assert!(
+            note == b"{\"event\": \"note\", \"message\": \"frame \\\"1\\\"\\u000a\"}\n",
+            "{}",
+            String::from_utf8_lossy(&note)
+        );
+    }
+
+    #[test]
+    fn reports_estimate_the_time_left() {
+        // a quarter done in 2 seconds leaves 6 at 125 samples per second
+        let line = report_at(style(Verbosity::Normal, false), "progress", 250, 1000, 2.0);
+        assert!(
+            line == "\r 25.0%         125 samples/s  elapsed 0:02  ETA 0:06",
+            "{:?}",
+            line
+        );
+        let line = report_at(
+            style(Verbosity::Verbose, false),
+            "progress",
+            1500,
+            3000,
+            1800.0,
+        );
+        assert!(
+            line == " 50.0%           1 samples/s  elapsed 30:00  ETA 30:00\n",
+            "{:?}",
+            line
+        );
+        let line = report_at(
+            style(Verbosity::Normal, false),
+            "done",
+            4_000_000,
+            4_000_000,
+            2.0,
+        );
+        assert!(
+            line == "\r100.0%       2.00M samples/s  elapsed 0:02  ETA 0:00\n",
+            "{:?}",
+            line
+        );
+    }
+
+    #[test]
+    fn no_time_elapsed_leaves_the_eta_unknown() {
+        let line = report_at(style(Verbosity::Verbose, false), "progress", 10, 100, 0.0);
+        assert!(
+            line == " 10.0%           0 samples/s  elapsed 0:00  ETA --:--\n",
+            "{:?}",
+            line
+        );
+        let line = report_at(style(Verbosity::Normal, true), "progress", 10, 100, 0.0);
+        assert!(
+            line.contains("\"samples_per_second\": 0, \"eta\": null}"),
+            "{}",
+            line
+        );
+        // a render without samples is complete
+        let line = report_at(style(Verbosity::Normal, true), "done", 0, 0, 0.0);
+        assert!(line.contains("\"fraction\": 1,"), "{}", line);
+    }
+
+    #[test]
+    fn quiet_prints_nothing() {
+        for json in [false, true] {
+            let quiet = style(Verbosity::Quiet, json);
+            for event in ["progress", "done"] {
+                assert!(report_at(quiet, event, 250, 1000, 2.0).is_empty());
+            }
+            let mut note = Vec::new();
+            quiet.write_note(&mut note, "frame 1");
+            assert!(note.is_empty());
+        }
+        // text notes need --verbose
+        let mut note = Vec::new();
+        style(Verbosity::Normal, false).write_note(&mut note, "frame 1");
+        assert!(note.is_empty());
+        style(Verbosity::Verbose, false).write_note(&mut note, "frame 1");
+        assert!(note == b"frame 1\n");
+    }
+}
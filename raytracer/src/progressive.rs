@@ -0,0 +1,159 @@
+use crate::color;
+use crate::image;
+use crate::progress;
+
+use std::time::Duration;
+
+// When a progressive render stops. Any limit left unset is not checked, so
+// a render with none of them only stops when interrupted.
+#[derive(Clone, Copy, Default)]
+pub struct Budget {
+    // wall-clock time of the whole render
+    pub time: Option<Duration>,
+    // estimated noise of the image, see estimate_noise
+    pub noise: Option<f64>,
+    // samples per pixel
+    pub samples: Option<i32>,
+}
+
+// Why a progressive render stopped.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    TimeBudget,
+    NoiseTarget,
+    SampleLimit,
+    Interrupted,
+}
+
+impl Stop {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stop::TimeBudget => "time budget",
+            Stop::NoiseTarget => "noise target",
+            Stop::SampleLimit => "sample limit",
+            Stop::Interrupted => "interrupted",
+        }
+    }
+
+    pub fn report(&self, style: progress::Style) {
+        if style.verbosity == progress::Verbosity::Quiet {
+            return;
+        }
+        if style.json {
+            eprintln!("{{\"event\": \"stop\", \"reason\": \"{}\"}}", self.name());
+        } else {
+            eprintln!("stopped: {}", self.name());
+        }
+    }
+}
+
+// The state of the image after a pass.
+pub struct Pass {
+    // passes are numbered from 1
+    pub index: i32,
+    // samples per pixel of the image so far
+    pub samples: i32,
+    // since the render started
+    pub elapsed: Duration,
+    // None until both halves have samples
+    pub noise: Option<f64>,
+}
+
+impl Pass {
+    pub fn report(&self, style: progress::Style) {
+        if style.verbosity == progress::Verbosity::Quiet {
+            return;
+        }
+        if style.json {
+            eprintln!(
+                "{{\"event\": \"pass\", \"pass\": {}, \"samples\": {}, \"elapsed\": {}, \
+                 \"noise\": {}}}",
+                self.index,
+                self.samples,
+                self.elapsed.as_secs_f64(),
+                self.noise
+                    .map_or("null".to_string(), |noise| noise.to_string())
+            );
+        } else {
+            eprintln!(
+                "pass {:3}  {:6} spp  {:9.1} s  noise {}",
+                self.index,
+                self.samples,
+                self.elapsed.as_secs_f64(),
+                self.noise
+                    .map_or("-".to_string(), |noise| format!("{:.5}", noise))
+            );
+        }
+    }
+}
+
+// Estimates the noise of the average of two independent renders a and b of
+// the same pixels, each given with its samples per pixel. With as many
+// samples each, their difference has twice the variance of either, so half
+// of it is the standard error of the average; in general the difference is
+// scaled by sqrt(n_a n_b) / (n_a + n_b). The result is the RMS of that error
+// over every channel, in display encoded values from 0 to 1 after the
+// display transform, which keeps it in step with what the eye sees in dark
+// and bright regions alike. Returns None while either render has no samples,
+// as an empty render says nothing about the noise.
+pub fn estimate_noise(
+    (a, a_samples): (&image::Image, i32),
+    (b, b_samples): (&image::Image, i32),
+    display: &color::DisplayTransform,
+) -> Option<f64> {
+    if a_samples <= 0 || b_samples <= 0 {
+        return None;
+    }
+    let (a_samples, b_samples) = (a_samples as f64, b_samples as f64);
+    let scale = (a_samples * b_samples).sqrt() / (a_samples + b_samples);
+    let encode = |c: f64| color::linear_to_srgb(c.clamp(0.0, 1.0));
+    let mut sum = 0.0;
+    for y in 0..a.height {
+        for x in 0..a.width {
+            let (p, q) = (display.tone_map(a.get(x, y)), display.tone_map(b.get(x, y)));
+            for (p, q) in [(p.x(), q.x()), (p.y(), q.y()), (p.z(), q.z())] {
+                let error = (encode(p) - encode(q)) * scale;
+                sum += error * error;
+            }
+        }
+    }
+    let count = 3 * a.width * a.height;
+    if count == 0 {
+        return Some(0.0);
+    }
+    return Some((sum / count as f64).sqrt());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::vector;
+
+    #[test]
+    fn noise_is_half_the_difference_of_the_halves() {
+        let display = color::DisplayTransform::new();
+        let mut a = image::Image::new(4, 2);
+        let mut b = image::Image::new(4, 2);
+        assert!(estimate_noise((&a, 4), (&b, 4), &display) == Some(0.0));
+
+        // a display encoded difference of 0.2 in every channel
+        let low = color::srgb_to_linear(0.4);
+        let high = color::srgb_to_linear(0.6);
+        for y in 0..2 {
+            for x in 0..4 {
+                a.set(x, y, vector::Vec3::new(low, low, low));
+                b.set(x, y, vector::Vec3::new(high, high, high));
+            }
+        }
+        let noise = |a_samples, b_samples| {
+            return estimate_noise((&a, a_samples), (&b, b_samples), &display).unwrap();
+        };
+        assert!((noise(4, 4) - 0.1).abs() < 1e-9);
+        // a third of the samples in a: 0.2 sqrt(2 * 4) / 6
+        let expected = 0.2 * 8.0f64.sqrt() / 6.0;
+        assert!((noise(2, 4) - expected).abs() < 1e-9);
+        // an empty half, as after a single sample per pixel
+        assert!(estimate_noise((&a, 1), (&b, 0), &display).is_none());
+    }
+}
@@ -6,11 +6,25 @@ use vector::Vec3 as Point3;
 pub struct Ray {
     pub orig: Point3,
     pub dir: vector::Vec3,
+    // hero wavelength in nm when rendering spectrally, None for RGB rays
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
     pub fn new(orig: Point3, dir: vector::Vec3) -> Ray {
-        Ray { orig, dir }
+        Ray {
+            orig,
+            dir,
+            wavelength: None,
+        }
+    }
+
+    pub fn with_wavelength(orig: Point3, dir: vector::Vec3, wavelength: f64) -> Ray {
+        Ray {
+            orig,
+            dir,
+            wavelength: Some(wavelength),
+        }
     }
     pub fn origin(&self) -> Point3 {
         return self.orig;
@@ -0,0 +1,679 @@
+use crate::camera;
+use crate::constant_medium;
+use crate::csg;
+use crate::heterogeneous_medium;
+use crate::hittable;
+use crate::hittable_list;
+use crate::material;
+use crate::sdf;
+use crate::utility;
+use crate::vector;
+
+use std::boxed::Box;
+use std::io;
+use std::path::Path;
+use std::vec::Vec;
+
+use utility::random_double;
+use vector::Vec3 as Color;
+use vector::Vec3 as Point3;
+
+// A world with its materials and a camera set up to view it.
+pub struct Scene {
+    pub world: hittable_list::HittableList,
+    pub materials: material::MaterialList,
+    pub camera: camera::Camera,
+}
+
+// The scenes of the reference images in images/, named after them, the
+// final scene of Ray Tracing in One Weekend, and scenes showing the
+// primitives the book does not have.
+pub const PRESETS: [&str; 18] = [
+    "lambertian_sphere",
+    "metal_spheres",
+    "fuzz",
+    "glass",
+    "more_glass",
+    "hollow",
+    "wide_fov",
+    "distant_view",
+    "zoomed",
+    "depth_of_field",
+    "random_spheres",
+    "hollow_csg",
+    "csg_shapes",
+    "sdf_shapes",
+    "sdf_combinators",
+    "fog",
+    "cloud",
+    "dispersion",
+];
+
+pub fn preset(name: &str) -> Option<Scene> {
+    let scene = match name {
+        "lambertian_sphere" => lambertian_sphere(),
+        "metal_spheres" => three_spheres(ThreeSpheres::Metal { fuzz: [0.0, 0.0] }),
+        "fuzz" => three_spheres(ThreeSpheres::Metal { fuzz: [0.3, 1.0] }),
+        "glass" => three_spheres(ThreeSpheres::Glass),
+        "more_glass" => three_spheres(ThreeSpheres::MoreGlass { hollow: None }),
+        "hollow" => three_spheres(ThreeSpheres::MoreGlass {
+            hollow: Some(Hollow::NegativeRadius),
+        }),
+        "wide_fov" => wide_fov(),
+        "distant_view" => {
+            let mut scene = three_spheres(ThreeSpheres::MoreGlass {
+                hollow: Some(Hollow::NegativeRadius),
+            });
+            scene.camera.look_from = Point3::new(-2.0, 2.0, 1.0);
+            scene
+        }
+        "zoomed" => {
+            let mut scene = three_spheres(ThreeSpheres::MoreGlass {
+                hollow: Some(Hollow::NegativeRadius),
+            });
+            scene.camera.look_from = Point3::new(-2.0, 2.0, 1.0);
+            scene.camera.vfov = 20.0;
+            scene
+        }
+        "depth_of_field" => {
+            let mut scene = three_spheres(ThreeSpheres::MoreGlass {
+                hollow: Some(Hollow::NegativeRadius),
+            });
+            scene.camera.look_from = Point3::new(-2.0, 2.0, 1.0);
+            scene.camera.vfov = 20.0;
+            scene.camera.defocus_angle = 10.0;
+            scene.camera.focus_dist = 3.4;
+            scene
+        }
+        "random_spheres" => random_spheres(),
+        "hollow_csg" => three_spheres(ThreeSpheres::MoreGlass {
+            hollow: Some(Hollow::Difference),
+        }),
+        "csg_shapes" => csg_shapes(),
+        "sdf_shapes" => sdf_shapes(),
+        "sdf_combinators" => sdf_combinators(),
+        "fog" => fog(),
+        "cloud" => cloud(heterogeneous_medium::Density::Noise(
+            heterogeneous_medium::NoiseDensity::new(2.0, 8.0),
+        )),
+        "dispersion" => dispersion(),
+        _ => return None,
+    };
+    return Some(scene);
+}
+
+// The camera of the book's chapter images: 400 pixels wide, looking down -z
+// from the origin with a 90 degree field of view.
+fn chapter_camera() -> camera::Camera {
+    let mut cam = camera::Camera::new();
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.vfov = 90.0;
+    cam.look_from = Point3::new(0.0, 0.0, 0.0);
+    cam.look_at = Point3::new(0.0, 0.0, -1.0);
+    cam.defocus_angle = 0.0;
+    return cam;
+}
+
+fn lambertian_sphere() -> Scene {
+    let mut materials = material::MaterialList::new();
+    let grey = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.5, 0.5, 0.5),
+    )));
+
+    let mut world = hittable_list::HittableList::new(Vec::new());
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        grey,
+    )));
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        grey,
+    )));
+
+    return Scene {
+        world,
+        materials,
+        camera: chapter_camera(),
+    };
+}
+
+// Variants of the three spheres on a yellow ground used by the material
+// chapters.
+enum ThreeSpheres {
+    // two metal spheres around a diffuse one, with the left and right fuzz
+    Metal { fuzz: [f64; 2] },
+    // glass on the left and in the centre
+    Glass,
+    // glass on the left, optionally hollow, around a blue diffuse sphere
+    MoreGlass { hollow: Option<Hollow> },
+}
+
+// How the hollow glass sphere is made.
+enum Hollow {
+    // a second sphere with a negative radius, whose normals point inwards
+    NegativeRadius,
+    // a CSG difference of two spheres
+    Difference,
+}
+
+fn three_spheres(variant: ThreeSpheres) -> Scene {
+    let mut materials = material::MaterialList::new();
+    let lambertian = |materials: &mut material::MaterialList, albedo: Color| {
+        materials.add(material::Material::Lambertian(material::Lambertian::new(
+            albedo,
+        )))
+    };
+    let ground = lambertian(&mut materials, Color::new(0.8, 0.8, 0.0));
+    let (center, left, right) = match variant {
+        ThreeSpheres::Metal { fuzz } => {
+            let center = lambertian(&mut materials, Color::new(0.7, 0.3, 0.3));
+            let left = materials.add(material::Material::Metal(material::Metal::new(
+                Color::new(0.8, 0.8, 0.8),
+                fuzz[0],
+            )));
+            let right = materials.add(material::Material::Metal(material::Metal::new(
+                Color::new(0.8, 0.6, 0.2),
+                fuzz[1],
+            )));
+            (center, left, right)
+        }
+        ThreeSpheres::Glass => {
+            let glass = materials.add(material::Material::Dielectric(material::Dielectric::new(
+                1.5,
+            )));
+            let right = materials.add(material::Material::Metal(material::Metal::new(
+                Color::new(0.8, 0.6, 0.2),
+                1.0,
+            )));
+            (glass, glass, right)
+        }
+        ThreeSpheres::MoreGlass { .. } => {
+            let center = lambertian(&mut materials, Color::new(0.1, 0.2, 0.5));
+            let left = materials.add(material::Material::Dielectric(material::Dielectric::new(
+                1.5,
+            )));
+            let right = materials.add(material::Material::Metal(material::Metal::new(
+                Color::new(0.8, 0.6, 0.2),
+                0.0,
+            )));
+            (center, left, right)
+        }
+    };
+
+    let mut world = hittable_list::HittableList::new(Vec::new());
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        ground,
+    )));
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        center,
+    )));
+    let left_sphere = Box::new(hittable::Sphere::new(
+        Point3::new(-1.0, 0.0, -1.0),
+        0.5,
+        left,
+    ));
+    match variant {
+        ThreeSpheres::MoreGlass {
+            hollow: Some(Hollow::NegativeRadius),
+        } => {
+            // a negative radius turns the normals inwards, making a glass shell
+            world.add(left_sphere);
+            world.add(Box::new(hittable::Sphere::new(
+                Point3::new(-1.0, 0.0, -1.0),
+                -0.4,
+                left,
+            )));
+        }
+        ThreeSpheres::MoreGlass {
+            hollow: Some(Hollow::Difference),
+        } => {
+            let cavity = Box::new(hittable::Sphere::new(
+                Point3::new(-1.0, 0.0, -1.0),
+                0.4,
+                left,
+            ));
+            world.add(Box::new(csg::Csg::difference(left_sphere, cavity)));
+        }
+        _ => world.add(left_sphere),
+    }
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(1.0, 0.0, -1.0),
+        0.5,
+        right,
+    )));
+
+    return Scene {
+        world,
+        materials,
+        camera: chapter_camera(),
+    };
+}
+
+fn wide_fov() -> Scene {
+    let mut materials = material::MaterialList::new();
+    let blue = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.0, 0.0, 1.0),
+    )));
+    let red = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(1.0, 0.0, 0.0),
+    )));
+
+    let r = (std::f64::consts::PI / 4.0).cos();
+    let mut world = hittable_list::HittableList::new(Vec::new());
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(-r, 0.0, -1.0),
+        r,
+        blue,
+    )));
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(r, 0.0, -1.0),
+        r,
+        red,
+    )));
+
+    return Scene {
+        world,
+        materials,
+        camera: chapter_camera(),
+    };
+}
+
+// The final scene of Ray Tracing in One Weekend.
+fn random_spheres() -> Scene {
+    // Materials
+    let mut materials = material::MaterialList::new();
+    let mat_ground = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.5, 0.5, 0.5),
+    )));
+    // World
+    let world_list = Vec::new();
+    let mut world = hittable_list::HittableList::new(world_list);
+
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        mat_ground,
+    )));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = utility::random_double(0.0, 1.0);
+            let center = Point3::new(
+                a as f64 + 0.9 * random_double(0.0, 1.0),
+                0.2,
+                b as f64 + 0.9 * random_double(0.0, 1.0),
+            );
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if choose_mat < 0.8 {
+                    let albedo = Color::random(0.0, 1.0) * Color::random(0.0, 1.0);
+                    let mat = materials.add(material::Material::Lambertian(
+                        material::Lambertian::new(albedo),
+                    ));
+                    world.add(Box::new(hittable::Sphere::new(center, 0.2, mat)));
+                } else if choose_mat < 0.95 {
+                    let albedo = Color::random(0.5, 1.0);
+                    let fuzz = random_double(0.0, 0.5);
+                    let mat = materials.add(material::Material::Metal(material::Metal::new(
+                        albedo, fuzz,
+                    )));
+                    world.add(Box::new(hittable::Sphere::new(center, 0.2, mat)));
+                } else {
+                    let mat = materials.add(material::Material::Dielectric(
+                        material::Dielectric::new(1.5),
+                    ));
+                    world.add(Box::new(hittable::Sphere::new(center, 0.2, mat)));
+                }
+            }
+        }
+    }
+
+    let mat1 = materials.add(material::Material::Dielectric(material::Dielectric::new(
+        1.5,
+    )));
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        mat1,
+    )));
+
+    let mat2 = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.4, 0.2, 0.1),
+    )));
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(-4.0, 1.0, 0.0),
+        1.0,
+        mat2,
+    )));
+
+    let mat3 = materials.add(material::Material::Metal(material::Metal::new(
+        Color::new(0.7, 0.6, 0.5),
+        0.0,
+    )));
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(4.0, 1.0, 0.0),
+        1.0,
+        mat3,
+    )));
+
+    // Camera
+    let mut cam = camera::Camera::new();
+    cam.look_from = Point3::new(13.0, 2.0, 3.0);
+    cam.look_at = Point3::new(0.0, 0.0, 0.0);
+    cam.defocus_angle = 0.6;
+    cam.focus_dist = 10.0;
+
+    return Scene {
+        world,
+        materials,
+        camera: cam,
+    };
+}
+
+// A glass lens cut from two spheres, two glass spheres fused into one solid
+// and a diffuse sphere with a bite taken out of it, all built with CSG.
+fn csg_shapes() -> Scene {
+    let mut materials = material::MaterialList::new();
+    let ground = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.8, 0.8, 0.0),
+    )));
+    let glass = materials.add(material::Material::Dielectric(material::Dielectric::new(
+        1.5,
+    )));
+    let red = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.7, 0.3, 0.3),
+    )));
+
+    let mut world = hittable_list::HittableList::new(Vec::new());
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        ground,
+    )));
+    let sphere = |center: Point3, radius: f64, mat: material::MaterialId| {
+        Box::new(hittable::Sphere::new(center, radius, mat))
+    };
+    // a biconvex lens facing the camera
+    world.add(Box::new(csg::Csg::intersection(
+        sphere(Point3::new(-1.1, 0.0, -1.4), 0.5, glass),
+        sphere(Point3::new(-1.1, 0.0, -0.6), 0.5, glass),
+    )));
+    // two overlapping glass spheres would refract again at the inner walls
+    world.add(Box::new(csg::Csg::union(
+        sphere(Point3::new(-0.1, -0.2, -1.0), 0.3, glass),
+        sphere(Point3::new(0.15, 0.1, -1.0), 0.25, glass),
+    )));
+    world.add(Box::new(csg::Csg::difference(
+        sphere(Point3::new(1.1, 0.0, -1.0), 0.45, red),
+        sphere(Point3::new(0.8, 0.3, -0.7), 0.35, red),
+    )));
+
+    let mut cam = chapter_camera();
+    cam.look_from = Point3::new(0.0, 1.2, 1.0);
+    cam.look_at = Point3::new(0.0, -0.2, -1.0);
+    cam.vfov = 60.0;
+    return Scene {
+        world,
+        materials,
+        camera: cam,
+    };
+}
+
+// A rounded box, a torus and a smooth union of two spheres, all sphere
+// traced, on the analytic ground sphere.
+fn sdf_shapes() -> Scene {
+    let mut materials = material::MaterialList::new();
+    let ground = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.8, 0.8, 0.0),
+    )));
+    let red = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.7, 0.3, 0.3),
+    )));
+    let gold = materials.add(material::Material::Metal(material::Metal::new(
+        Color::new(0.8, 0.6, 0.2),
+        0.1,
+    )));
+    let glass = materials.add(material::Material::Dielectric(material::Dielectric::new(
+        1.5,
+    )));
+
+    let mut world = hittable_list::HittableList::new(Vec::new());
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        ground,
+    )));
+    let round_box = sdf::Sdf::round_box(vector::Vec3::new(0.3, 0.3, 0.3), 0.1)
+        .translate(vector::Vec3::new(-1.1, -0.1, -1.0));
+    world.add(Box::new(sdf::SdfObject::new(round_box, red)));
+    let torus = sdf::Sdf::torus(0.35, 0.12).translate(vector::Vec3::new(0.0, -0.38, -1.0));
+    world.add(Box::new(sdf::SdfObject::new(torus, gold)));
+    let blob = sdf::Sdf::sphere(0.3)
+        .translate(vector::Vec3::new(1.1, -0.2, -1.0))
+        .smooth_union(
+            sdf::Sdf::sphere(0.2).translate(vector::Vec3::new(1.1, 0.25, -1.0)),
+            0.15,
+        );
+    world.add(Box::new(sdf::SdfObject::new(blob, glass)));
+
+    let mut cam = chapter_camera();
+    cam.look_from = Point3::new(0.0, 1.2, 1.0);
+    cam.look_at = Point3::new(0.0, -0.2, -1.0);
+    cam.vfov = 60.0;
+    return Scene {
+        world,
+        materials,
+        camera: cam,
+    };
+}
+
+// A box hollowed out by a sphere, a box rounded by a sphere with a knob on
+// top and a Mandelbulb, built from the distance combinators.
+fn sdf_combinators() -> Scene {
+    let mut materials = material::MaterialList::new();
+    let ground = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.8, 0.8, 0.0),
+    )));
+    let red = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.7, 0.3, 0.3),
+    )));
+    let gold = materials.add(material::Material::Metal(material::Metal::new(
+        Color::new(0.8, 0.6, 0.2),
+        0.1,
+    )));
+    let blue = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.2, 0.3, 0.7),
+    )));
+
+    let mut world = hittable_list::HittableList::new(Vec::new());
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        ground,
+    )));
+    let hollowed = sdf::Sdf::round_box(vector::Vec3::new(0.25, 0.25, 0.25), 0.02)
+        .difference(sdf::Sdf::sphere(0.33))
+        .translate(vector::Vec3::new(-0.9, -0.23, -1.0));
+    world.add(Box::new(sdf::SdfObject::new(hollowed, red)));
+    let rounded = sdf::Sdf::round_box(vector::Vec3::new(0.25, 0.25, 0.25), 0.0)
+        .intersection(sdf::Sdf::sphere(0.33))
+        .union(sdf::Sdf::sphere(0.1).translate(vector::Vec3::new(0.0, 0.33, 0.0)))
+        .translate(vector::Vec3::new(0.0, -0.25, -1.0));
+    world.add(Box::new(sdf::SdfObject::new(rounded, gold)));
+    let bulb = sdf::Sdf::mandelbulb(8.0, 10)
+        .scale(0.3)
+        .translate(vector::Vec3::new(0.9, -0.15, -1.0));
+    world.add(Box::new(sdf::SdfObject::new(bulb, blue)));
+
+    let mut cam = chapter_camera();
+    cam.look_from = Point3::new(0.0, 1.2, 1.0);
+    cam.look_at = Point3::new(0.0, -0.2, -1.0);
+    cam.vfov = 60.0;
+    return Scene {
+        world,
+        materials,
+        camera: cam,
+    };
+}
+
+// Constant density media: a thin white fog, a dense dark smoke and a glass
+// sphere filled with a dense blue medium, which looks like subsurface
+// scattering.
+fn fog() -> Scene {
+    let mut materials = material::MaterialList::new();
+    let ground = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.8, 0.8, 0.0),
+    )));
+    let white = materials.add(material::Material::Isotropic(material::Isotropic::new(
+        Color::new(0.9, 0.9, 0.9),
+    )));
+    let dark = materials.add(material::Material::Isotropic(material::Isotropic::new(
+        Color::new(0.2, 0.2, 0.2),
+    )));
+    let blue = materials.add(material::Material::Isotropic(material::Isotropic::new(
+        Color::new(0.2, 0.4, 0.9),
+    )));
+    let glass = materials.add(material::Material::Dielectric(material::Dielectric::new(
+        1.5,
+    )));
+
+    let mut world = hittable_list::HittableList::new(Vec::new());
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        ground,
+    )));
+    let medium = |center: Point3, density: f64, phase_function: material::MaterialId| {
+        let boundary = Box::new(hittable::Sphere::new(center, 0.4, phase_function));
+        Box::new(constant_medium::ConstantMedium::new(
+            boundary,
+            density,
+            phase_function,
+        ))
+    };
+    world.add(medium(Point3::new(-0.9, -0.1, -1.0), 2.0, white));
+    world.add(medium(Point3::new(0.0, -0.1, -1.0), 20.0, dark));
+    let center = Point3::new(0.9, -0.1, -1.0);
+    world.add(Box::new(hittable::Sphere::new(center, 0.4, glass)));
+    world.add(medium(center, 20.0, blue));
+
+    let mut cam = chapter_camera();
+    cam.look_from = Point3::new(0.0, 1.2, 1.0);
+    cam.look_at = Point3::new(0.0, -0.2, -1.0);
+    cam.vfov = 60.0;
+    return Scene {
+        world,
+        materials,
+        camera: cam,
+    };
+}
+
+// The box the cloud's density fills, around its spherical boundary.
+const CLOUD_MIN: Point3 = Point3 {
+    e: [-0.5, -0.5, -1.5],
+};
+const CLOUD_MAX: Point3 = Point3 {
+    e: [0.5, 0.5, -0.5],
+};
+
+// The cloud preset with its density read from a grid file, a text grid or,
+// given its size, a raw one. The grid fills the box around the cloud.
+pub fn cloud_from_grid<P: AsRef<Path>>(path: P, raw_size: Option<[usize; 3]>) -> io::Result<Scene> {
+    let grid = match raw_size {
+        Some([nx, ny, nz]) => heterogeneous_medium::DensityGrid::from_raw_file(
+            path, nx, ny, nz, CLOUD_MIN, CLOUD_MAX,
+        )?,
+        None => heterogeneous_medium::DensityGrid::from_text_file(path, CLOUD_MIN, CLOUD_MAX)?,
+    };
+    return Ok(cloud(heterogeneous_medium::Density::Grid(grid)));
+}
+
+// A forward scattering cloud in a sphere, hovering over the ground.
+fn cloud(density: heterogeneous_medium::Density) -> Scene {
+    let mut materials = material::MaterialList::new();
+    let ground = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.5, 0.5, 0.5),
+    )));
+    let droplets = materials.add(material::Material::HenyeyGreenstein(
+        material::HenyeyGreenstein::new(Color::new(0.9, 0.9, 0.9), 0.6),
+    ));
+
+    let mut world = hittable_list::HittableList::new(Vec::new());
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        ground,
+    )));
+    let boundary = Box::new(hittable::Sphere::new(
+        (CLOUD_MIN + CLOUD_MAX) / 2.0,
+        0.5,
+        droplets,
+    ));
+    world.add(Box::new(heterogeneous_medium::HeterogeneousMedium::new(
+        boundary, density, droplets,
+    )));
+
+    return Scene {
+        world,
+        materials,
+        camera: chapter_camera(),
+    };
+}
+
+// A ball of dense flint glass, whose dispersion fringes the spheres seen
+// through it, rendered spectrally.
+fn dispersion() -> Scene {
+    let mut materials = material::MaterialList::new();
+    let ground = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.8, 0.8, 0.8),
+    )));
+    let red = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.7, 0.1, 0.1),
+    )));
+    let blue = materials.add(material::Material::Lambertian(material::Lambertian::new(
+        Color::new(0.1, 0.2, 0.7),
+    )));
+    // Schott SF11
+    let flint = materials.add(material::Material::Dielectric(
+        material::Dielectric::sellmeier(
+            [1.73759695, 0.313747346, 1.89878101],
+            [0.013188707, 0.0623068142, 155.23629],
+        ),
+    ));
+
+    let mut world = hittable_list::HittableList::new(Vec::new());
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        ground,
+    )));
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        flint,
+    )));
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(-1.2, 0.0, -2.2),
+        0.5,
+        red,
+    )));
+    world.add(Box::new(hittable::Sphere::new(
+        Point3::new(1.2, 0.0, -2.2),
+        0.5,
+        blue,
+    )));
+
+    let mut cam = chapter_camera();
+    cam.spectral = true;
+    return Scene {
+        world,
+        materials,
+        camera: cam,
+    };
+}
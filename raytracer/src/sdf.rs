@@ -0,0 +1,414 @@
+use crate::hittable;
+use crate::interval;
+use crate::material;
+use crate::ray;
+use crate::stats;
+use crate::vector;
+
+use std::boxed::Box;
+
+use vector::Vec3 as Point3;
+
+// A composable signed distance expression. Primitives are centred on the
+// origin; use translate and scale to place them.
+pub enum Sdf {
+    Sphere {
+        radius: f64,
+    },
+    RoundBox {
+        half_extents: vector::Vec3,
+        radius: f64,
+    },
+    // lies in the xz plane
+    Torus {
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    Mandelbulb {
+        power: f64,
+        iterations: i32,
+    },
+    Translate {
+        offset: vector::Vec3,
+        sdf: Box<Sdf>,
+    },
+    Scale {
+        factor: f64,
+        sdf: Box<Sdf>,
+    },
+    Union(Box<Sdf>, Box<Sdf>),
+    Intersection(Box<Sdf>, Box<Sdf>),
+    Difference(Box<Sdf>, Box<Sdf>),
+    SmoothUnion {
+        k: f64,
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+    },
+}
+
+impl Sdf {
+    pub fn sphere(radius: f64) -> Sdf {
+        Sdf::Sphere { radius }
+    }
+
+    pub fn round_box(half_extents: vector::Vec3, radius: f64) -> Sdf {
+        Sdf::RoundBox {
+            half_extents,
+            radius,
+        }
+    }
+
+    pub fn torus(major_radius: f64, minor_radius: f64) -> Sdf {
+        Sdf::Torus {
+            major_radius,
+            minor_radius,
+        }
+    }
+
+    pub fn mandelbulb(power: f64, iterations: i32) -> Sdf {
+        Sdf::Mandelbulb { power, iterations }
+    }
+
+    pub fn translate(self, offset: vector::Vec3) -> Sdf {
+        Sdf::Translate {
+            offset,
+            sdf: Box::new(self),
+        }
+    }
+
+    pub fn scale(self, factor: f64) -> Sdf {
+        Sdf::Scale {
+            factor,
+            sdf: Box::new(self),
+        }
+    }
+
+    pub fn union(self, other: Sdf) -> Sdf {
+        Sdf::Union(Box::new(self), Box::new(other))
+    }
+
+    pub fn intersection(self, other: Sdf) -> Sdf {
+        Sdf::Intersection(Box::new(self), Box::new(other))
+    }
+
+    pub fn difference(self, other: Sdf) -> Sdf {
+        Sdf::Difference(Box::new(self), Box::new(other))
+    }
+
+    pub fn smooth_union(self, other: Sdf, k: f64) -> Sdf {
+        Sdf::SmoothUnion {
+            k,
+            a: Box::new(self),
+            b: Box::new(other),
+        }
+    }
+
+    pub fn distance(&self, p: Point3) -> f64 {
+        match self {
+            Sdf::Sphere { radius } => p.length() - radius,
+            Sdf::RoundBox {
+                half_extents,
+                radius,
+            } => {
+                let q = Point3::new(
+                    p.x().abs() - half_extents.x(),
+                    p.y().abs() - half_extents.y(),
+                    p.z().abs() - half_extents.z(),
+                );
+                let outside = Point3::new(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0));
+                let inside = q.x().max(q.y().max(q.z())).min(0.0);
+                outside.length() + inside - radius
+            }
+            Sdf::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let qx = (p.x() * p.x() + p.z() * p.z()).sqrt() - major_radius;
+                (qx * qx + p.y() * p.y()).sqrt() - minor_radius
+            }
+            Sdf::Mandelbulb { power, iterations } => {
+                Self::mandelbulb_distance(p, *power, *iterations)
+            }
+            Sdf::Translate { offset, sdf } => sdf.distance(p - *offset),
+            Sdf::Scale { factor, sdf } => sdf.distance(p / *factor) * factor,
+            Sdf::Union(a, b) => a.distance(p).min(b.distance(p)),
+            Sdf::Intersection(a, b) => a.distance(p).max(b.distance(p)),
+            Sdf::Difference(a, b) => a.distance(p).max(-b.distance(p)),
+            Sdf::SmoothUnion { k, a, b } => {
+                let da = a.distance(p);
+                let db = b.distance(p);
+                let h = (0.5 + 0.5 * (db - da) / k).clamp(0.0, 1.0);
+                db * (1.0 - h) + da * h - k * h * (1.0 - h)
+            }
+        }
+    }
+
+    // Distance estimator from the running derivative of the power iteration.
+    fn mandelbulb_distance(p: Point3, power: f64, iterations: i32) -> f64 {
+        // Far from the set the estimator overshoots and marching steps over
+        // it. Every point farther than 2 from the origin escapes, so the
+        // distance to that sphere is a safe step there.
+        let bound = p.length() - 2.0;
+        if bound > 0.5 {
+            return bound;
+        }
+        let mut z = p;
+        let mut dr = 1.0;
+        let mut r = 0.0;
+        for _ in 0..iterations {
+            r = z.length();
+            if !(1e-12..=2.0).contains(&r) {
+                break;
+            }
+            let theta = (z.z() / r).acos() * power;
+            let phi = z.y().atan2(z.x()) * power;
+            dr = r.powf(power - 1.0) * power * dr + 1.0;
+            let zr = r.powf(power);
+            z = Point3::new(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            ) * zr
+                + p;
+        }
+        if r < 1e-12 {
+            return 0.0;
+        }
+        return 0.5 * r.ln() * r / dr;
+    }
+}
+
+pub struct SdfObject {
+    pub sdf: Sdf,
+    pub mat: material::MaterialId,
+    pub max_steps: i32,
+    pub epsilon: f64,
+}
+
+impl SdfObject {
+    // Sphere tracing needs a finite end point, so unbounded ray intervals end
+    // at this distance along the ray.
+    pub const MAX_DISTANCE: f64 = 1.0e4;
+
+    pub fn new(sdf: Sdf, mat: material::MaterialId) -> SdfObject {
+        SdfObject {
+            sdf,
+            mat,
+            max_steps: 256,
+            epsilon: 1e-4,
+        }
+    }
+
+    // Returns the first t in (t_min, t_max) where the ray reaches the surface.
+    // Marching never starts behind the ray origin, where an unbounded t_min
+    // would spend the step budget far from any hit in front of the ray.
+    fn march(&self, r: &ray::Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        let len = r.direction().length();
+        let t_end = t_max.min(Self::MAX_DISTANCE / len);
+        let mut t = t_min.max(0.0);
+
+        // A ray spawned from a previous hit starts on the surface, so step off
+        // it before looking for the next crossing.
+        let mut leaving = self.sdf.distance(r.at(t)).abs() < self.epsilon;
+        for _ in 0..self.max_steps {
+            if t >= t_end {
+                return None;
+            }
+            let d = self.sdf.distance(r.at(t)).abs();
+            if d < self.epsilon {
+                if !leaving && t > t_min {
+                    return Some(t);
+                }
+            } else {
+                leaving = false;
+            }
+            t += d.max(self.epsilon) / len;
+        }
+        return None;
+    }
+
+    fn normal(&self, p: Point3) -> vector::Vec3 {
+        let h = self.epsilon;
+        let dx = vector::Vec3::new(h, 0.0, 0.0);
+        let dy = vector::Vec3::new(0.0, h, 0.0);
+        let dz = vector::Vec3::new(0.0, 0.0, h);
+        let gradient = vector::Vec3::new(
+            self.sdf.distance(p + dx) - self.sdf.distance(p - dx),
+            self.sdf.distance(p + dy) - self.sdf.distance(p - dy),
+            self.sdf.distance(p + dz) - self.sdf.distance(p - dz),
+        );
+        if gradient.near_zero() {
+            return vector::Vec3::new(0.0, 1.0, 0.0);
+        }
+        return gradient.unit_vector();
+    }
+
+    fn record(&self, r: &ray::Ray, t: f64, rec: &mut hittable::HitRecord) {
+        rec.t = t;
+        rec.p = r.at(t);
+        let outward_normal = self.normal(rec.p);
+        rec.set_normal_face(r, &outward_normal);
+        rec.mat = self.mat;
+    }
+}
+
+impl hittable::Hittable for SdfObject {
+    fn hit(
+        &mut self,
+        r: &ray::Ray,
+        ray_t: interval::Interval,
+        rec: &mut hittable::HitRecord,
+    ) -> bool {
+        stats::count(stats::Counter::IntersectionTest);
+        match self.march(r, ray_t.min, ray_t.max) {
+            Some(t) => {
+                self.record(r, t, rec);
+                return true;
+            }
+            None => return false,
+        }
+    }
+
+    fn hit_all(
+        &mut self,
+        r: &ray::Ray,
+        ray_t: interval::Interval,
+        hits: &mut Vec<hittable::HitRecord>,
+    ) {
+        // Crossings behind the ray origin are not marched, so a ray that
+        // starts inside reports its entry there to keep the crossings paired.
+        let start = ray_t.min.max(0.0);
+        if ray_t.min < start && self.sdf.distance(r.at(start)) < 0.0 {
+            let mut rec = hittable::HitRecord::default();
+            self.record(r, start, &mut rec);
+            rec.front_face = true;
+            hits.push(rec);
+        }
+        let mut rec = hittable::HitRecord::default();
+        let mut t_min = start;
+        while self.hit(r, interval::Interval::new(t_min, ray_t.max), &mut rec) {
+            hits.push(rec);
+            t_min = rec.t;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::hittable::Hittable;
+
+    fn assert_hit(sdf: Sdf, r: ray::Ray, t: f64, normal: vector::Vec3) {
+        let mut object = SdfObject::new(sdf, material::MaterialId::default());
+        let mut rec = hittable::HitRecord::default();
+        assert!(object.hit(&r, interval::Interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!(
+            (rec.t - t).abs() < 1e-3,
+            "hit at t = {}, expected {}",
+            rec.t,
+            t
+        );
+        assert!(
+            (rec.geometric_normal - normal).length() < 1e-3,
+            "normal ({}, {}, {})",
+            rec.geometric_normal.x(),
+            rec.geometric_normal.y(),
+            rec.geometric_normal.z()
+        );
+    }
+
+    #[test]
+    fn sphere_hits_at_the_analytic_distance() {
+        let r = ray::Ray::new(
+            Point3::new(0.0, 0.0, -5.0),
+            vector::Vec3::new(0.0, 0.0, 2.0),
+        );
+        assert_hit(Sdf::sphere(1.0), r, 2.0, vector::Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn torus_hits_at_the_analytic_distance() {
+        // straight down onto the tube, which lies at distance 1 from the y axis
+        let r = ray::Ray::new(
+            Point3::new(1.0, 5.0, 0.0),
+            vector::Vec3::new(0.0, -1.0, 0.0),
+        );
+        assert_hit(
+            Sdf::torus(1.0, 0.25),
+            r,
+            4.75,
+            vector::Vec3::new(0.0, 1.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn rounded_box_hits_at_the_analytic_distance() {
+        let r = ray::Ray::new(
+            Point3::new(-5.0, 0.2, 0.3),
+            vector::Vec3::new(1.0, 0.0, 0.0),
+        );
+        let sdf = Sdf::round_box(vector::Vec3::new(1.0, 0.5, 0.5), 0.1);
+        assert_hit(sdf, r, 3.9, vector::Vec3::new(-1.0, 0.0, 0.0));
+
+        // through the rounded edge at 45 degrees
+        let diagonal = vector::Vec3::new(1.0, 1.0, 0.0).unit_vector();
+        let corner = Point3::new(1.0, 0.5, 0.0) + diagonal * 0.1;
+        let r = ray::Ray::new(corner + diagonal * 3.0, -diagonal);
+        let sdf = Sdf::round_box(vector::Vec3::new(1.0, 0.5, 0.5), 0.1);
+        assert_hit(sdf, r, 3.0, diagonal);
+    }
+
+    #[test]
+    fn distant_rays_hit_the_mandelbulb() {
+        for distance in [3.0, 10.0, 100.0] {
+            let mut bulb =
+                SdfObject::new(Sdf::mandelbulb(8.0, 10), material::MaterialId::default());
+            let r = ray::Ray::new(
+                Point3::new(0.1, 0.2, -distance),
+                vector::Vec3::new(0.0, 0.0, 1.0),
+            );
+            let mut rec = hittable::HitRecord::default();
+            assert!(
+                bulb.hit(&r, interval::Interval::new(0.001, f64::INFINITY), &mut rec),
+                "missed from {}",
+                distance
+            );
+            assert!(rec.p.length() < 1.5, "hit at {}", rec.p.length());
+        }
+    }
+
+    #[test]
+    fn unbounded_intervals_find_the_crossings_in_front() {
+        let mut sphere = SdfObject::new(Sdf::sphere(1.0), material::MaterialId::default());
+        let r = ray::Ray::new(
+            Point3::new(0.0, 0.0, -5.0),
+            vector::Vec3::new(0.0, 0.0, 1.0),
+        );
+        // a thin rod behind the origin, along the ray, would use up the step
+        // budget if marching started at the interval's minimum
+        let rod = Sdf::round_box(vector::Vec3::new(0.02, 0.02, 500.0), 0.0)
+            .translate(vector::Vec3::new(0.05, 0.0, -510.0));
+        let mut with_rod =
+            SdfObject::new(Sdf::sphere(1.0).union(rod), material::MaterialId::default());
+        let mut rec = hittable::HitRecord::default();
+        assert!(with_rod.hit(&r, interval::UNIVERSE, &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-3);
+
+        let mut hits = Vec::new();
+        sphere.hit_all(&r, interval::UNIVERSE, &mut hits);
+        let crossings: Vec<(f64, bool)> = hits.iter().map(|rec| (rec.t, rec.front_face)).collect();
+        assert!(crossings.len() == 2, "{:?}", crossings);
+        assert!((crossings[0].0 - 4.0).abs() < 1e-3 && crossings[0].1);
+        assert!((crossings[1].0 - 6.0).abs() < 1e-3 && !crossings[1].1);
+
+        // from inside, the entry is reported where the ray starts
+        let r = ray::Ray::new(Point3::new(0.0, 0.0, 0.0), vector::Vec3::new(0.0, 0.0, 1.0));
+        hits.clear();
+        sphere.hit_all(&r, interval::UNIVERSE, &mut hits);
+        let crossings: Vec<(f64, bool)> = hits.iter().map(|rec| (rec.t, rec.front_face)).collect();
+        assert!(crossings.len() == 2, "{:?}", crossings);
+        assert!(crossings[0] == (0.0, true));
+        assert!((crossings[1].0 - 1.0).abs() < 1e-3 && !crossings[1].1);
+    }
+}
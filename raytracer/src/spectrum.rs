@@ -0,0 +1,274 @@
+use crate::vector;
+
+use std::sync::OnceLock;
+
+use vector::Vec3 as Color;
+
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 780.0;
+pub const N_SAMPLES: usize = 4;
+
+// Radiance or reflectance at each of the wavelengths carried by a path.
+#[derive(Copy, Clone)]
+pub struct SampledSpectrum {
+    pub values: [f64; N_SAMPLES],
+}
+
+impl SampledSpectrum {
+    pub fn new(value: f64) -> Self {
+        SampledSpectrum {
+            values: [value; N_SAMPLES],
+        }
+    }
+
+    // Upsamples an RGB reflectance (or an RGB emission) to the wavelengths.
+    pub fn from_rgb(rgb: Color, wavelengths: &SampledWavelengths) -> Self {
+        let mut values = [0.0; N_SAMPLES];
+        for (value, lambda) in values.iter_mut().zip(wavelengths.lambda.iter()) {
+            *value = rgb_to_spectrum(rgb, *lambda);
+        }
+        SampledSpectrum { values }
+    }
+}
+
+impl std::ops::Mul for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        let mut values = self.values;
+        for (value, other) in values.iter_mut().zip(rhs.values.iter()) {
+            *value *= other;
+        }
+        SampledSpectrum { values }
+    }
+}
+
+// Hero wavelength sampling: the first wavelength is drawn uniformly and the
+// rest are spaced evenly after it, wrapping around the visible range. The
+// hero wavelength decides the direction of wavelength-dependent scattering.
+#[derive(Copy, Clone)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; N_SAMPLES],
+    pub pdf: [f64; N_SAMPLES],
+}
+
+impl SampledWavelengths {
+    pub fn sample_uniform(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut lambda = [0.0; N_SAMPLES];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let offset = u * range + i as f64 * range / N_SAMPLES as f64;
+            *l = LAMBDA_MIN + offset % range;
+        }
+        SampledWavelengths {
+            lambda,
+            pdf: [1.0 / range; N_SAMPLES],
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        return self.lambda[0];
+    }
+
+    // Called once a path has scattered in a wavelength-dependent direction;
+    // only the hero wavelength remains valid and it carries the full weight.
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for pdf in self.pdf.iter_mut().skip(1) {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= N_SAMPLES as f64;
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        return self.pdf[1..].iter().all(|pdf| *pdf == 0.0);
+    }
+
+    // Monte Carlo estimate of the linear RGB color of a sampled radiance,
+    // white balanced so that a flat spectrum maps to (1, 1, 1).
+    pub fn estimate_rgb(&self, radiance: &SampledSpectrum) -> Color {
+        let mut xyz = Color::new(0.0, 0.0, 0.0);
+        for i in 0..N_SAMPLES {
+            if self.pdf[i] == 0.0 {
+                continue;
+            }
+            xyz = xyz + cie_xyz(self.lambda[i]) * (radiance.values[i] / self.pdf[i]);
+        }
+        let rgb = xyz_to_linear_srgb(xyz / N_SAMPLES as f64);
+        let white = flat_spectrum_rgb();
+        return Color::new(
+            rgb.x() / white.x(),
+            rgb.y() / white.y(),
+            rgb.z() / white.z(),
+        );
+    }
+}
+
+// Multi-lobe Gaussian fit to the CIE 1931 2-degree colour matching functions
+// (Wyman, Sloan and Shirley 2013).
+pub fn cie_xyz(lambda: f64) -> Color {
+    let g = |mu: f64, sigma_low: f64, sigma_high: f64| {
+        let sigma = if lambda < mu { sigma_low } else { sigma_high };
+        let t = (lambda - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+    let x =
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2);
+    let y = 0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1);
+    let z = 1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8);
+    return Color::new(x, y, z);
+}
+
+pub fn xyz_to_linear_srgb(xyz: Color) -> Color {
+    return Color::new(
+        3.2404542 * xyz.x() - 1.5371385 * xyz.y() - 0.4985314 * xyz.z(),
+        -0.9692660 * xyz.x() + 1.8760108 * xyz.y() + 0.0415560 * xyz.z(),
+        0.0556434 * xyz.x() - 0.2040259 * xyz.y() + 1.0572252 * xyz.z(),
+    );
+}
+
+// Linear sRGB response to a constant unit spectrum over the sampled range.
+fn flat_spectrum_rgb() -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    return *WHITE.get_or_init(|| {
+        let mut xyz = Color::new(0.0, 0.0, 0.0);
+        let mut lambda = LAMBDA_MIN + 0.5;
+        while lambda < LAMBDA_MAX {
+            xyz = xyz + cie_xyz(lambda);
+            lambda += 1.0;
+        }
+        xyz_to_linear_srgb(xyz)
+    });
+}
+
+// Smits' RGB to spectrum conversion: the RGB value is split into white plus
+// at most one of cyan/magenta/yellow plus one of red/green/blue, each with a
+// smooth basis spectrum over ten bins spanning 380-720 nm.
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+pub fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    let bin = ((lambda - 380.0) / 34.0).floor().clamp(0.0, 9.0) as usize;
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+
+    if r <= g && r <= b {
+        let base = r * SMITS_WHITE[bin];
+        if g <= b {
+            return base + (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin];
+        }
+        return base + (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin];
+    }
+    if g <= r && g <= b {
+        let base = g * SMITS_WHITE[bin];
+        if r <= b {
+            return base + (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin];
+        }
+        return base + (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin];
+    }
+    let base = b * SMITS_WHITE[bin];
+    if r <= g {
+        return base + (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin];
+    }
+    return base + (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Color, b: Color, tolerance: f64) -> bool {
+        return (a - b).length() < tolerance;
+    }
+
+    // Averages estimate_rgb of a spectrum over stratified hero wavelengths.
+    fn render_rgb(spectrum: impl Fn(&SampledWavelengths) -> SampledSpectrum) -> Color {
+        let n = 4000;
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for i in 0..n {
+            let wavelengths = SampledWavelengths::sample_uniform((i as f64 + 0.5) / n as f64);
+            sum = sum + wavelengths.estimate_rgb(&spectrum(&wavelengths));
+        }
+        return sum / n as f64;
+    }
+
+    #[test]
+    fn spectra_multiply_per_wavelength() {
+        let a = SampledSpectrum {
+            values: [1.0, 2.0, 3.0, 4.0],
+        };
+        let b = SampledSpectrum {
+            values: [0.5, 0.25, 2.0, 0.0],
+        };
+        assert!((a * b).values == [0.5, 0.5, 6.0, 0.0]);
+    }
+
+    #[test]
+    fn hero_wavelengths_are_evenly_spaced() {
+        let wavelengths = SampledWavelengths::sample_uniform(0.9);
+        assert!((wavelengths.hero() - 740.0).abs() < 1e-9);
+        // 740 and then 100 nm steps, wrapping around from 780 to 380
+        for (lambda, expected) in wavelengths.lambda.iter().zip([740.0, 440.0, 540.0, 640.0]) {
+            assert!((lambda - expected).abs() < 1e-9);
+        }
+
+        let mut terminated = wavelengths;
+        terminated.terminate_secondary();
+        terminated.terminate_secondary();
+        assert!(terminated.secondary_terminated());
+        // the hero carries the weight of all four wavelengths, once
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        assert!((terminated.pdf[0] - 1.0 / range / N_SAMPLES as f64).abs() < 1e-15);
+    }
+
+    #[test]
+    fn flat_spectra_render_white() {
+        let white = render_rgb(|_| SampledSpectrum::new(1.0));
+        assert!(close(white, Color::new(1.0, 1.0, 1.0), 1e-3));
+
+        // once the secondary wavelengths are dropped the hero alone gives the
+        // same expectation
+        let n = 4000;
+        let mut hero_only = Color::new(0.0, 0.0, 0.0);
+        for i in 0..n {
+            let mut wavelengths = SampledWavelengths::sample_uniform((i as f64 + 0.5) / n as f64);
+            wavelengths.terminate_secondary();
+            hero_only = hero_only + wavelengths.estimate_rgb(&SampledSpectrum::new(1.0));
+        }
+        assert!(close(hero_only / n as f64, Color::new(1.0, 1.0, 1.0), 1e-3));
+    }
+
+    #[test]
+    fn upsampled_rgb_renders_back_to_its_color() {
+        for grey in [0.2, 0.5, 1.0] {
+            let rgb = Color::new(grey, grey, grey);
+            let rendered = render_rgb(|wavelengths| SampledSpectrum::from_rgb(rgb, wavelengths));
+            assert!(close(rendered, rgb, 0.01 * grey));
+        }
+        // Smits' spectra are smooth, so saturated colors only come back close
+        let red = Color::new(0.8, 0.1, 0.1);
+        let rendered = render_rgb(|wavelengths| SampledSpectrum::from_rgb(red, wavelengths));
+        assert!(rendered.x() > 0.6 && rendered.y() < 0.2 && rendered.z() < 0.2);
+        assert!(rgb_to_spectrum(red, 650.0) > 0.7 && rgb_to_spectrum(red, 450.0) < 0.2);
+    }
+}
@@ -0,0 +1,233 @@
+use std::cell::Cell;
+use std::fmt::Write;
+use std::time::Duration;
+use std::time::Instant;
+
+// Render counters. They are kept per thread, which covers the whole render
+// since it runs on the main thread. The scene is a flat HittableList with no
+// acceleration structure, so there are no BVH node visits to count.
+#[derive(Clone, Copy, Default)]
+pub struct Counters {
+    pub camera_rays: u64,
+    pub bounce_rays: u64,
+    // primitive intersection tests
+    pub intersection_tests: u64,
+}
+
+impl Counters {
+    pub fn rays(&self) -> u64 {
+        return self.camera_rays + self.bounce_rays;
+    }
+
+    // Mean number of segments in a camera path.
+    pub fn average_path_length(&self) -> f64 {
+        if self.camera_rays == 0 {
+            return 0.0;
+        }
+        return (self.camera_rays + self.bounce_rays) as f64 / self.camera_rays as f64;
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Counter {
+    CameraRay,
+    BounceRay,
+    IntersectionTest,
+}
+
+thread_local! {
+    static COUNTERS: Cell<Counters> = const {
+        Cell::new(Counters {
+            camera_rays: 0,
+            bounce_rays: 0,
+            intersection_tests: 0,
+        })
+    };
+}
+
+pub fn count(counter: Counter) {
+    COUNTERS.with(|counters| {
+        let mut c = counters.get();
+        match counter {
+            Counter::CameraRay => c.camera_rays += 1,
+            Counter::BounceRay => c.bounce_rays += 1,
+            Counter::IntersectionTest => c.intersection_tests += 1,
+        }
+        counters.set(c);
+    });
+}
+
+pub fn counters() -> Counters {
+    return COUNTERS.with(|counters| counters.get());
+}
+
+// Wall-clock time of the phases of a run along with the counters.
+pub struct Summary {
+    phases: Vec<(&'static str, Duration)>,
+    counters: Counters,
+}
+
+impl Summary {
+    pub fn new() -> Self {
+        Summary {
+            phases: Vec::new(),
+            counters: Counters::default(),
+        }
+    }
+
+    // Runs f as the named phase and records how long it took.
+    pub fn time<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.phases.push((phase, start.elapsed()));
+        return result;
+    }
+
+    // Takes the counters as they stand now.
+    pub fn finish(&mut self) {
+        self.counters = counters();
+    }
+
+    fn phase_seconds(&self, name: &str) -> f64 {
+        return self
+            .phases
+            .iter()
+            .filter(|(phase, _)| *phase == name)
+            .map(|(_, duration)| duration.as_secs_f64())
+            .sum();
+    }
+
+    // Rays traced per second of the render phase.
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.phase_seconds("render");
+        if seconds <= 0.0 {
+            return 0.0;
+        }
+        return self.counters.rays() as f64 / seconds;
+    }
+
+    pub fn to_text(&self) -> String {
+        let c = &self.counters;
+        let mut out = String::new();
+        for (phase, duration) in &self.phases {
+            let _ = writeln!(out, "{:<20} {:>12.3} s", phase, duration.as_secs_f64());
+        }
+        let _ = writeln!(out, "{:<20} {:>12}", "camera rays", c.camera_rays);
+        let _ = writeln!(out, "{:<20} {:>12}", "bounce rays", c.bounce_rays);
+        let _ = writeln!(
+            out,
+            "{:<20} {:>12}",
+            "intersection tests", c.intersection_tests
+        );
+        let _ = writeln!(
+            out,
+            "{:<20} {:>12.3}",
+            "average path length",
+            c.average_path_length()
+        );
+        let _ = writeln!(
+            out,
+            "{:<20} {:>12.0}",
+            "rays per second",
+            self.rays_per_second()
+        );
+        return out;
+    }
+
+    pub fn to_json(&self) -> String {
+        let c = &self.counters;
+        let mut out = String::new();
+        let _ = writeln!(out, "{{");
+        let _ = writeln!(out, "  \"phases\": {{");
+        for (i, (phase, duration)) in self.phases.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "    \"{}\": {}{}",
+                phase,
+                duration.as_secs_f64(),
+                if i + 1 < self.phases.len() { "," } else { "" }
+            );
+        }
+        let _ = writeln!(out, "  }},");
+        let _ = writeln!(out, "  \"camera_rays\": {},", c.camera_rays);
+        let _ = writeln!(out, "  \"bounce_rays\": {},", c.bounce_rays);
+        let _ = writeln!(out, "  \"intersection_tests\": {},", c.intersection_tests);
+        let _ = writeln!(
+            out,
+            "  \"average_path_length\": {},",
+            c.average_path_length()
+        );
+        let _ = writeln!(out, "  \"rays_per_second\": {}", self.rays_per_second());
+        let _ = writeln!(out, "}}");
+        return out;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary() -> Summary {
+        return Summary {
+            phases: vec![
+                ("scene build", Duration::from_millis(500)),
+                ("render", Duration::from_secs(2)),
+            ],
+            counters: Counters {
+                camera_rays: 100,
+                bounce_rays: 300,
+                intersection_tests: 1200,
+            },
+        };
+    }
+
+    #[test]
+    fn counters_count_on_this_thread() {
+        let before = counters();
+        count(Counter::CameraRay);
+        count(Counter::BounceRay);
+        count(Counter::BounceRay);
+        count(Counter::IntersectionTest);
+        let after = counters();
+        assert!(after.camera_rays - before.camera_rays == 1);
+        assert!(after.bounce_rays - before.bounce_rays == 2);
+        assert!(after.intersection_tests - before.intersection_tests == 1);
+
+        let c = summary().counters;
+        assert!(c.rays() == 400 && c.average_path_length() == 4.0);
+        assert!(Counters::default().average_path_length() == 0.0);
+    }
+
+    #[test]
+    fn rays_per_second_of_the_render_phase() {
+        let mut summary = summary();
+        assert!(summary.rays_per_second() == 200.0);
+        // phases with the same name add up
+        summary.phases.push(("render", Duration::from_secs(2)));
+        assert!(summary.rays_per_second() == 100.0);
+        summary.phases.clear();
+        assert!(summary.rays_per_second() == 0.0);
+    }
+
+    #[test]
+    fn json_lists_the_phases_and_counters() {
+        let expected = "{
+  \"phases\": {
+    \"scene build\": 0.5,
+    \"render\": 2
+  },
+  \"camera_rays\": 100,
+  \"bounce_rays\": 300,
+  \"intersection_tests\": 1200,
+  \"average_path_length\": 4,
+  \"rays_per_second\": 200
+}
+";
+        assert!(summary().to_json() == expected, "{}", summary().to_json());
+
+        // no trailing comma without phases
+        let mut summary = summary();
+        summary.phases.clear();
+        assert!(summary.to_json().starts_with("{\n  \"phases\": {\n  },\n"));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::camera;
+use crate::hittable;
+use crate::image;
+use crate::material;
+use crate::vector;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StereoLayout {
+    // left eye on the left half, right eye on the right half
+    SideBySide,
+    // left eye on the top half, right eye on the bottom half
+    TopBottom,
+}
+
+impl StereoLayout {
+    pub fn from_name(name: &str) -> Option<StereoLayout> {
+        match name {
+            "side-by-side" => Some(StereoLayout::SideBySide),
+            "top-bottom" => Some(StereoLayout::TopBottom),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StereoMode {
+    // two planar views toed in to meet at the convergence distance
+    Planar,
+    // omni-directional stereo: each eye is a full equirectangular panorama
+    // whose rays start on a circle of diameter interpupillary_distance
+    OmniDirectional,
+}
+
+// A stereo rig built on a camera. The camera's look_from is the point
+// between the eyes; each eye is rendered with the camera's other settings.
+pub struct StereoCamera {
+    pub camera: camera::Camera,
+    pub interpupillary_distance: f64,
+    pub convergence_distance: f64,
+    pub layout: StereoLayout,
+    pub mode: StereoMode,
+}
+
+impl StereoCamera {
+    pub fn new(camera: camera::Camera) -> Self {
+        StereoCamera {
+            camera,
+            interpupillary_distance: 0.064,
+            convergence_distance: 10.0,
+            layout: StereoLayout::SideBySide,
+            mode: StereoMode::Planar,
+        }
+    }
+
+    pub fn render_image(
+        &mut self,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+    ) -> image::Image {
+        let left = self.render_eye(-0.5, world, materials);
+        let right = self.render_eye(0.5, world, materials);
+
+        let (width, height) = (left.width, left.height);
+        match self.layout {
+            StereoLayout::SideBySide => {
+                let mut image = image::Image::new(width * 2, height);
+                image.blit(&left, 0, 0);
+                image.blit(&right, width, 0);
+                return image;
+            }
+            StereoLayout::TopBottom => {
+                let mut image = image::Image::new(width, height * 2);
+                image.blit(&left, 0, 0);
+                image.blit(&right, 0, height);
+                return image;
+            }
+        }
+    }
+
+    // The look_from and look_at of a planar eye. side is -0.5 for the left
+    // eye and 0.5 for the right eye.
+    fn planar_eye(&self, side: f64) -> (vector::Vec3, vector::Vec3) {
+        let cam = &self.camera;
+        let forward = (cam.look_at - cam.look_from).unit_vector();
+        let right = vector::cross(&forward, &cam.v_up).unit_vector();
+        return (
+            cam.look_from + right * (side * self.interpupillary_distance),
+            cam.look_from + forward * self.convergence_distance,
+        );
+    }
+
+    // side is -0.5 for the left eye and 0.5 for the right eye
+    fn render_eye(
+        &mut self,
+        side: f64,
+        world: &mut dyn hittable::Hittable,
+        materials: &material::MaterialList,
+    ) -> image::Image {
+        let eye_offset = side * self.interpupillary_distance;
+        let eye = self.planar_eye(side);
+        let cam = &mut self.camera;
+
+        match self.mode {
+            StereoMode::Planar => {
+                let (look_from, look_at) = (cam.look_from, cam.look_at);
+                (cam.look_from, cam.look_at) = eye;
+                let image = cam.render_image(world, materials);
+
+                cam.look_from = look_from;
+                cam.look_at = look_at;
+                return image;
+            }
+            StereoMode::OmniDirectional => {
+                let (projection, ods_eye_offset) = (cam.projection, cam.ods_eye_offset);
+
+                cam.projection = camera::Projection::Equirectangular;
+                cam.ods_eye_offset = eye_offset;
+                let image = cam.render_image(world, materials);
+
+                cam.projection = projection;
+                cam.ods_eye_offset = ods_eye_offset;
+                return image;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::hittable_list;
+    use crate::progress;
+
+    // An 8x4 camera at the origin looking down -z.
+    fn rig(layout: StereoLayout, mode: StereoMode) -> StereoCamera {
+        let mut cam = camera::Camera::new();
+        cam.image_width = 8;
+        cam.aspect_ratio = 2.0;
+        cam.samples_per_pixel = 1;
+        cam.look_from = vector::Vec3::new(0.0, 0.0, 0.0);
+        cam.look_at = vector::Vec3::new(0.0, 0.0, -1.0);
+        cam.progress.verbosity = progress::Verbosity::Quiet;
+        let mut rig = StereoCamera::new(cam);
+        rig.layout = layout;
+        rig.mode = mode;
+        return rig;
+    }
+
+    fn render(rig: &mut StereoCamera) -> image::Image {
+        let mut world = hittable_list::HittableList::new(Vec::new());
+        return rig.render_image(&mut world, &material::MaterialList::new());
+    }
+
+    #[test]
+    fn layouts_stack_the_eyes() {
+        let image = render(&mut rig(StereoLayout::SideBySide, StereoMode::Planar));
+        assert!(image.width == 16 && image.height == 4);
+        let image = render(&mut rig(StereoLayout::TopBottom, StereoMode::Planar));
+        assert!(image.width == 8 && image.height == 8);
+        let image = render(&mut rig(
+            StereoLayout::TopBottom,
+            StereoMode::OmniDirectional,
+        ));
+        assert!(image.width == 8 && image.height == 8);
+    }
+
+    #[test]
+    fn eyes_sit_half_the_interpupillary_distance_apart() {
+        let mut rig = rig(StereoLayout::SideBySide, StereoMode::Planar);
+        rig.convergence_distance = 5.0;
+        let (left, left_target) = rig.planar_eye(-0.5);
+        let (right, right_target) = rig.planar_eye(0.5);
+        assert!((left - vector::Vec3::new(-0.032, 0.0, 0.0)).length() < 1e-12);
+        assert!((right - vector::Vec3::new(0.032, 0.0, 0.0)).length() < 1e-12);
+        // both eyes are toed in to the convergence point
+        let convergence = vector::Vec3::new(0.0, 0.0, -5.0);
+        assert!((left_target - convergence).length() < 1e-12);
+        assert!((right_target - convergence).length() < 1e-12);
+
+        // rendering leaves the camera where it was
+        render(&mut rig);
+        assert!(rig.camera.look_from.length() < 1e-12);
+        assert!((rig.camera.look_at - vector::Vec3::new(0.0, 0.0, -1.0)).length() < 1e-12);
+    }
+}
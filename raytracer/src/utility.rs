@@ -1,9 +1,53 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::cell::Cell;
+use std::cell::RefCell;
 use std::f64::consts::PI;
 
+thread_local! {
+    // None draws from the operating system seeded generator.
+    static SEEDED_RNG: RefCell<Option<StdRng>> = const { RefCell::new(None) };
+    // the value given to seed(), from which seed_sample derives its streams
+    static BASE_SEED: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     return degrees * PI / 180.0;
 }
 
+// Makes every following random number on this thread reproducible.
+pub fn seed(seed: u64) {
+    BASE_SEED.with(|base| base.set(Some(seed)));
+    SEEDED_RNG.with(|rng| *rng.borrow_mut() = Some(StdRng::seed_from_u64(seed)));
+}
+
+// Restarts the seeded stream at a point that depends only on the seed, the
+// pixel and the sample index, so a single sample of a render can be traced
+// again on its own. Does nothing unless seed() was called.
+pub fn seed_sample(x: i32, y: i32, sample: i32) {
+    let Some(base) = BASE_SEED.with(|base| base.get()) else {
+        return;
+    };
+    let mut key = base;
+    for value in [x as u32 as u64, y as u32 as u64, sample as u32 as u64] {
+        key = splitmix64(key ^ value);
+    }
+    SEEDED_RNG.with(|rng| *rng.borrow_mut() = Some(StdRng::seed_from_u64(key)));
+}
+
+// The SplitMix64 mixing function, a cheap bijective hash of 64 bits.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    return z ^ (z >> 31);
+}
+
 pub fn random_double(min: f64, max: f64) -> f64 {
-    return min + (max - min) * rand::random::<f64>();
+    let unit = SEEDED_RNG.with(|rng| match rng.borrow_mut().as_mut() {
+        Some(rng) => rng.gen::<f64>(),
+        None => rand::random::<f64>(),
+    });
+    return min + (max - min) * unit;
 }
@@ -0,0 +1,581 @@
+use crate::constant_medium;
+use crate::hittable;
+use crate::interval;
+use crate::material;
+use crate::ray;
+use crate::vector;
+
+use std::f64::consts::PI;
+
+use vector::Vec3 as Color;
+use vector::Vec3 as Point3;
+
+// Statistical checks of the materials in material.rs:
+// - white furnace: a white object lit by a uniform white environment must
+//   look exactly as bright as the environment if it conserves energy
+// - chi-square: the directions scatter samples must follow the density that
+//   Material::pdf reports
+// - reciprocity: the scattering function must not change when the incoming
+//   and outgoing directions are swapped
+// Scatter weights a sample by its attenuation, so for surfaces the implied
+// BSDF is attenuation * pdf / cos(theta_o) and for phase functions it is
+// attenuation * pdf.
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Passed,
+    Failed,
+    // the check does not apply to the material
+    Skipped,
+}
+
+pub struct Report {
+    pub material: String,
+    pub check: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+pub struct Validator {
+    pub furnace_paths: usize,
+    pub chi2_samples: usize,
+    pub reciprocity_pairs: usize,
+    // family-wise significance level of the chi-square tests
+    pub significance: f64,
+}
+
+// One white variant of every material, with the parameters that matter.
+pub fn white_materials() -> Vec<(String, material::Material)> {
+    let white = Color::new(1.0, 1.0, 1.0);
+    return vec![
+        (
+            "lambertian".to_string(),
+            material::Material::Lambertian(material::Lambertian::new(white)),
+        ),
+        (
+            "metal (fuzz 0)".to_string(),
+            material::Material::Metal(material::Metal::new(white, 0.0)),
+        ),
+        (
+            "metal (fuzz 0.3)".to_string(),
+            material::Material::Metal(material::Metal::new(white, 0.3)),
+        ),
+        (
+            "metal (fuzz 1)".to_string(),
+            material::Material::Metal(material::Metal::new(white, 1.0)),
+        ),
+        (
+            "dielectric (ir 1.5)".to_string(),
+            material::Material::Dielectric(material::Dielectric::new(1.5)),
+        ),
+        (
+            "dielectric (Cauchy BK7)".to_string(),
+            material::Material::Dielectric(material::Dielectric::cauchy(1.5046, 0.0042)),
+        ),
+        (
+            "dielectric (Sellmeier BK7)".to_string(),
+            material::Material::Dielectric(material::Dielectric::sellmeier(
+                [1.03961212, 0.231792344, 1.01046945],
+                [0.00600069867, 0.0200179144, 103.560653],
+            )),
+        ),
+        (
+            "isotropic".to_string(),
+            material::Material::Isotropic(material::Isotropic::new(white)),
+        ),
+        (
+            "henyey-greenstein (g 0.6)".to_string(),
+            material::Material::HenyeyGreenstein(material::HenyeyGreenstein::new(white, 0.6)),
+        ),
+        (
+            "henyey-greenstein (g -0.4)".to_string(),
+            material::Material::HenyeyGreenstein(material::HenyeyGreenstein::new(white, -0.4)),
+        ),
+    ];
+}
+
+fn is_phase_function(mat: &material::Material) -> bool {
+    return matches!(
+        mat,
+        material::Material::Isotropic(_) | material::Material::HenyeyGreenstein(_)
+    );
+}
+
+// Incoming directions, as cosines to the normal, for the sampling checks.
+const INCIDENT_COSINES: [f64; 3] = [0.9, 0.5, 0.1];
+
+impl Validator {
+    const MAX_DEPTH: i32 = 256;
+    const THETA_BINS: usize = 20;
+    const PHI_BINS: usize = 40;
+    // cells expecting fewer samples are pooled together
+    const MIN_EXPECTED: f64 = 5.0;
+
+    pub fn new() -> Self {
+        Validator {
+            furnace_paths: 100_000,
+            chi2_samples: 200_000,
+            reciprocity_pairs: 10_000,
+            significance: 0.01,
+        }
+    }
+
+    pub fn run(&self) -> Vec<Report> {
+        let materials = white_materials();
+        let chi2_tests = materials.len() * INCIDENT_COSINES.len();
+        let mut reports = Vec::new();
+        for (name, mat) in &materials {
+            reports.push(self.white_furnace(name, mat));
+            reports.push(self.chi_square(name, mat, chi2_tests));
+            reports.push(self.reciprocity(name, mat));
+        }
+        return reports;
+    }
+
+    // Renders a unit sphere of the material, or a unit sphere of medium for
+    // phase functions, under a uniform environment of radiance 1. Paths that
+    // scatter are weighted by their attenuation and paths that are rejected
+    // carry no energy.
+    pub fn white_furnace(&self, name: &str, mat: &material::Material) -> Report {
+        let mut materials = material::MaterialList::new();
+        let id = materials.add(mat.clone());
+        let sphere = Box::new(hittable::Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, id));
+        let mut object: Box<dyn hittable::Hittable> = if is_phase_function(mat) {
+            Box::new(constant_medium::ConstantMedium::new(sphere, 2.0, id))
+        } else {
+            sphere
+        };
+
+        let mut sum = 0.0;
+        let mut sum_squares = 0.0;
+        let mut truncated = 0;
+        for _ in 0..self.furnace_paths {
+            // aim at a random point of the sphere's silhouette from outside
+            let disk = vector::Vec3::random_in_unit_disk();
+            let origin = Point3::new(disk.x(), disk.y(), 5.0);
+            let mut r = ray::Ray::new(origin, vector::Vec3::new(0.0, 0.0, -1.0));
+            let mut throughput = Color::new(1.0, 1.0, 1.0);
+            let mut escaped = false;
+
+            for _ in 0..Self::MAX_DEPTH {
+                let mut rec = hittable::HitRecord::default();
+                if !object.hit(&r, interval::Interval::new(0.001, f64::INFINITY), &mut rec) {
+                    escaped = true;
+                    break;
+                }
+                let mut attenuation = Color::new(0.0, 0.0, 0.0);
+                let mut scattered = ray::Ray::new(origin, origin);
+                if !materials
+                    .get(rec.mat)
+                    .scatter(&r, &rec, &mut attenuation, &mut scattered)
+                {
+                    throughput = Color::new(0.0, 0.0, 0.0);
+                    escaped = true;
+                    break;
+                }
+                throughput = throughput * attenuation;
+                r = scattered;
+            }
+            if !escaped {
+                truncated += 1;
+                continue;
+            }
+            let value = (throughput.x() + throughput.y() + throughput.z()) / 3.0;
+            sum += value;
+            sum_squares += value * value;
+        }
+
+        let n = self.furnace_paths as f64;
+        let mean = sum / n;
+        let standard_error = ((sum_squares / n - mean * mean).max(0.0) / n).sqrt();
+        // paths cut off at the depth limit count as lost energy, so they
+        // widen the tolerance
+        let tolerance = 4.0 * standard_error + truncated as f64 / n + 1e-3;
+        let status = if (mean - 1.0).abs() <= tolerance {
+            Status::Passed
+        } else {
+            Status::Failed
+        };
+        return Report {
+            material: name.to_string(),
+            check: "white furnace",
+            status,
+            detail: format!(
+                "reflected {:.4} +- {:.4} of the incident energy",
+                mean, standard_error
+            ),
+        };
+    }
+
+    // Bins scattered directions over the sphere around the normal and
+    // compares the counts with the integral of the pdf over each bin.
+    // tests is the number of chi-square tests in the whole run, used to keep
+    // the family-wise false alarm rate at the significance level.
+    pub fn chi_square(&self, name: &str, mat: &material::Material, tests: usize) -> Report {
+        let rec = Self::surface_record();
+        let mut worst_p_value = 1.0f64;
+
+        for cos_i in INCIDENT_COSINES {
+            let r_in = Self::incoming_ray(cos_i, 0.3);
+            if mat.pdf(&r_in, &rec, rec.normal).is_none() {
+                return Report {
+                    material: name.to_string(),
+                    check: "chi-square",
+                    status: Status::Skipped,
+                    detail: "scatters into discrete directions".to_string(),
+                };
+            }
+
+            let mut observed = vec![0.0; Self::THETA_BINS * Self::PHI_BINS];
+            for _ in 0..self.chi2_samples {
+                let mut attenuation = Color::new(0.0, 0.0, 0.0);
+                let mut scattered = r_in;
+                mat.scatter(&r_in, &rec, &mut attenuation, &mut scattered);
+                let d = scattered.direction().unit_vector();
+                let theta = d.z().clamp(-1.0, 1.0).acos();
+                let phi = d.y().atan2(d.x()).rem_euclid(2.0 * PI);
+                let i = ((theta / PI * Self::THETA_BINS as f64) as usize).min(Self::THETA_BINS - 1);
+                let j =
+                    ((phi / (2.0 * PI) * Self::PHI_BINS as f64) as usize).min(Self::PHI_BINS - 1);
+                observed[i * Self::PHI_BINS + j] += 1.0;
+            }
+
+            let density = |theta: f64, phi: f64| {
+                let direction = vector::Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                mat.pdf(&r_in, &rec, direction).unwrap_or(0.0) * theta.sin()
+            };
+            let mut expected = Vec::with_capacity(observed.len());
+            for i in 0..Self::THETA_BINS {
+                for j in 0..Self::PHI_BINS {
+                    let theta = (
+                        i as f64 * PI / Self::THETA_BINS as f64,
+                        (i + 1) as f64 * PI / Self::THETA_BINS as f64,
+                    );
+                    let phi = (
+                        j as f64 * 2.0 * PI / Self::PHI_BINS as f64,
+                        (j + 1) as f64 * 2.0 * PI / Self::PHI_BINS as f64,
+                    );
+                    let probability = integrate_2d(&density, theta, phi);
+                    expected.push(probability * self.chi2_samples as f64);
+                }
+            }
+
+            match chi_square_p_value(&observed, &expected) {
+                Ok(p_value) => worst_p_value = worst_p_value.min(p_value),
+                Err(message) => {
+                    return Report {
+                        material: name.to_string(),
+                        check: "chi-square",
+                        status: Status::Failed,
+                        detail: format!("incident cosine {}: {}", cos_i, message),
+                    };
+                }
+            }
+        }
+
+        // Sidak correction for the number of tests
+        let threshold = 1.0 - (1.0 - self.significance).powf(1.0 / tests as f64);
+        let status = if worst_p_value >= threshold {
+            Status::Passed
+        } else {
+            Status::Failed
+        };
+        return Report {
+            material: name.to_string(),
+            check: "chi-square",
+            status,
+            detail: format!(
+                "smallest p-value {:.3e}, rejecting below {:.3e}",
+                worst_p_value, threshold
+            ),
+        };
+    }
+
+    // Evaluates the implied scattering function at random direction pairs in
+    // both orders.
+    pub fn reciprocity(&self, name: &str, mat: &material::Material) -> Report {
+        let rec = Self::surface_record();
+        let phase = is_phase_function(mat);
+        let scattering = |wi: vector::Vec3, wo: vector::Vec3| {
+            let r_in = ray::Ray::new(rec.p + wi, -wi);
+            let pdf = mat.pdf(&r_in, &rec, wo)?;
+            if phase {
+                return Some(pdf);
+            }
+            return Some(pdf / vector::dot(&wo, &rec.normal).abs());
+        };
+
+        let mut worst = 0.0f64;
+        for _ in 0..self.reciprocity_pairs {
+            let wi = vector::Vec3::random_on_hemisphere(&rec.normal);
+            let wo = vector::Vec3::random_on_hemisphere(&rec.normal);
+            let (Some(forward), Some(backward)) = (scattering(wi, wo), scattering(wo, wi)) else {
+                return Report {
+                    material: name.to_string(),
+                    check: "reciprocity",
+                    status: Status::Skipped,
+                    detail: "scatters into discrete directions".to_string(),
+                };
+            };
+            let scale = forward.abs().max(backward.abs());
+            if scale > 0.0 {
+                worst = worst.max((forward - backward).abs() / scale);
+            }
+        }
+
+        let status = if worst <= 1e-6 {
+            Status::Passed
+        } else {
+            Status::Failed
+        };
+        return Report {
+            material: name.to_string(),
+            check: "reciprocity",
+            status,
+            detail: format!("largest relative difference {:.3e}", worst),
+        };
+    }
+
+    // A hit at the origin on a surface facing +z.
+    fn surface_record() -> hittable::HitRecord {
+        return hittable::HitRecord::new(
+            Point3::new(0.0, 0.0, 0.0),
+            vector::Vec3::new(0.0, 0.0, 1.0),
+            material::MaterialId::default(),
+            1.0,
+            true,
+        );
+    }
+
+    // A ray arriving at the origin from a direction with the given cosine to
+    // the normal.
+    fn incoming_ray(cos_theta: f64, phi: f64) -> ray::Ray {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let wi = vector::Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        return ray::Ray::new(wi, -wi);
+    }
+}
+
+// Pools cells expecting few samples and returns the chi-square p-value. Fails
+// outright when samples land where the pdf says they cannot.
+fn chi_square_p_value(observed: &[f64], expected: &[f64]) -> Result<f64, String> {
+    let total: f64 = observed.iter().sum();
+    let mut cells: Vec<(f64, f64)> = observed
+        .iter()
+        .copied()
+        .zip(expected.iter().copied())
+        .collect();
+    cells.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let mut statistic = 0.0;
+    let mut dof = 0;
+    let (mut pooled_observed, mut pooled_expected) = (0.0, 0.0);
+    for (o, e) in cells {
+        if e <= 0.0 {
+            if o > total * 1e-5 {
+                return Err(format!("{} samples in a cell of zero probability", o));
+            }
+            continue;
+        }
+        if e < Validator::MIN_EXPECTED {
+            pooled_observed += o;
+            pooled_expected += e;
+            continue;
+        }
+        statistic += (o - e) * (o - e) / e;
+        dof += 1;
+    }
+    if pooled_expected > 0.0 {
+        statistic += (pooled_observed - pooled_expected) * (pooled_observed - pooled_expected)
+            / pooled_expected;
+        dof += 1;
+    }
+    if dof < 2 {
+        return Ok(1.0);
+    }
+    return Ok(chi_square_survival(statistic, (dof - 1) as f64));
+}
+
+// P(X > x) for a chi-square distribution with k degrees of freedom.
+fn chi_square_survival(x: f64, k: f64) -> f64 {
+    return 1.0 - regularized_gamma_p(k / 2.0, x / 2.0);
+}
+
+// Lower regularized incomplete gamma function P(a, x), from its series for
+// small x and its continued fraction otherwise.
+fn regularized_gamma_p(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let log_prefactor = a * x.ln() - x - ln_gamma(a);
+    if x < a + 1.0 {
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        let mut n = a;
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        return sum * log_prefactor.exp();
+    }
+
+    // modified Lentz evaluation of the continued fraction for Q(a, x)
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..1000 {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = b + an / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    return 1.0 - log_prefactor.exp() * h;
+}
+
+// Lanczos approximation of ln(Gamma(x)) for x > 0.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let mut y = x;
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000000000190015;
+    for c in COEFFICIENTS {
+        y += 1.0;
+        series += c / y;
+    }
+    return -tmp + (2.5066282746310005 * series / x).ln();
+}
+
+// Adaptive Simpson integration over a rectangle, integrating the inner
+// variable for every outer evaluation.
+fn integrate_2d(f: &dyn Fn(f64, f64) -> f64, x: (f64, f64), y: (f64, f64)) -> f64 {
+    let outer = |u: f64| adaptive_simpson(&|v: f64| f(u, v), y.0, y.1, 1e-7, 8);
+    return adaptive_simpson(&outer, x.0, x.1, 1e-7, 8);
+}
+
+fn adaptive_simpson(f: &dyn Fn(f64) -> f64, a: f64, b: f64, eps: f64, depth: i32) -> f64 {
+    let m = 0.5 * (a + b);
+    let (fa, fm, fb) = (f(a), f(m), f(b));
+    let whole = (b - a) / 6.0 * (fa + 4.0 * fm + fb);
+    return simpson_step(f, a, b, fa, fm, fb, whole, eps, depth);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn simpson_step(
+    f: &dyn Fn(f64) -> f64,
+    a: f64,
+    b: f64,
+    fa: f64,
+    fm: f64,
+    fb: f64,
+    whole: f64,
+    eps: f64,
+    depth: i32,
+) -> f64 {
+    let m = 0.5 * (a + b);
+    let (lm, rm) = (0.5 * (a + m), 0.5 * (m + b));
+    let (flm, frm) = (f(lm), f(rm));
+    let left = (m - a) / 6.0 * (fa + 4.0 * flm + fm);
+    let right = (b - m) / 6.0 * (fm + 4.0 * frm + fb);
+    if depth <= 0 || (left + right - whole).abs() <= 15.0 * eps {
+        return left + right + (left + right - whole) / 15.0;
+    }
+    return simpson_step(f, a, m, fa, flm, fm, left, eps / 2.0, depth - 1)
+        + simpson_step(f, m, b, fm, frm, fb, right, eps / 2.0, depth - 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::utility;
+
+    // Fewer samples than the subcommand, to keep debug builds quick.
+    fn validator() -> Validator {
+        utility::seed(7);
+        let mut validator = Validator::new();
+        validator.furnace_paths = 20_000;
+        validator.chi2_samples = 50_000;
+        validator.reciprocity_pairs = 1_000;
+        return validator;
+    }
+
+    fn assert_not_failed(report: &Report) {
+        assert!(
+            report.status != Status::Failed,
+            "{} failed {}: {}",
+            report.material,
+            report.check,
+            report.detail
+        );
+    }
+
+    #[test]
+    fn sampling_matches_pdf() {
+        let validator = validator();
+        let materials = white_materials();
+        let tests = materials.len() * INCIDENT_COSINES.len();
+        for (name, mat) in &materials {
+            assert_not_failed(&validator.chi_square(name, mat, tests));
+        }
+    }
+
+    #[test]
+    fn lambertian_and_phase_functions_are_reciprocal() {
+        let validator = validator();
+        for (name, mat) in white_materials() {
+            if matches!(mat, material::Material::Metal(_)) {
+                continue;
+            }
+            assert_not_failed(&validator.reciprocity(&name, &mat));
+        }
+    }
+
+    #[test]
+    fn non_absorbing_materials_conserve_energy() {
+        let validator = validator();
+        for (name, mat) in white_materials() {
+            // rays that fuzz scatters below the surface are lost
+            if name == "metal (fuzz 0.3)" || name == "metal (fuzz 1)" {
+                continue;
+            }
+            assert_not_failed(&validator.white_furnace(&name, &mat));
+        }
+    }
+
+    #[test]
+    fn p_value_of_a_known_statistic() {
+        // the 95th percentile of chi-square with 10 degrees of freedom
+        assert!((chi_square_survival(18.307, 10.0) - 0.05).abs() < 1e-4);
+        assert!((chi_square_survival(2.0, 3.0) - 0.5724).abs() < 1e-4);
+    }
+}
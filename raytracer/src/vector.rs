@@ -1,6 +1,6 @@
 use crate::utility::random_double;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Default)]
 pub struct Vec3 {
     pub e: [f64; 3],
 }
@@ -77,6 +77,7 @@ impl Vec3 {
     }
 
     // currently only prints to stdout, might need to change
+    #[allow(dead_code)]
     pub fn print(&self) {
         println!("{} {} {}", self.e[0], self.e[1], self.e[2]);
     }